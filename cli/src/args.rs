@@ -21,6 +21,7 @@ arg_enum!{
     #[allow(non_camel_case_types)]
     #[derive(Debug)]
     pub enum CliProtoVersion {
+        v4,
         v3
     }
 }
@@ -38,6 +39,7 @@ impl From<ProtocolVersion> for CliProtoVersion {
     fn from(v: ProtocolVersion) -> Self {
         match v {
             ProtocolVersion::Version3 => CliProtoVersion::v3,
+            ProtocolVersion::Version4 => CliProtoVersion::v4,
         }
     }
 }
@@ -46,6 +48,7 @@ impl From<CliProtoVersion> for ProtocolVersion {
     fn from(v: CliProtoVersion) -> Self {
         match v {
             CliProtoVersion::v3 => ProtocolVersion::Version3,
+            CliProtoVersion::v4 => ProtocolVersion::Version4,
         }
     }
 }
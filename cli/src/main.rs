@@ -146,7 +146,7 @@ pub fn run() -> Result<()> {
                 .default_value(&CliProtoVersion::variants()[0])
                 .possible_values(&CliProtoVersion::variants())
                 .help(
-                    "The protocol version to use. If not specified, the highest-supported version is used.",
+                    "The highest protocol version to try. Lower versions are used if the server does not support it.",
                 ),
         )
         .arg(
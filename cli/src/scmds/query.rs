@@ -102,7 +102,7 @@ pub fn query(opts: ConnectionOptions, args: &clap::ArgMatches) -> Result<()> {
     };
 
     let (mut core, connect_client) = opts.connect();
-    let req = request_from_query(&query)?;
+    let req = request_from_query(&query, false)?;
 
     let connect_and_call = connect_client
         .then(|res| {
@@ -1,17 +1,24 @@
 use super::super::errors::{ResultExt, Result};
 use super::super::args::ConnectionOptions;
-use super::utils::{handle_call_result, request_from_query};
+use super::utils::{handle_call_result, request_from_query, output_result};
 
 use std::io::{Write, stderr};
 use clap;
 use std::rc::Rc;
 use std::ascii::AsciiExt;
+use std::thread;
+use std::time::Duration;
 
 use linefeed::{Completion, Completer, ReadResult, Reader};
 use linefeed::Terminal;
 use tokio_core::reactor::Core;
 use tokio_service::Service;
-use tokio_cassandra::tokio::easy::ClientHandle;
+use tokio_cassandra::tokio::easy::{self, ClientHandle};
+use tokio_cassandra::codec::primitives::datatypes::Uuid;
+
+/// Traces are written asynchronously by the server, and may not be complete right away.
+const TRACE_FETCH_ATTEMPTS: usize = 5;
+const TRACE_FETCH_DELAY_MS: u64 = 200;
 
 enum PromptKind {
     Idle,
@@ -26,20 +33,65 @@ fn prompt<T: Terminal>(rd: &mut Reader<T>, s: PromptKind) {
     });
 }
 
+/// Returns Some(enabled) if the line is a `TRACING ON|OFF` command, which is handled by the shell.
+fn parse_tracing_command(line: &str) -> Option<bool> {
+    let line = line.trim().trim_right_matches(';').to_ascii_lowercase();
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some("tracing"), Some("on"), None) => Some(true),
+        (Some("tracing"), Some("off"), None) => Some(false),
+        _ => None,
+    }
+}
+
+fn print_trace(client: &ClientHandle, core: &mut Core, id: Uuid, args: &clap::ArgMatches) -> Result<()> {
+    for _ in 0..TRACE_FETCH_ATTEMPTS {
+        let trace = core.run(client.fetch_trace(id.clone())).chain_err(
+            || format!("failed to fetch trace {:?}", id),
+        )?;
+        if let Some(trace) = trace {
+            println!("Tracing session: {:?}", id);
+            let res = output_result(
+                &trace,
+                args.value_of("output-format")
+                    .expect("clap to work")
+                    .parse()
+                    .expect("clap to work"),
+                args,
+            );
+            println!();
+            return res;
+        }
+        thread::sleep(Duration::from_millis(TRACE_FETCH_DELAY_MS));
+    }
+    writeln!(stderr(), "Trace {:?} is not complete yet, please try again later", id).ok();
+    Ok(())
+}
+
 fn execute<T: Terminal>(
     rd: &mut Reader<T>,
     client: &mut ClientHandle,
     core: &mut Core,
     query: &str,
+    tracing: bool,
     args: &clap::ArgMatches,
 ) -> Result<()> {
     prompt(rd, Busy);
 
-    let query_req = request_from_query(query)?;
+    let query_req = request_from_query(query, tracing)?;
     let req = client.call(query_req);
     let res = core.run(req).map_err(Into::into).and_then(|res| {
-        handle_call_result(res, args)
+        let tracing_id = match res {
+            easy::Message::Result(_, ref info) => info.tracing_id.clone(),
+            _ => None,
+        };
+        handle_call_result(res, args).map(|_| tracing_id)
     });
+    let res = match res {
+        Ok(Some(id)) => print_trace(client, core, id, args),
+        Ok(None) => Ok(()),
+        Err(err) => Err(err),
+    };
 
     prompt(rd, Idle);
 
@@ -62,8 +114,9 @@ pub fn interactive<T: Terminal>(
         || format!("failed to connect to {}", addr),
     )?;
 
+    let mut tracing = false;
     if let Some(query) = initial_query {
-        execute(&mut rd, &mut client, &mut core, &query, args)
+        execute(&mut rd, &mut client, &mut core, &query, tracing, args)
             .chain_err(|| format!("Initial query failed '{}'", query))?;
     }
 
@@ -76,7 +129,19 @@ pub fn interactive<T: Terminal>(
             ReadResult::Input(line) => {
                 if line.len() > 0 {
                     rd.add_history(line.to_owned());
-                    execute(&mut rd, &mut client, &mut core, &line, args)
+                    if let Some(enabled) = parse_tracing_command(&line) {
+                        tracing = enabled;
+                        println!(
+                            "{}",
+                            if tracing {
+                                "Now Tracing is enabled"
+                            } else {
+                                "Disabled Tracing."
+                            }
+                        );
+                        continue;
+                    }
+                    execute(&mut rd, &mut client, &mut core, &line, tracing, args)
                         .map_err(|err| { writeln!(stderr(), "{}", err).ok(); })
                         .ok();
                 }
@@ -180,6 +245,7 @@ const CQL_KEYWORDS: &'static [&'static str] = &[
     "three",
     "to",
     "token",
+    "tracing",
     "truncate",
     "ttl",
     "tuple",
@@ -7,8 +7,12 @@ pub fn test_connection(opts: ConnectionOptions, _args: &clap::ArgMatches) -> Res
     let (mut core, client) = opts.connect();
     core.run(client)
         .chain_err(|| format!("Failed to connect to {}", addr))
-        .map(|_client| {
-            println!("Connection to {} successful", addr);
+        .map(|client| {
+            println!(
+                "Connection to {} successful using {:?}",
+                addr,
+                client.protocol_version()
+            );
             ()
         })
         .map_err(|e| e.into())
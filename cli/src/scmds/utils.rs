@@ -6,7 +6,7 @@ use std::io;
 use clap;
 use serde::Serialize;
 use tokio_cassandra::codec::primitives::{CqlFrom, CqlLongString, CqlConsistency};
-use tokio_cassandra::codec::request::{QueryMessage, Message};
+use tokio_cassandra::codec::request::{QueryMessage, QueryParameters, Message};
 use tokio_cassandra::tokio::easy;
 use tokio_cassandra::codec::response::ErrorMessage;
 
@@ -58,10 +58,17 @@ fn output_result_to_stdout_without_color<S: Serialize>(res: &S, fmt: OutputForma
     Ok(())
 }
 
+/// Print the result of a query. `easy::ClientHandle` received all rows of a streamed result
+/// before returning it, so large results are printed just like small ones.
 pub fn handle_call_result(res: easy::Message, args: &clap::ArgMatches) -> Result<()> {
     match res {
-        easy::Message::Error(ErrorMessage { text, code }) => Err(ErrorKind::CqlError(code, text).into()),
-        easy::Message::Result(res) => {
+        easy::Message::Error(ErrorMessage { text, code, .. }) => Err(ErrorKind::CqlError(code, text).into()),
+        easy::Message::Result(res, info) => {
+            if let Some(warnings) = info.warnings {
+                for warning in warnings.iter() {
+                    eprintln!("Warning: {}", warning);
+                }
+            }
             let res = output_result(
                 &res,
                 args.value_of("output-format")
@@ -77,18 +84,20 @@ pub fn handle_call_result(res: easy::Message, args: &clap::ArgMatches) -> Result
     }
 }
 
-pub fn request_from_query(query: &str) -> Result<Message> {
+pub fn request_from_query(query: &str, tracing: bool) -> Result<Message> {
     Ok(Message::Query(QueryMessage {
         // FIXME: provide a consuming version that consumes a string directly into the vec
         // and thus prevents an entirely unnecessary copy
         query: CqlLongString::try_from(query)?,
-        values: None,
-        consistency: CqlConsistency::All,
-        skip_metadata: false,
-        page_size: None,
-        paging_state: None,
-        serial_consistency: Some(CqlConsistency::All),
-        timestamp: None,
+        parameters: QueryParameters {
+            consistency: CqlConsistency::All,
+            serial_consistency: Some(CqlConsistency::All),
+            ..Default::default()
+        },
+        tracing: tracing,
+        routing_key: None,
+        idempotent: false,
+        timeout: None,
     }))
 }
 
@@ -0,0 +1,221 @@
+//! Derives mapping structs with named fields to the rows, query values and user defined types
+//! of tokio-cassandra.
+//!
+//! * `FromRow` reads a struct from the columns of a row,
+//! * `IntoValues` binds a struct to the named variables of a query,
+//! * `FromUdt` and `IntoUdt` convert a struct from and into values of a user defined type, the
+//!   latter with `IntoUdt::to_udt` in the field order of the type.
+//!
+//! Fields map to the column, variable or field of the same name. Fields can be mapped to
+//! another name with `#[cassandra(rename = "name")]`, and are left out with
+//! `#[cassandra(skip)]`, in which case they are read as `Default::default()`.
+//!
+//! Names and types are checked against the `RowsMetadata` or `UdtDefinition` of each value
+//! when reading, which fails with an error if they don't match.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as Tokens;
+use syn::{Data, DeriveInput, Fields, Ident, Lit, Meta, NestedMeta, Type};
+
+/// A field of the derived struct.
+struct Field {
+    ident: Ident,
+    ty: Type,
+    /// The name of its column, variable or field.
+    name: String,
+    skip: bool,
+}
+
+impl Field {
+    fn parse(field: &syn::Field) -> syn::Result<Field> {
+        let ident = field.ident.clone().expect("a named field");
+        let mut name = ident.to_string();
+        let mut skip = false;
+        for attr in field.attrs.iter().filter(|a| a.path.is_ident("cassandra")) {
+            let list = match attr.parse_meta()? {
+                Meta::List(list) => list,
+                meta => return Err(syn::Error::new_spanned(meta, "expected #[cassandra(...)]")),
+            };
+            for item in list.nested {
+                match item {
+                    NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("skip") => skip = true,
+                    NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("rename") => match nv.lit {
+                        Lit::Str(ref s) => name = s.value(),
+                        ref lit => return Err(syn::Error::new_spanned(lit, "expected a string")),
+                    },
+                    item => {
+                        return Err(syn::Error::new_spanned(
+                            item,
+                            "expected `rename = \"name\"` or `skip`",
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(Field {
+            ident: ident,
+            ty: field.ty.clone(),
+            name: name,
+            skip: skip,
+        })
+    }
+}
+
+fn fields(input: &DeriveInput) -> syn::Result<Vec<Field>> {
+    match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => fields.named.iter().map(Field::parse).collect(),
+            _ => Err(syn::Error::new_spanned(
+                &input.ident,
+                "only structs with named fields can be derived",
+            )),
+        },
+        _ => Err(syn::Error::new_spanned(&input.ident, "only structs can be derived")),
+    }
+}
+
+/// Expand `derive` for `input`, or report the error at its origin.
+fn expand<F>(input: TokenStream, derive: F) -> TokenStream
+where
+    F: FnOnce(&DeriveInput, &[Field]) -> Tokens,
+{
+    let input = match syn::parse::<DeriveInput>(input) {
+        Ok(input) => input,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    match fields(&input) {
+        Ok(fields) => derive(&input, &fields).into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// The initialization of all fields, reading the ones which aren't skipped with `read`.
+fn read_fields<F>(fields: &[Field], read: F) -> Vec<Tokens>
+where
+    F: Fn(&Field) -> Tokens,
+{
+    fields
+        .iter()
+        .map(|f| {
+            let ident = &f.ident;
+            if f.skip {
+                quote!(#ident: ::std::default::Default::default())
+            } else {
+                let value = read(f);
+                quote!(#ident: #value)
+            }
+        })
+        .collect()
+}
+
+#[proc_macro_derive(FromRow, attributes(cassandra))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    expand(input, |input, fields| {
+        let ident = &input.ident;
+        let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+        let values = read_fields(fields, |f| {
+            let name = &f.name;
+            quote!(row.get(meta, #name)?)
+        });
+        quote! {
+            impl #impl_generics ::tokio_cassandra::codec::response::FromRow for #ident #ty_generics #where_clause {
+                fn from_row(
+                    row: &::tokio_cassandra::codec::response::Row,
+                    meta: &::tokio_cassandra::codec::response::RowsMetadata,
+                ) -> ::tokio_cassandra::codec::response::Result<Self> {
+                    Ok(#ident { #(#values),* })
+                }
+            }
+        }
+    })
+}
+
+#[proc_macro_derive(IntoValues, attributes(cassandra))]
+pub fn derive_into_values(input: TokenStream) -> TokenStream {
+    expand(input, |input, fields| {
+        let ident = &input.ident;
+        let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+        let fields = fields.iter().filter(|f| !f.skip);
+        let names = fields.clone().map(|f| &f.name);
+        let idents = fields.map(|f| &f.ident);
+        quote! {
+            impl #impl_generics ::tokio_cassandra::codec::primitives::datatypes::IntoValues
+                for #ident #ty_generics #where_clause
+            {
+                fn into_values(self) -> ::tokio_cassandra::codec::request::QueryValues {
+                    let mut values = ::std::collections::HashMap::new();
+                    #(
+                        values.insert(
+                            <::tokio_cassandra::codec::primitives::CqlString as
+                                ::tokio_cassandra::codec::primitives::CqlFrom<_, &str>>::try_from(#names)
+                                .expect("a valid variable name"),
+                            ::tokio_cassandra::codec::primitives::datatypes::ToCql::to_cql(&self.#idents),
+                        );
+                    )*
+                    ::tokio_cassandra::codec::request::QueryValues::Named(values)
+                }
+            }
+        }
+    })
+}
+
+#[proc_macro_derive(FromUdt, attributes(cassandra))]
+pub fn derive_from_udt(input: TokenStream) -> TokenStream {
+    expand(input, |input, fields| {
+        let ident = &input.ident;
+        let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+        let read = fields.iter().filter(|f| !f.skip);
+        let names = read.clone().map(|f| &f.name);
+        let types = read.map(|f| &f.ty);
+        let values = read_fields(fields, |f| {
+            let name = &f.name;
+            quote!(value.get(#name)?)
+        });
+        quote! {
+            impl #impl_generics ::tokio_cassandra::codec::primitives::datatypes::FromUdt
+                for #ident #ty_generics #where_clause
+            {
+                fn accepts_udt(def: &::tokio_cassandra::codec::response::UdtDefinition) -> bool {
+                    true #(&& ::tokio_cassandra::codec::primitives::datatypes::udt_field_accepts::<#types>(def, #names))*
+                }
+
+                fn from_udt(
+                    value: &::tokio_cassandra::codec::primitives::datatypes::UdtValue,
+                ) -> ::tokio_cassandra::codec::primitives::datatypes::Result<Self> {
+                    Ok(#ident { #(#values),* })
+                }
+            }
+        }
+    })
+}
+
+/// No `ToCql` is implemented, as the fields must be sent in the order of the definition of the
+/// type, which is only known to `IntoUdt::to_udt`.
+#[proc_macro_derive(IntoUdt, attributes(cassandra))]
+pub fn derive_into_udt(input: TokenStream) -> TokenStream {
+    expand(input, |input, fields| {
+        let ident = &input.ident;
+        let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+        let fields = fields.iter().filter(|f| !f.skip);
+        let names = fields.clone().map(|f| &f.name);
+        let idents = fields.map(|f| &f.ident);
+        quote! {
+            impl #impl_generics ::tokio_cassandra::codec::primitives::datatypes::IntoUdt
+                for #ident #ty_generics #where_clause
+            {
+                fn udt_fields(&self) -> Vec<(&'static str, ::tokio_cassandra::codec::primitives::CqlBytes)> {
+                    vec![#((
+                        #names,
+                        ::tokio_cassandra::codec::primitives::datatypes::ToCql::to_cql(&self.#idents),
+                    )),*]
+                }
+            }
+        }
+    })
+}
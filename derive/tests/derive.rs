@@ -0,0 +1,174 @@
+extern crate bytes;
+extern crate tokio_cassandra;
+#[macro_use]
+extern crate tokio_cassandra_derive;
+
+use bytes::BytesMut;
+use std::collections::HashMap;
+use tokio_cassandra::codec::primitives::{encode, CqlBytes, CqlFrom, CqlString};
+use tokio_cassandra::codec::primitives::datatypes::{FromCql, IntoUdt, IntoValues, ToCql};
+use tokio_cassandra::codec::request::QueryValues;
+use tokio_cassandra::codec::response::{ColumnSpec, ColumnType, ErrorKind, Row, RowsMetadata, TableSpec,
+                                       UdtDefinition, UdtField};
+
+fn name(s: &str) -> CqlString {
+    CqlString::try_from(s).unwrap()
+}
+
+fn address_type() -> UdtDefinition {
+    UdtDefinition {
+        keyspace: name("ks"),
+        name: name("address"),
+        fields: vec![
+            UdtField(name("street"), ColumnType::Varchar),
+            UdtField(name("zip_code"), ColumnType::Int),
+        ],
+    }
+}
+
+#[derive(Debug, PartialEq, FromUdt, IntoUdt)]
+struct Address {
+    street: String,
+    #[cassandra(rename = "zip_code")]
+    zip: Option<i32>,
+    #[cassandra(skip)]
+    verified: bool,
+}
+
+#[derive(Debug, PartialEq, FromRow)]
+struct User {
+    id: i32,
+    #[cassandra(rename = "user_name")]
+    name: String,
+    address: Option<Address>,
+    #[cassandra(skip)]
+    cached: Vec<u8>,
+}
+
+#[derive(IntoValues)]
+struct NewUser {
+    id: i32,
+    #[cassandra(rename = "user_name")]
+    name: String,
+    address: CqlBytes,
+    #[cassandra(skip)]
+    cached: Vec<u8>,
+}
+
+fn user_rows() -> RowsMetadata {
+    let column = |n: &str, column_type| ColumnSpec::WithoutGlobalSpec {
+        table_spec: TableSpec::new("ks", "users"),
+        name: name(n),
+        column_type: column_type,
+    };
+    RowsMetadata {
+        column_spec: vec![
+            column("user_name", ColumnType::Varchar),
+            column("id", ColumnType::Int),
+            column("address", ColumnType::Udt(address_type())),
+        ],
+        columns_count: 3,
+        rows_count: 1,
+        ..Default::default()
+    }
+}
+
+fn row(meta: &RowsMetadata, values: &[CqlBytes]) -> Row {
+    let mut buf = BytesMut::with_capacity(128);
+    for value in values {
+        encode::bytes(value, &mut buf);
+    }
+    Row::decode(buf, meta).unwrap().1.expect("a complete row")
+}
+
+#[test]
+fn from_row() {
+    let meta = user_rows();
+    let address = Address {
+        street: String::from("main"),
+        zip: None,
+        verified: true,
+    };
+    let row = row(&meta, &["a".to_cql(), 1i32.to_cql(), address.to_udt(&address_type()).unwrap()]);
+    let user: User = row.into_typed(&meta).unwrap();
+    assert_eq!(
+        user,
+        User {
+            id: 1,
+            name: String::from("a"),
+            address: Some(Address {
+                street: String::from("main"),
+                zip: None,
+                verified: false,
+            }),
+            cached: Vec::new(),
+        }
+    );
+}
+
+#[test]
+fn from_row_checks_columns() {
+    let mut meta = user_rows();
+    let row = row(&meta, &[1i32.to_cql(), 1i32.to_cql(), CqlBytes::null_value()]);
+    meta.column_spec[0] = ColumnSpec::WithoutGlobalSpec {
+        table_spec: TableSpec::new("ks", "users"),
+        name: name("user_name"),
+        column_type: ColumnType::Int,
+    };
+    match *row.into_typed::<User>(&meta).unwrap_err().kind() {
+        ErrorKind::UnexpectedColumnType(ref column, _) => assert_eq!(column, "user_name"),
+        ref e => panic!("unexpected error {:?}", e),
+    }
+
+    let mut meta = user_rows();
+    meta.column_spec.pop();
+    match *row.into_typed::<User>(&meta).unwrap_err().kind() {
+        ErrorKind::NoSuchColumn(ref column) => assert_eq!(column, "address"),
+        ref e => panic!("unexpected error {:?}", e),
+    }
+}
+
+#[test]
+fn udt_checks_fields() {
+    let mut def = address_type();
+    assert!(Address::accepts(&ColumnType::Udt(def.clone())));
+    def.fields[1].1 = ColumnType::Varchar;
+    assert!(!Address::accepts(&ColumnType::Udt(def.clone())));
+    def.fields.pop();
+    assert!(!Address::accepts(&ColumnType::Udt(def.clone())));
+
+    let address = Address {
+        street: String::from("main"),
+        zip: Some(1),
+        verified: false,
+    };
+    assert!(address.to_udt(&def).is_err());
+
+    // Fields are sent in the order of the definition.
+    let mut def = address_type();
+    def.fields.reverse();
+    let mut buf = BytesMut::with_capacity(32);
+    encode::bytes(&1i32.to_cql(), &mut buf);
+    encode::bytes(&"main".to_cql(), &mut buf);
+    assert_eq!(address.to_udt(&def).unwrap(), CqlBytes::from(buf));
+}
+
+#[test]
+fn into_values() {
+    let user = NewUser {
+        id: 1,
+        name: String::from("a"),
+        address: CqlBytes::null_value(),
+        cached: vec![1],
+    };
+    match user.into_values() {
+        QueryValues::Named(values) => {
+            let mut expected = HashMap::new();
+            expected.insert(name("id"), 1i32.to_cql());
+            expected.insert(name("user_name"), "a".to_cql());
+            expected.insert(name("address"), CqlBytes::null_value());
+            assert_eq!(values, expected);
+        }
+        _ => panic!("expected named values"),
+    }
+}
@@ -0,0 +1,216 @@
+//! Compression of frame bodies, which is negotiated in the STARTUP message.
+//!
+//! Only the algorithms enabled through the `with-lz4` and `with-snappy` features are
+//! available. Once agreed upon, all frames but STARTUP and OPTIONS may have a compressed body,
+//! which is indicated by the compression flag in their header.
+mod errors {
+    error_chain! {
+        errors {
+            NotCompiledIn(algorithm: &'static str) {
+                description("The compression algorithm was not compiled into this library")
+                display("Support for {} compression is not compiled in", algorithm)
+            }
+            CompressionFailed(algorithm: &'static str, msg: String) {
+                description("A frame body could not be compressed")
+                display("Failed to compress frame body with {}: {}", algorithm, msg)
+            }
+            DecompressionFailed(algorithm: &'static str, msg: String) {
+                description("A frame body could not be decompressed")
+                display("Failed to decompress frame body with {}: {}", algorithm, msg)
+            }
+        }
+    }
+}
+
+pub use self::errors::{Error, ErrorKind, Result};
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Compression {
+    /// The body is prefixed with its uncompressed length as [int], followed by an LZ4 block.
+    Lz4,
+    /// The body is compressed in the raw Snappy format.
+    Snappy,
+}
+
+impl Compression {
+    /// All algorithms this library was compiled with, in order of preference.
+    pub fn supported() -> Vec<Compression> {
+        let mut res = Vec::new();
+        if cfg!(feature = "with-lz4") {
+            res.push(Compression::Lz4);
+        }
+        if cfg!(feature = "with-snappy") {
+            res.push(Compression::Snappy);
+        }
+        res
+    }
+
+    /// The first of our supported algorithms which is also contained in `names`, as
+    /// advertised by the server in its SUPPORTED message.
+    pub fn negotiate<'a, I>(names: I) -> Option<Compression>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let names: Vec<_> = names.into_iter().collect();
+        Compression::supported().into_iter().find(|c| {
+            names.iter().any(|n| *n == c.as_str())
+        })
+    }
+
+    /// Returns the algorithm with the given name, if it is supported.
+    pub fn from_name(name: &str) -> Option<Compression> {
+        Compression::supported().into_iter().find(
+            |c| c.as_str() == name,
+        )
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Compression::Lz4 => "lz4",
+            Compression::Snappy => "snappy",
+        }
+    }
+
+    pub fn compress(&self, body: &[u8]) -> Result<Vec<u8>> {
+        match *self {
+            Compression::Lz4 => lz4::compress(body),
+            Compression::Snappy => snappy::compress(body),
+        }
+    }
+
+    pub fn decompress(&self, body: &[u8]) -> Result<Vec<u8>> {
+        match *self {
+            Compression::Lz4 => lz4::decompress(body),
+            Compression::Snappy => snappy::decompress(body),
+        }
+    }
+}
+
+#[cfg(feature = "with-lz4")]
+mod lz4 {
+    use super::{Result, ErrorKind};
+    use byteorder::{BigEndian, ByteOrder};
+    use ::lz4::block;
+
+    const NAME: &str = "lz4";
+
+    pub fn compress(body: &[u8]) -> Result<Vec<u8>> {
+        let compressed = block::compress(body, None, false).map_err(|e| {
+            ErrorKind::CompressionFailed(NAME, e.to_string())
+        })?;
+        let mut res = vec![0; 4];
+        BigEndian::write_u32(&mut res, body.len() as u32);
+        res.extend_from_slice(&compressed);
+        Ok(res)
+    }
+
+    pub fn decompress(body: &[u8]) -> Result<Vec<u8>> {
+        if body.len() < 4 {
+            return Err(
+                ErrorKind::DecompressionFailed(
+                    NAME,
+                    format!("Expected at least 4 bytes, got {}", body.len()),
+                ).into(),
+            );
+        }
+        let len = BigEndian::read_i32(&body[..4]);
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        block::decompress(&body[4..], Some(len)).map_err(|e| {
+            ErrorKind::DecompressionFailed(NAME, e.to_string()).into()
+        })
+    }
+}
+
+#[cfg(not(feature = "with-lz4"))]
+mod lz4 {
+    use super::{Result, ErrorKind};
+
+    pub fn compress(_body: &[u8]) -> Result<Vec<u8>> {
+        Err(ErrorKind::NotCompiledIn("lz4").into())
+    }
+
+    pub fn decompress(_body: &[u8]) -> Result<Vec<u8>> {
+        Err(ErrorKind::NotCompiledIn("lz4").into())
+    }
+}
+
+#[cfg(feature = "with-snappy")]
+mod snappy {
+    use super::{Result, ErrorKind};
+    use snap::{Encoder, Decoder};
+
+    const NAME: &str = "snappy";
+
+    pub fn compress(body: &[u8]) -> Result<Vec<u8>> {
+        Encoder::new().compress_vec(body).map_err(|e| {
+            ErrorKind::CompressionFailed(NAME, e.to_string()).into()
+        })
+    }
+
+    pub fn decompress(body: &[u8]) -> Result<Vec<u8>> {
+        Decoder::new().decompress_vec(body).map_err(|e| {
+            ErrorKind::DecompressionFailed(NAME, e.to_string()).into()
+        })
+    }
+}
+
+#[cfg(not(feature = "with-snappy"))]
+mod snappy {
+    use super::{Result, ErrorKind};
+
+    pub fn compress(_body: &[u8]) -> Result<Vec<u8>> {
+        Err(ErrorKind::NotCompiledIn("snappy").into())
+    }
+
+    pub fn decompress(_body: &[u8]) -> Result<Vec<u8>> {
+        Err(ErrorKind::NotCompiledIn("snappy").into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn not_compiled_in() {
+        if !cfg!(feature = "with-snappy") {
+            assert!(Compression::Snappy.compress(b"foo").is_err());
+            assert_eq!(Compression::from_name("snappy"), None);
+        }
+    }
+
+    #[test]
+    fn negotiate() {
+        assert_eq!(Compression::negotiate(vec!["unknown"]), None);
+        for c in Compression::supported() {
+            assert_eq!(Compression::negotiate(vec!["unknown", c.as_str()]), Some(c));
+            assert_eq!(Compression::from_name(c.as_str()), Some(c));
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let body: Vec<u8> = b"select * from system.local where key = 'local'"
+            .iter()
+            .cycle()
+            .take(1024)
+            .cloned()
+            .collect();
+        for c in Compression::supported() {
+            let compressed = c.compress(&body).unwrap();
+            assert!(compressed.len() < body.len());
+            assert_eq!(c.decompress(&compressed).unwrap(), body);
+            assert!(c.decompress(&compressed[..2]).is_err());
+        }
+    }
+
+    #[cfg(feature = "with-lz4")]
+    #[test]
+    fn lz4_length_prefix() {
+        let compressed = Compression::Lz4.compress(&[1u8; 300]).unwrap();
+        assert_eq!(&compressed[..4], &[0, 0, 1, 44]);
+        assert_eq!(Compression::Lz4.decompress(&[0, 0, 0, 0]).unwrap(), vec![]);
+    }
+}
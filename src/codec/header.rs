@@ -35,6 +35,10 @@
 //!  version.
 use byteorder::{BigEndian, ReadBytesExt, ByteOrder};
 pub const HEADER_LENGTH: usize = 9;
+/// Set in the header flags if the frame body is compressed.
+pub const COMPRESSION_FLAG: u8 = 0x01;
+/// Set in the header flags of requests to be traced, and of responses carrying a tracing id.
+pub const TRACING_FLAG: u8 = 0x02;
 
 mod errors {
     error_chain! {
@@ -51,6 +55,10 @@ mod errors {
                 description("Could not parse value as opcode.")
                 display("Value {} is not a valid opcode", c)
             }
+            VersionMismatch(expected: u8, actual: u8) {
+                description("The server responded with a different protocol version than requested")
+                display("Expected a response of protocol version {}, but got {}", expected, actual)
+            }
         }
         foreign_links {
             Io(::std::io::Error);
@@ -206,11 +214,21 @@ impl Header {
     }
 
     pub fn is_compressed(&self) -> bool {
-        self.flags & 0x01 == 0x01
+        self.flags & COMPRESSION_FLAG == COMPRESSION_FLAG
     }
 
     pub fn is_traced(&self) -> bool {
-        self.flags & 0x02 == 0x02
+        self.flags & TRACING_FLAG == TRACING_FLAG
+    }
+
+    /// Only used from protocol version 4 onwards.
+    pub fn has_custom_payload(&self) -> bool {
+        self.flags & 0x04 == 0x04
+    }
+
+    /// Only used from protocol version 4 onwards.
+    pub fn has_warnings(&self) -> bool {
+        self.flags & 0x08 == 0x08
     }
 
     pub fn encode(&self) -> Result<[u8; 9]> {
@@ -252,10 +270,28 @@ impl Header {
 /// connection.
 /// This document describe the version 3 of the protocol. For the changes made since
 /// version 2, see Section 10.
+///
+/// Version 4 uses 0x04 and 0x84 respectively.
 #[cfg_attr(feature = "with-serde", derive(Deserialize, Serialize))]
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub enum ProtocolVersion {
     Version3,
+    Version4,
+}
+
+impl ProtocolVersion {
+    /// The highest protocol version we can speak, which is the one to try first when connecting.
+    pub fn highest() -> ProtocolVersion {
+        ProtocolVersion::Version4
+    }
+
+    /// The next lower version to try if the server rejects this one, if there is any.
+    pub fn lower(&self) -> Option<ProtocolVersion> {
+        match *self {
+            ProtocolVersion::Version4 => Some(ProtocolVersion::Version3),
+            ProtocolVersion::Version3 => None,
+        }
+    }
 }
 
 #[cfg_attr(feature = "with-serde", derive(Deserialize, Serialize))]
@@ -270,6 +306,8 @@ impl Version {
         Ok(match b {
             0x03 => Version::v3_request(),
             0x83 => Version::v3_response(),
+            0x04 => Version::v4_request(),
+            0x84 => Version::v4_response(),
             _ => return Err(ErrorKind::UnsupportedVersion(b).into()),
         })
     }
@@ -285,6 +323,8 @@ impl Version {
         match (&self.version, &self.direction) {
             (&ProtocolVersion::Version3, &Direction::Request) => 0x03,
             (&ProtocolVersion::Version3, &Direction::Response) => 0x83,
+            (&ProtocolVersion::Version4, &Direction::Request) => 0x04,
+            (&ProtocolVersion::Version4, &Direction::Response) => 0x84,
         }
     }
     pub fn v3_response() -> Version {
@@ -299,6 +339,18 @@ impl Version {
             direction: Direction::Request,
         }
     }
+    pub fn v4_response() -> Version {
+        Version {
+            version: ProtocolVersion::Version4,
+            direction: Direction::Response,
+        }
+    }
+    pub fn v4_request() -> Version {
+        Version {
+            version: ProtocolVersion::Version4,
+            direction: Direction::Request,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -322,6 +374,28 @@ mod test {
         assert!(Version::try_from(b'\x88').is_err());
     }
 
+    #[test]
+    fn version4() {
+        assert_eq!(Version::v4_request().encode(), b'\x04');
+        assert_eq!(Version::v4_response().encode(), b'\x84');
+        assert_eq!(
+            Version::request(ProtocolVersion::Version4),
+            Version::v4_request()
+        );
+        assert_eq!(Version::try_from(b'\x04').unwrap(), Version::v4_request());
+        assert_eq!(Version::try_from(b'\x84').unwrap(), Version::v4_response());
+    }
+
+    #[test]
+    fn protocol_version_lower() {
+        assert_eq!(ProtocolVersion::highest(), ProtocolVersion::Version4);
+        assert_eq!(
+            ProtocolVersion::Version4.lower(),
+            Some(ProtocolVersion::Version3)
+        );
+        assert_eq!(ProtocolVersion::Version3.lower(), None);
+    }
+
     #[test]
     fn complete_decode() {
         let bytes = b"\x03\x00\x01\x01\x05\x00\x00\x01\x05";
@@ -391,12 +465,23 @@ mod test {
         assert_eq!(h.is_traced(), true);
     }
 
+    #[test]
+    fn flags_v4() {
+        let bytes = b"\x84\x0c\x00\x00\x08\x00\x00\x00\x00";
+        let h = Header::try_from(&bytes[..]).unwrap();
+
+        assert_eq!(h.version, Version::v4_response());
+        assert_eq!(h.is_traced(), false);
+        assert_eq!(h.has_custom_payload(), true);
+        assert_eq!(h.has_warnings(), true);
+    }
+
     #[test]
     fn unsupported_version() {
-        let bytes = b"\x04\x02\x00\x00\x05\x00\x00\x00\x00";
+        let bytes = b"\x05\x02\x00\x00\x05\x00\x00\x00\x00";
         let res = Header::try_from(&bytes[..]);
 
-        assert!(err_is(res, ErrorKind::UnsupportedVersion(0x04)));
+        assert!(err_is(res, ErrorKind::UnsupportedVersion(0x05)));
     }
 
 
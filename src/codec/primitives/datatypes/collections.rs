@@ -149,6 +149,14 @@ where
         key.serialize(&mut bytes);
         self.inner.insert(bytes, value);
     }
+
+    /// Deserialize all keys of the map, in no particular order, along with their values.
+    pub fn entries(&self) -> Result<Vec<(K, Option<V>)>>
+    where
+        V: Clone,
+    {
+        self.inner.iter().map(|(k, v)| Ok((K::deserialize(k.clone())?, v.clone()))).collect()
+    }
 }
 
 
@@ -352,6 +360,11 @@ where
         value.serialize(&mut bytes);
         self.inner.insert(bytes);
     }
+
+    /// Deserialize all values of the set, in no particular order.
+    pub fn values(&self) -> Result<Vec<V>> {
+        self.inner.iter().map(|v| V::deserialize(v.clone())).collect()
+    }
 }
 
 impl<V> CqlSerializable for Set<V>
@@ -665,6 +678,18 @@ mod test {
         assert!("{false, true}" == format!("{:?}", x) || "{true, false}" == format!("{:?}", x));
     }
 
+    #[test]
+    fn set_values() {
+        let mut s = Set::new();
+        s.insert(Varchar::try_from("a").unwrap());
+        s.insert(Varchar::try_from("b").unwrap());
+        s.insert(Varchar::try_from("a").unwrap());
+
+        let mut values: Vec<String> = s.values().unwrap().iter().map(|v| v.as_ref().to_string()).collect();
+        values.sort();
+        assert_eq!(values, vec!["a", "b"]);
+    }
+
     #[test]
     fn genericset_debug() {
         let ctype = ColumnType::Varchar;
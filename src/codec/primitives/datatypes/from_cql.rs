@@ -0,0 +1,435 @@
+use super::*;
+use byteorder::ByteOrder;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, UTC};
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hash};
+
+/// Conversion of the values of columns into native values.
+pub trait FromCql: Sized {
+    /// Whether values of columns of type `column_type` can be converted into `Self`.
+    fn accepts(column_type: &ColumnType) -> bool;
+
+    /// Convert a value which is not null. `column_type` was accepted before.
+    fn from_cql(column_type: &ColumnType, data: BytesMut) -> Result<Self>;
+
+    /// The value to use for null, or `None` if `Self` can't represent it.
+    fn from_null() -> Option<Self> {
+        None
+    }
+}
+
+/// Convert a serialized value of type `column_type`, which may be null.
+pub fn from_cql_value<T: FromCql>(column_type: &ColumnType, data: Option<BytesMut>) -> Result<T> {
+    match data {
+        Some(data) => T::from_cql(column_type, data),
+        None => T::from_null().ok_or_else(|| ErrorKind::UnexpectedNull.into()),
+    }
+}
+
+fn fixed_len(data: &BytesMut, len: usize) -> Result<()> {
+    if data.len() != len {
+        return Err(ErrorKind::Incomplete.into());
+    }
+    Ok(())
+}
+
+impl<T: FromCql> FromCql for Option<T> {
+    fn accepts(column_type: &ColumnType) -> bool {
+        T::accepts(column_type)
+    }
+
+    fn from_cql(column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+        T::from_cql(column_type, data).map(Some)
+    }
+
+    fn from_null() -> Option<Self> {
+        Some(None)
+    }
+}
+
+macro_rules! fixed_len_from_cql {
+    ($t: ty, $len: expr, $read: expr, $($column_type: pat)|+) => {
+        impl FromCql for $t {
+            fn accepts(column_type: &ColumnType) -> bool {
+                match *column_type {
+                    $($column_type)|+ => true,
+                    _ => false,
+                }
+            }
+
+            fn from_cql(_column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+                fixed_len(&data, $len)?;
+                Ok($read(data.as_ref()))
+            }
+        }
+    }
+}
+
+fixed_len_from_cql!(bool, 1, |b: &[u8]| b[0] != 0, ColumnType::Boolean);
+fixed_len_from_cql!(i8, 1, |b: &[u8]| b[0] as i8, ColumnType::Tinyint);
+fixed_len_from_cql!(i16, 2, BigEndian::read_i16, ColumnType::Smallint);
+fixed_len_from_cql!(i32, 4, BigEndian::read_i32, ColumnType::Int);
+fixed_len_from_cql!(i64, 8, BigEndian::read_i64, ColumnType::Bigint | ColumnType::Counter);
+fixed_len_from_cql!(f32, 4, BigEndian::read_f32, ColumnType::Float);
+fixed_len_from_cql!(f64, 8, BigEndian::read_f64, ColumnType::Double);
+
+impl FromCql for String {
+    fn accepts(column_type: &ColumnType) -> bool {
+        match *column_type {
+            ColumnType::Varchar | ColumnType::Ascii => true,
+            _ => false,
+        }
+    }
+
+    fn from_cql(_column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+        String::from_utf8(data.to_vec()).map_err(|_| ErrorKind::InvalidUtf8.into())
+    }
+}
+
+/// Bytes are read from blobs.
+impl FromCql for Vec<u8> {
+    fn accepts(column_type: &ColumnType) -> bool {
+        match *column_type {
+            ColumnType::Blob | ColumnType::Custom(_) => true,
+            _ => false,
+        }
+    }
+
+    fn from_cql(_column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+        Ok(data.to_vec())
+    }
+}
+
+impl FromCql for IpAddr {
+    fn accepts(column_type: &ColumnType) -> bool {
+        *column_type == ColumnType::Inet
+    }
+
+    fn from_cql(_column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+        Ok(Inet::deserialize(data)?.into())
+    }
+}
+
+/// The time since the epoch in milliseconds, as stored in timestamps.
+fn epoch_millis(data: BytesMut) -> Result<(i64, u32)> {
+    fixed_len(&data, 8)?;
+    let millis = BigEndian::read_i64(data.as_ref());
+    let (secs, millis) = (millis / 1000, millis % 1000);
+    Ok(if millis < 0 {
+        (secs - 1, (millis + 1000) as u32 * 1_000_000)
+    } else {
+        (secs, millis as u32 * 1_000_000)
+    })
+}
+
+/// Date-times are read from timestamps.
+impl FromCql for DateTime<UTC> {
+    fn accepts(column_type: &ColumnType) -> bool {
+        *column_type == ColumnType::Timestamp
+    }
+
+    fn from_cql(_column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+        let (secs, nanos) = epoch_millis(data)?;
+        Ok(UTC.timestamp(secs, nanos))
+    }
+}
+
+/// Date-times are read from timestamps, which are in UTC.
+impl FromCql for NaiveDateTime {
+    fn accepts(column_type: &ColumnType) -> bool {
+        *column_type == ColumnType::Timestamp
+    }
+
+    fn from_cql(_column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+        let (secs, nanos) = epoch_millis(data)?;
+        Ok(NaiveDateTime::from_timestamp(secs, nanos))
+    }
+}
+
+/// Dates are read from dates within the range of chrono.
+impl FromCql for NaiveDate {
+    fn accepts(column_type: &ColumnType) -> bool {
+        *column_type == ColumnType::Date
+    }
+
+    fn from_cql(_column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+        Date::deserialize(data)?.to_naive().ok_or_else(
+            || ErrorKind::UnexpectedType(String::from("Date")).into(),
+        )
+    }
+}
+
+/// Times are read from times within a day.
+impl FromCql for NaiveTime {
+    fn accepts(column_type: &ColumnType) -> bool {
+        *column_type == ColumnType::Time
+    }
+
+    fn from_cql(_column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+        Time::deserialize(data)?.to_naive().ok_or_else(
+            || ErrorKind::UnexpectedType(String::from("Time")).into(),
+        )
+    }
+}
+
+/// Call `f` with each element of a serialized list or set.
+fn for_each_element<F>(data: BytesMut, mut f: F) -> Result<()>
+where
+    F: FnMut(Option<BytesMut>) -> Result<()>,
+{
+    let (mut data, n) = ::codec::primitives::decode::int(data)?;
+    for _ in 0..n {
+        let (rest, element) = deserialize_bytesmut(data)?;
+        f(element)?;
+        data = rest;
+    }
+    Ok(())
+}
+
+fn element_type(column_type: &ColumnType) -> Option<&ColumnType> {
+    match *column_type {
+        ColumnType::List(ref t) | ColumnType::Set(ref t) => Some(t),
+        _ => None,
+    }
+}
+
+/// Vectors of anything but bytes are read from lists and sets.
+impl<T: FromCql> FromCql for Vec<T> {
+    fn accepts(column_type: &ColumnType) -> bool {
+        element_type(column_type).map_or(false, T::accepts)
+    }
+
+    fn from_cql(column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+        let element_type = element_type(column_type).ok_or(ErrorKind::Incomplete)?;
+        let mut v = Vec::new();
+        for_each_element(data, |element| {
+            v.push(from_cql_value(element_type, element)?);
+            Ok(())
+        })?;
+        Ok(v)
+    }
+}
+
+impl<T: FromCql + Eq + Hash, S: BuildHasher + Default> FromCql for HashSet<T, S> {
+    fn accepts(column_type: &ColumnType) -> bool {
+        element_type(column_type).map_or(false, T::accepts)
+    }
+
+    fn from_cql(column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+        Ok(Vec::<T>::from_cql(column_type, data)?.into_iter().collect())
+    }
+}
+
+/// Read the entries of a serialized map.
+fn map_entries<K: FromCql, V: FromCql>(column_type: &ColumnType, data: BytesMut) -> Result<Vec<(K, V)>> {
+    let (key_type, value_type) = match *column_type {
+        ColumnType::Map(ref k, ref v) => (k, v),
+        _ => return Err(ErrorKind::Incomplete.into()),
+    };
+    let (mut data, n) = ::codec::primitives::decode::int(data)?;
+    let mut entries = Vec::new();
+    for _ in 0..n {
+        let (rest, key) = deserialize_bytesmut(data)?;
+        let (rest, value) = deserialize_bytesmut(rest)?;
+        entries.push((from_cql_value(key_type, key)?, from_cql_value(value_type, value)?));
+        data = rest;
+    }
+    Ok(entries)
+}
+
+fn accepts_map<K: FromCql, V: FromCql>(column_type: &ColumnType) -> bool {
+    match *column_type {
+        ColumnType::Map(ref k, ref v) => K::accepts(k) && V::accepts(v),
+        _ => false,
+    }
+}
+
+impl<K: FromCql + Eq + Hash, V: FromCql, S: BuildHasher + Default> FromCql for HashMap<K, V, S> {
+    fn accepts(column_type: &ColumnType) -> bool {
+        accepts_map::<K, V>(column_type)
+    }
+
+    fn from_cql(column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+        Ok(map_entries(column_type, data)?.into_iter().collect())
+    }
+}
+
+impl<K: FromCql + Ord, V: FromCql> FromCql for BTreeMap<K, V> {
+    fn accepts(column_type: &ColumnType) -> bool {
+        accepts_map::<K, V>(column_type)
+    }
+
+    fn from_cql(column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+        Ok(map_entries(column_type, data)?.into_iter().collect())
+    }
+}
+
+macro_rules! tuple_from_cql {
+    ($len: expr, $($name: ident: $i: tt),+) => {
+        /// Tuples are read from tuples with the same amount of fields.
+        impl<$($name: FromCql),+> FromCql for ($($name,)+) {
+            fn accepts(column_type: &ColumnType) -> bool {
+                match *column_type {
+                    ColumnType::Tuple(ref d) => d.0.len() == $len $(&& $name::accepts(&d.0[$i]))+,
+                    _ => false,
+                }
+            }
+
+            // The remaining data after the last field is empty.
+            #[allow(unused_assignments)]
+            fn from_cql(column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+                let types = match *column_type {
+                    ColumnType::Tuple(ref d) if d.0.len() == $len => &d.0,
+                    _ => return Err(ErrorKind::Incomplete.into()),
+                };
+                let mut data = data;
+                Ok(($({
+                    let (rest, value) = deserialize_bytesmut(data)?;
+                    data = rest;
+                    from_cql_value::<$name>(&types[$i], value)?
+                },)+))
+            }
+        }
+    }
+}
+
+tuple_from_cql!(1, A: 0);
+tuple_from_cql!(2, A: 0, B: 1);
+tuple_from_cql!(3, A: 0, B: 1, C: 2);
+tuple_from_cql!(4, A: 0, B: 1, C: 2, D: 3);
+tuple_from_cql!(5, A: 0, B: 1, C: 2, D: 3, E: 4);
+tuple_from_cql!(6, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+tuple_from_cql!(7, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+tuple_from_cql!(8, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+
+macro_rules! serializable_from_cql {
+    ($($t: ty => $($column_type: pat)|+),*) => {
+        $(
+            impl FromCql for $t {
+                fn accepts(column_type: &ColumnType) -> bool {
+                    match *column_type {
+                        $($column_type)|+ => true,
+                        _ => false,
+                    }
+                }
+
+                fn from_cql(_column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+                    Self::deserialize(data)
+                }
+            }
+        )*
+    }
+}
+
+serializable_from_cql!(
+    Ascii => ColumnType::Ascii,
+    Bigint => ColumnType::Bigint | ColumnType::Counter,
+    Blob => ColumnType::Blob | ColumnType::Custom(_),
+    Boolean => ColumnType::Boolean,
+    Date => ColumnType::Date,
+    Decimal => ColumnType::Decimal,
+    Double => ColumnType::Double,
+    Float => ColumnType::Float,
+    Inet => ColumnType::Inet,
+    Int => ColumnType::Int,
+    Smallint => ColumnType::Smallint,
+    Text => ColumnType::Varchar | ColumnType::Ascii,
+    Time => ColumnType::Time,
+    Timestamp => ColumnType::Timestamp,
+    Tinyint => ColumnType::Tinyint,
+    Uuid => ColumnType::Uuid | ColumnType::Timeuuid,
+    Varint => ColumnType::Varint
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codec::response::TupleDefinition;
+
+    fn from<T: FromCql, V: ToCql>(column_type: ColumnType, value: V) -> Result<T> {
+        assert!(T::accepts(&column_type));
+        from_cql_value(&column_type, value.to_cql().as_option())
+    }
+
+    fn list(t: ColumnType) -> ColumnType {
+        ColumnType::List(Box::new(t))
+    }
+
+    #[test]
+    fn native_values() {
+        assert_eq!(from::<bool, _>(ColumnType::Boolean, true).unwrap(), true);
+        assert_eq!(from::<i32, _>(ColumnType::Int, -5i32).unwrap(), -5);
+        assert_eq!(from::<i64, _>(ColumnType::Counter, 5i64).unwrap(), 5);
+        assert_eq!(from::<i16, _>(ColumnType::Smallint, -300i16).unwrap(), -300);
+        assert_eq!(from::<i8, _>(ColumnType::Tinyint, -3i8).unwrap(), -3);
+        assert_eq!(from::<f64, _>(ColumnType::Double, 1.5f64).unwrap(), 1.5);
+        assert_eq!(from::<String, _>(ColumnType::Varchar, "ab").unwrap(), "ab");
+        assert_eq!(from::<Vec<u8>, _>(ColumnType::Blob, vec![1u8, 2]).unwrap(), vec![1, 2]);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        assert_eq!(from::<IpAddr, _>(ColumnType::Inet, ip).unwrap(), ip);
+        assert_eq!(from::<Int, _>(ColumnType::Int, 7i32).unwrap(), Int::new(7));
+    }
+
+    #[test]
+    fn date_times() {
+        let t = UTC.timestamp(-2, 5_000_000);
+        assert_eq!(from::<DateTime<UTC>, _>(ColumnType::Timestamp, t).unwrap(), t);
+        assert_eq!(
+            from::<NaiveDateTime, _>(ColumnType::Timestamp, t).unwrap(),
+            NaiveDateTime::from_timestamp(-2, 5_000_000)
+        );
+
+        let d = NaiveDate::from_ymd(1900, 2, 28);
+        assert_eq!(from::<NaiveDate, _>(ColumnType::Date, d).unwrap(), d);
+        let t = NaiveTime::from_hms_nano(23, 59, 59, 999_999_999);
+        assert_eq!(from::<NaiveTime, _>(ColumnType::Time, t).unwrap(), t);
+        assert!(from::<NaiveTime, _>(ColumnType::Time, Time::new(-1)).is_err());
+    }
+
+    #[test]
+    fn nulls() {
+        assert_eq!(from_cql_value::<Option<i32>>(&ColumnType::Int, None).unwrap(), None);
+        assert!(from_cql_value::<i32>(&ColumnType::Int, None).is_err());
+        assert_eq!(from::<Option<i32>, _>(ColumnType::Int, 1i32).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn collections() {
+        let l = list(ColumnType::Int);
+        assert_eq!(from::<Vec<i32>, _>(l.clone(), vec![1i32, 2]).unwrap(), vec![1, 2]);
+        let s: HashSet<i32> = from(ColumnType::Set(Box::new(ColumnType::Int)), vec![1i32]).unwrap();
+        assert!(s.contains(&1));
+        let nested = list(list(ColumnType::Varchar));
+        assert_eq!(
+            from::<Vec<Vec<String>>, _>(nested, vec![vec!["a"], vec![]]).unwrap(),
+            vec![vec!["a".to_string()], vec![]]
+        );
+
+        let mut map = HashMap::new();
+        map.insert("a", Some(1i32));
+        map.insert("b", None);
+        let m = ColumnType::Map(Box::new(ColumnType::Varchar), Box::new(ColumnType::Int));
+        let decoded: HashMap<String, Option<i32>> = from(m.clone(), map).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded["a"], Some(1));
+        assert_eq!(decoded["b"], None);
+
+        let tuple = ColumnType::Tuple(TupleDefinition(vec![ColumnType::Int, ColumnType::Varchar]));
+        assert_eq!(from::<(i32, Option<String>), _>(tuple, (1i32, None::<&str>)).unwrap(), (1, None));
+    }
+
+    #[test]
+    fn type_checks() {
+        assert!(!i32::accepts(&ColumnType::Bigint));
+        assert!(!String::accepts(&ColumnType::Blob));
+        assert!(!Vec::<i32>::accepts(&list(ColumnType::Varchar)));
+        assert!(!Vec::<u8>::accepts(&list(ColumnType::Int)));
+        assert!(!HashMap::<String, i32>::accepts(&ColumnType::Map(
+            Box::new(ColumnType::Varchar),
+            Box::new(ColumnType::Varchar),
+        )));
+        let tuple = ColumnType::Tuple(TupleDefinition(vec![ColumnType::Int]));
+        assert!(!<(i32, i32)>::accepts(&tuple));
+        assert!(from_cql_value::<i32>(&ColumnType::Int, Some(vec![0u8; 3].into())).is_err());
+    }
+}
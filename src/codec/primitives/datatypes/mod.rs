@@ -1,7 +1,7 @@
 use byteorder::BigEndian;
 use codec::primitives::CqlBytes;
 use bytes::BytesMut;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::collections::{HashSet, HashMap};
 use std::marker::PhantomData;
 use std::fmt::Formatter;
@@ -25,14 +25,33 @@ pub use self::primitive::*;
 mod special;
 pub use self::special::*;
 
+mod to_cql;
+pub use self::to_cql::*;
+
+mod from_cql;
+pub use self::from_cql::*;
+
+mod udt;
+pub use self::udt::*;
+
 type BytesLen = i32;
 
 mod errors {
     error_chain! {
         errors {
             InvalidAscii
+            InvalidUtf8
             Incomplete
             MaximumLengthExceeded
+            UnexpectedNull
+            UnexpectedType(column_type: String) {
+                description("A value can't be converted into the requested type")
+                display("A value of type {} can't be converted into the requested type", column_type)
+            }
+            NoSuchField(field: String) {
+                description("The user defined type has no such field")
+                display("The user defined type has no field {}", field)
+            }
         }
 
         foreign_links {
@@ -165,7 +184,11 @@ debug_cell!(
     ColumnType::Varint => Varint,
     ColumnType::Inet => Inet,
     ColumnType::Varchar => Varchar,
-    ColumnType::Ascii => Ascii
+    ColumnType::Ascii => Ascii,
+    ColumnType::Date => Date,
+    ColumnType::Time => Time,
+    ColumnType::Smallint => Smallint,
+    ColumnType::Tinyint => Tinyint
 );
 
 pub struct SerializableCell<'a>(pub &'a ColumnType, pub Option<BytesMut>);
@@ -238,7 +261,11 @@ serde_cell!(
     ColumnType::Varint => Varint,
     ColumnType::Inet => Inet,
     ColumnType::Varchar => Varchar,
-    ColumnType::Ascii => Ascii
+    ColumnType::Ascii => Ascii,
+    ColumnType::Date => Date,
+    ColumnType::Time => Time,
+    ColumnType::Smallint => Smallint,
+    ColumnType::Tinyint => Tinyint
 );
 
 #[cfg(test)]
@@ -396,6 +423,30 @@ mod test_encode_decode {
         assert_serialization_deserialization(to_encode);
     }
 
+    #[test]
+    fn date() {
+        assert_serialization_deserialization(Date::new(1 << 31));
+        assert_eq!(format!("{:?}", Date::new((1 << 31) - 1)), "1969-12-31");
+        assert_eq!(format!("{:?}", Date::new(0)), "-2147483648 days");
+    }
+
+    #[test]
+    fn time() {
+        assert_serialization_deserialization(Time::new(3_600_000_000_001));
+        assert_eq!(format!("{:?}", Time::new(3_600_000_000_001)), "01:00:00.000000001");
+        assert_eq!(format!("{:?}", Time::new(-1)), "-1ns");
+    }
+
+    #[test]
+    fn smallint() {
+        assert_serialization_deserialization(Smallint::new(-12345));
+    }
+
+    #[test]
+    fn tinyint() {
+        assert_serialization_deserialization(Tinyint::new(-123));
+    }
+
     #[test]
     fn uuid() {
         let to_encode = Uuid::new([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
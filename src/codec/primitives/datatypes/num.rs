@@ -262,6 +262,98 @@ impl ::serde::Serialize for Int {
     }
 }
 
+#[derive(PartialEq, Eq, Clone)]
+pub struct Smallint {
+    inner: i16,
+}
+
+impl Debug for Smallint {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        self.inner.fmt(fmt)
+    }
+}
+
+impl Smallint {
+    pub fn new(i: i16) -> Self {
+        Smallint { inner: i }
+    }
+}
+
+impl CqlSerializable for Smallint {
+    fn serialize(&self, buf: &mut BytesMut) {
+        buf.reserve(2);
+        buf.put_i16::<BigEndian>(self.inner);
+    }
+
+    fn deserialize(data: BytesMut) -> Result<Self> {
+        if data.len() != 2 {
+            return Err(ErrorKind::Incomplete.into());
+        }
+        let v = BigEndian::read_i16(data.as_ref());
+        Ok(Smallint { inner: v })
+    }
+
+    fn bytes_len(&self) -> Option<BytesLen> {
+        Some(2)
+    }
+}
+
+#[cfg(feature = "with-serde")]
+impl ::serde::Serialize for Smallint {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: ::serde::ser::Serializer,
+    {
+        serializer.serialize_i16(self.inner)
+    }
+}
+
+#[derive(PartialEq, Eq, Clone)]
+pub struct Tinyint {
+    inner: i8,
+}
+
+impl Debug for Tinyint {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        self.inner.fmt(fmt)
+    }
+}
+
+impl Tinyint {
+    pub fn new(i: i8) -> Self {
+        Tinyint { inner: i }
+    }
+}
+
+impl CqlSerializable for Tinyint {
+    fn serialize(&self, buf: &mut BytesMut) {
+        buf.reserve(1);
+        buf.put_i8(self.inner);
+    }
+
+    fn deserialize(data: BytesMut) -> Result<Self> {
+        if data.len() != 1 {
+            return Err(ErrorKind::Incomplete.into());
+        }
+        let v = data[0] as i8;
+        Ok(Tinyint { inner: v })
+    }
+
+    fn bytes_len(&self) -> Option<BytesLen> {
+        Some(1)
+    }
+}
+
+#[cfg(feature = "with-serde")]
+impl ::serde::Serialize for Tinyint {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: ::serde::ser::Serializer,
+    {
+        serializer.serialize_i8(self.inner)
+    }
+}
+
 #[derive(PartialEq, Eq, Clone)]
 pub struct Decimal {
     scale: i32,
@@ -2,6 +2,7 @@ use super::*;
 use std::fmt::Debug;
 use bytes::BufMut;
 use byteorder::ByteOrder;
+use chrono::{Datelike, NaiveDate, NaiveTime, Timelike};
 
 #[derive(PartialEq, Clone)]
 pub enum Inet {
@@ -61,6 +62,15 @@ impl CqlSerializable for Inet {
     }
 }
 
+impl From<Inet> for IpAddr {
+    fn from(f: Inet) -> Self {
+        match f {
+            Inet::Ipv4(addr) => IpAddr::V4(addr),
+            Inet::Ipv6(addr) => IpAddr::V6(addr),
+        }
+    }
+}
+
 impl Debug for Inet {
     fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
         match *self {
@@ -127,6 +137,140 @@ impl ::serde::Serialize for Timestamp {
     }
 }
 
+/// The day of the epoch in dates, 1970-01-01.
+const EPOCH_DAY: i64 = 1 << 31;
+/// The days from 0001-01-01 until 1970-01-01.
+const EPOCH_DAYS_FROM_CE: i64 = 719_163;
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+const NANOS_PER_DAY: i64 = 86_400 * NANOS_PER_SECOND;
+
+/// A date without time zone, as days since the epoch, which is day 2^31.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct Date {
+    days: u32,
+}
+
+impl Date {
+    pub fn new(days: u32) -> Self {
+        Date { days: days }
+    }
+
+    pub fn from_naive(date: NaiveDate) -> Self {
+        Date { days: (i64::from(date.num_days_from_ce()) - EPOCH_DAYS_FROM_CE + EPOCH_DAY) as u32 }
+    }
+
+    /// The date, or `None` if it is out of the range chrono supports.
+    pub fn to_naive(&self) -> Option<NaiveDate> {
+        let days = i64::from(self.days) - EPOCH_DAY + EPOCH_DAYS_FROM_CE;
+        if days < i64::from(i32::min_value()) || days > i64::from(i32::max_value()) {
+            return None;
+        }
+        NaiveDate::from_num_days_from_ce_opt(days as i32)
+    }
+}
+
+impl CqlSerializable for Date {
+    fn serialize(&self, buf: &mut BytesMut) {
+        buf.reserve(4);
+        buf.put_u32::<BigEndian>(self.days);
+    }
+
+    fn deserialize(data: BytesMut) -> Result<Self> {
+        if data.len() != 4 {
+            return Err(ErrorKind::Incomplete.into());
+        }
+        Ok(Date { days: BigEndian::read_u32(data.as_ref()) })
+    }
+
+    fn bytes_len(&self) -> Option<BytesLen> {
+        Some(4)
+    }
+}
+
+impl Debug for Date {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self.to_naive() {
+            Some(date) => ::std::fmt::Display::fmt(&date, fmt),
+            None => write!(fmt, "{} days", i64::from(self.days) - EPOCH_DAY),
+        }
+    }
+}
+
+#[cfg(feature = "with-serde")]
+impl ::serde::Serialize for Date {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: ::serde::ser::Serializer,
+    {
+        serializer.serialize_str(&format!("{:?}", self))
+    }
+}
+
+/// A time of day without time zone, as nanoseconds since midnight.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct Time {
+    nanos: i64,
+}
+
+impl Time {
+    pub fn new(nanos: i64) -> Self {
+        Time { nanos: nanos }
+    }
+
+    /// Leap seconds are not representable, and end up in the next second.
+    pub fn from_naive(time: NaiveTime) -> Self {
+        Time { nanos: i64::from(time.num_seconds_from_midnight()) * NANOS_PER_SECOND + i64::from(time.nanosecond()) }
+    }
+
+    /// The time, or `None` if it isn't within a day.
+    pub fn to_naive(&self) -> Option<NaiveTime> {
+        if self.nanos < 0 || self.nanos >= NANOS_PER_DAY {
+            return None;
+        }
+        NaiveTime::from_num_seconds_from_midnight_opt(
+            (self.nanos / NANOS_PER_SECOND) as u32,
+            (self.nanos % NANOS_PER_SECOND) as u32,
+        )
+    }
+}
+
+impl CqlSerializable for Time {
+    fn serialize(&self, buf: &mut BytesMut) {
+        buf.reserve(8);
+        buf.put_i64::<BigEndian>(self.nanos);
+    }
+
+    fn deserialize(data: BytesMut) -> Result<Self> {
+        if data.len() != 8 {
+            return Err(ErrorKind::Incomplete.into());
+        }
+        Ok(Time { nanos: BigEndian::read_i64(data.as_ref()) })
+    }
+
+    fn bytes_len(&self) -> Option<BytesLen> {
+        Some(8)
+    }
+}
+
+impl Debug for Time {
+    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self.to_naive() {
+            Some(time) => ::std::fmt::Display::fmt(&time, fmt),
+            None => write!(fmt, "{}ns", self.nanos),
+        }
+    }
+}
+
+#[cfg(feature = "with-serde")]
+impl ::serde::Serialize for Time {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: ::serde::ser::Serializer,
+    {
+        serializer.serialize_str(&format!("{:?}", self))
+    }
+}
+
 #[derive(PartialEq, Eq, Clone)]
 pub struct Uuid {
     inner: [u8; 16],
@@ -228,6 +372,12 @@ mod test {
         assert_eq!("::ffff:192.10.2.255", format!("{:?}", iv6));
     }
 
+    #[test]
+    fn inet_into_ip_addr() {
+        let ip: IpAddr = Inet::Ipv4(Ipv4Addr::new(127, 0, 0, 1)).into();
+        assert_eq!(ip, "127.0.0.1".parse::<IpAddr>().unwrap());
+    }
+
     #[test]
     fn timestamp_debug() {
         let timestamp = Timestamp::new(1491283495);
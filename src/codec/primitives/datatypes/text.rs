@@ -104,6 +104,12 @@ impl ::serde::Serialize for Text {
     }
 }
 
+impl AsRef<str> for Text {
+    fn as_ref(&self) -> &str {
+        &self.inner
+    }
+}
+
 pub type Varchar = Text;
 
 impl Debug for Text {
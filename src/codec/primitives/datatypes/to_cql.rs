@@ -0,0 +1,363 @@
+use super::*;
+use bytes::BufMut;
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone};
+use codec::primitives::encode;
+use codec::request::QueryValues;
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hash};
+
+/// Conversion of native values into values bound to the variables of a query.
+///
+/// See the `values!` and `named_values!` macros to build the `QueryValues` of a query.
+pub trait ToCql {
+    /// The serialized value, which is null for `None`.
+    fn to_cql(&self) -> CqlBytes;
+}
+
+/// Conversion of native values into all values of a query, usually derived with
+/// `#[derive(IntoValues)]`.
+pub trait IntoValues {
+    fn into_values(self) -> QueryValues;
+}
+
+impl IntoValues for QueryValues {
+    fn into_values(self) -> QueryValues {
+        self
+    }
+}
+
+fn serialized<F>(capacity: usize, f: F) -> CqlBytes
+where
+    F: FnOnce(&mut BytesMut),
+{
+    let mut buf = BytesMut::with_capacity(capacity);
+    f(&mut buf);
+    CqlBytes::from(buf)
+}
+
+impl<'a, T: ToCql + ?Sized> ToCql for &'a T {
+    fn to_cql(&self) -> CqlBytes {
+        (**self).to_cql()
+    }
+}
+
+impl<T: ToCql> ToCql for Option<T> {
+    fn to_cql(&self) -> CqlBytes {
+        match *self {
+            Some(ref v) => v.to_cql(),
+            None => CqlBytes::null_value(),
+        }
+    }
+}
+
+impl ToCql for bool {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(1, |buf| buf.put_u8(*self as u8))
+    }
+}
+
+impl ToCql for i8 {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(1, |buf| buf.put_i8(*self))
+    }
+}
+
+impl ToCql for i16 {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(2, |buf| buf.put_i16::<BigEndian>(*self))
+    }
+}
+
+impl ToCql for i32 {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(4, |buf| buf.put_i32::<BigEndian>(*self))
+    }
+}
+
+impl ToCql for i64 {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(8, |buf| buf.put_i64::<BigEndian>(*self))
+    }
+}
+
+impl ToCql for f32 {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(4, |buf| buf.put_f32::<BigEndian>(*self))
+    }
+}
+
+impl ToCql for f64 {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(8, |buf| buf.put_f64::<BigEndian>(*self))
+    }
+}
+
+impl ToCql for str {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(self.len(), |buf| buf.put_slice(self.as_bytes()))
+    }
+}
+
+impl ToCql for String {
+    fn to_cql(&self) -> CqlBytes {
+        self.as_str().to_cql()
+    }
+}
+
+/// Bytes are sent as blob.
+impl ToCql for [u8] {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(self.len(), |buf| buf.put_slice(self))
+    }
+}
+
+/// Bytes are sent as blob.
+impl ToCql for Vec<u8> {
+    fn to_cql(&self) -> CqlBytes {
+        self.as_slice().to_cql()
+    }
+}
+
+impl ToCql for IpAddr {
+    fn to_cql(&self) -> CqlBytes {
+        match *self {
+            IpAddr::V4(ref addr) => addr.to_cql(),
+            IpAddr::V6(ref addr) => addr.to_cql(),
+        }
+    }
+}
+
+impl ToCql for Ipv4Addr {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(4, |buf| buf.put_slice(&self.octets()))
+    }
+}
+
+impl ToCql for Ipv6Addr {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(16, |buf| buf.put_slice(&self.octets()))
+    }
+}
+
+/// Date-times are sent as timestamp, with millisecond precision.
+impl<Tz: TimeZone> ToCql for DateTime<Tz> {
+    fn to_cql(&self) -> CqlBytes {
+        (self.timestamp() * 1000 + i64::from(self.timestamp_subsec_millis())).to_cql()
+    }
+}
+
+/// Dates are sent as date.
+impl ToCql for NaiveDate {
+    fn to_cql(&self) -> CqlBytes {
+        Date::from_naive(*self).to_cql()
+    }
+}
+
+/// Times are sent as time, with nanosecond precision.
+impl ToCql for NaiveTime {
+    fn to_cql(&self) -> CqlBytes {
+        Time::from_naive(*self).to_cql()
+    }
+}
+
+/// Write `values` as the elements of a list, set or map.
+fn serialize_elements<'a, I, T>(len: usize, values: I, buf: &mut BytesMut)
+where
+    I: IntoIterator<Item = &'a T>,
+    T: ToCql + 'a,
+{
+    encode::int(len as BytesLen, buf);
+    for value in values {
+        encode::bytes(&value.to_cql(), buf);
+    }
+}
+
+/// Vectors of anything but bytes are sent as list.
+impl<T: ToCql> ToCql for Vec<T> {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(64, |buf| serialize_elements(self.len(), self, buf))
+    }
+}
+
+impl<T: ToCql + Eq + Hash, S: BuildHasher> ToCql for HashSet<T, S> {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(64, |buf| serialize_elements(self.len(), self, buf))
+    }
+}
+
+impl<K: ToCql + Eq + Hash, V: ToCql, S: BuildHasher> ToCql for HashMap<K, V, S> {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(64, |buf| {
+            encode::int(self.len() as BytesLen, buf);
+            for (k, v) in self {
+                encode::bytes(&k.to_cql(), buf);
+                encode::bytes(&v.to_cql(), buf);
+            }
+        })
+    }
+}
+
+impl<K: ToCql + Ord, V: ToCql> ToCql for BTreeMap<K, V> {
+    fn to_cql(&self) -> CqlBytes {
+        serialized(64, |buf| {
+            encode::int(self.len() as BytesLen, buf);
+            for (k, v) in self {
+                encode::bytes(&k.to_cql(), buf);
+                encode::bytes(&v.to_cql(), buf);
+            }
+        })
+    }
+}
+
+macro_rules! tuple_to_cql {
+    ($($name: ident),+) => {
+        /// Tuples are sent as tuple, and not as separate values.
+        impl<$($name: ToCql),+> ToCql for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn to_cql(&self) -> CqlBytes {
+                let ($(ref $name,)+) = *self;
+                serialized(64, |buf| {
+                    $(encode::bytes(&$name.to_cql(), buf);)+
+                })
+            }
+        }
+    }
+}
+
+tuple_to_cql!(A);
+tuple_to_cql!(A, B);
+tuple_to_cql!(A, B, C);
+tuple_to_cql!(A, B, C, D);
+tuple_to_cql!(A, B, C, D, E);
+tuple_to_cql!(A, B, C, D, E, F);
+tuple_to_cql!(A, B, C, D, E, F, G);
+tuple_to_cql!(A, B, C, D, E, F, G, H);
+
+macro_rules! serializable_to_cql {
+    ($($t: ty),*) => {
+        $(
+            impl ToCql for $t {
+                fn to_cql(&self) -> CqlBytes {
+                    serialized(16, |buf| self.serialize(buf))
+                }
+            }
+        )*
+    }
+}
+
+serializable_to_cql!(
+    Ascii,
+    Bigint,
+    Blob,
+    Boolean,
+    Date,
+    Decimal,
+    Double,
+    Float,
+    Inet,
+    Int,
+    Smallint,
+    Text,
+    Time,
+    Timestamp,
+    Tinyint,
+    Uuid,
+    Varint
+);
+
+/// Values which were serialized already, like the ones returned by `IntoUdt::to_udt`.
+impl ToCql for CqlBytes {
+    fn to_cql(&self) -> CqlBytes {
+        self.clone()
+    }
+}
+
+/// The unset value, which leaves a bound variable untouched. Requires protocol version 4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unset;
+
+impl ToCql for Unset {
+    fn to_cql(&self) -> CqlBytes {
+        CqlBytes::unset_value()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{FixedOffset, UTC};
+    use codec::request::QueryValues;
+    use codec::primitives::{CqlFrom, CqlString};
+
+    fn bytes(value: &ToCql) -> Vec<u8> {
+        value.to_cql().as_bytes().expect("a value which is not null").to_vec()
+    }
+
+    #[test]
+    fn native_values() {
+        assert_eq!(bytes(&true), vec![1]);
+        assert_eq!(bytes(&-2i32), vec![0xff, 0xff, 0xff, 0xfe]);
+        assert_eq!(bytes(&1i64), vec![0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(bytes(&-1i8), vec![0xff]);
+        assert_eq!(bytes(&258i16), vec![1, 2]);
+        assert_eq!(bytes(&1.5f64), vec![0x3f, 0xf8, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(bytes(&"ab"), b"ab".to_vec());
+        assert_eq!(bytes(&String::from("ab")), b"ab".to_vec());
+        assert_eq!(bytes(&vec![1u8, 2]), vec![1, 2]);
+        assert_eq!(bytes(&vec![1u8, 2].to_cql()), vec![1, 2]);
+        assert_eq!(bytes(&"10.0.0.1".parse::<IpAddr>().unwrap()), vec![10, 0, 0, 1]);
+        assert_eq!(bytes(&Int::new(7)), bytes(&7i32));
+        assert_eq!(None::<i32>.to_cql(), CqlBytes::null_value());
+        assert_eq!(Unset.to_cql(), CqlBytes::unset_value());
+    }
+
+    #[test]
+    fn date_times() {
+        let utc = UTC.timestamp(1, 5_000_000);
+        assert_eq!(bytes(&utc), bytes(&1005i64));
+        let offset = FixedOffset::east(3600).timestamp(1, 0);
+        assert_eq!(bytes(&offset), bytes(&1000i64));
+        assert_eq!(bytes(&NaiveDate::from_ymd(1970, 1, 2)), vec![0x80, 0, 0, 1]);
+        assert_eq!(bytes(&NaiveTime::from_hms_nano(0, 0, 1, 5)), bytes(&1_000_000_005i64));
+    }
+
+    #[test]
+    fn collections() {
+        let list = vec![1i32, 2];
+        assert_eq!(
+            bytes(&list),
+            vec![0, 0, 0, 2, 0, 0, 0, 4, 0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 2]
+        );
+        let set: HashSet<_> = vec![1i32].into_iter().collect();
+        assert_eq!(bytes(&set), vec![0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 1]);
+        let map: HashMap<_, _> = vec![("a", true)].into_iter().collect();
+        assert_eq!(bytes(&map), vec![0, 0, 0, 1, 0, 0, 0, 1, b'a', 0, 0, 0, 1, 1]);
+        assert_eq!(
+            bytes(&(1i32, None::<bool>)),
+            vec![0, 0, 0, 4, 0, 0, 0, 1, 0xff, 0xff, 0xff, 0xff]
+        );
+    }
+
+    #[test]
+    fn values_macros() {
+        let name = String::from("a");
+        match values![1i32, name, None::<bool>] {
+            QueryValues::Positional(values) => {
+                assert_eq!(values, vec![1i32.to_cql(), "a".to_cql(), CqlBytes::null_value()]);
+            }
+            _ => panic!("expected positional values"),
+        }
+        match values![] {
+            QueryValues::Positional(values) => assert!(values.is_empty()),
+            _ => panic!("expected positional values"),
+        }
+        match named_values!{"id" => 1i32, "name" => "a"} {
+            QueryValues::Named(values) => {
+                assert_eq!(values.len(), 2);
+                assert_eq!(values[&CqlString::try_from("id").unwrap()], 1i32.to_cql());
+                assert_eq!(values[&CqlString::try_from("name").unwrap()], "a".to_cql());
+            }
+            _ => panic!("expected named values"),
+        }
+    }
+}
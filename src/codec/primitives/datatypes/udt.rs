@@ -0,0 +1,195 @@
+use super::*;
+use codec::primitives::encode;
+use codec::response::UdtDefinition;
+
+/// Conversion of user defined types into native values, usually derived with `#[derive(FromUdt)]`.
+pub trait FromUdt: Sized {
+    /// Whether values of the user defined type `def` can be converted into `Self`.
+    fn accepts_udt(def: &UdtDefinition) -> bool;
+
+    /// Convert a value whose definition was accepted before.
+    fn from_udt(value: &UdtValue) -> Result<Self>;
+}
+
+/// Conversion of native values into user defined types, usually derived with `#[derive(IntoUdt)]`.
+///
+/// Values are converted with `to_udt` instead of `ToCql`, as the fields have to be sent in the
+/// order of the definition of the type.
+pub trait IntoUdt {
+    /// The values of all fields, by name.
+    fn udt_fields(&self) -> Vec<(&'static str, CqlBytes)>;
+
+    /// The value serialized in the field order of `def`, with all fields missing in `self` null.
+    ///
+    /// Fails if `self` has a field which `def` doesn't know.
+    fn to_udt(&self, def: &UdtDefinition) -> Result<CqlBytes> {
+        let mut fields: Vec<_> = self.udt_fields().into_iter().map(Some).collect();
+        let mut buf = BytesMut::with_capacity(64);
+        for field in &def.fields {
+            let value = fields
+                .iter_mut()
+                .find(|f| f.as_ref().map_or(false, |f| f.0 == field.0.as_ref()))
+                .and_then(Option::take)
+                .map_or_else(CqlBytes::null_value, |f| f.1);
+            encode::bytes(&value, &mut buf);
+        }
+        match fields.into_iter().filter_map(|f| f).next() {
+            Some((name, _)) => Err(ErrorKind::NoSuchField(name.to_string()).into()),
+            None => Ok(CqlBytes::from(buf)),
+        }
+    }
+}
+
+/// The fields of a value of a user defined type, which can be looked up by name.
+pub struct UdtValue<'a> {
+    def: &'a UdtDefinition,
+    fields: Vec<Option<BytesMut>>,
+}
+
+impl<'a> UdtValue<'a> {
+    /// Values which were written before fields were added to the type lack the new fields, which
+    /// are null.
+    pub fn decode(def: &'a UdtDefinition, data: BytesMut) -> Result<Self> {
+        let mut fields = Vec::with_capacity(def.fields.len());
+        let mut data = data;
+        while !data.is_empty() && fields.len() < def.fields.len() {
+            let (rest, field) = deserialize_bytesmut(data)?;
+            fields.push(field);
+            data = rest;
+        }
+        Ok(UdtValue {
+            def: def,
+            fields: fields,
+        })
+    }
+
+    /// The definition of the type of the value.
+    pub fn definition(&self) -> &UdtDefinition {
+        self.def
+    }
+
+    /// The value of the field `name`, converted into `T`.
+    pub fn get<T: FromCql>(&self, name: &str) -> Result<T> {
+        let i = self.def
+            .fields
+            .iter()
+            .position(|f| f.0.as_ref() == name)
+            .ok_or_else(|| Error::from(ErrorKind::NoSuchField(name.to_string())))?;
+        let field_type = &self.def.fields[i].1;
+        if !T::accepts(field_type) {
+            return Err(ErrorKind::UnexpectedType(format!("{:?}", field_type)).into());
+        }
+        from_cql_value(field_type, self.fields.get(i).cloned().and_then(|f| f))
+    }
+}
+
+/// Types implementing `FromUdt` are read from columns of their user defined type.
+impl<T: FromUdt> FromCql for T {
+    fn accepts(column_type: &ColumnType) -> bool {
+        match *column_type {
+            ColumnType::Udt(ref def) => T::accepts_udt(def),
+            _ => false,
+        }
+    }
+
+    fn from_cql(column_type: &ColumnType, data: BytesMut) -> Result<Self> {
+        match *column_type {
+            ColumnType::Udt(ref def) => T::from_udt(&UdtValue::decode(def, data)?),
+            _ => Err(ErrorKind::UnexpectedType(format!("{:?}", column_type)).into()),
+        }
+    }
+}
+
+/// Whether `def` has a field `name` whose type can be converted into `T`.
+pub fn udt_field_accepts<T: FromCql>(def: &UdtDefinition, name: &str) -> bool {
+    def.fields.iter().any(|f| f.0.as_ref() == name && T::accepts(&f.1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codec::primitives::{CqlFrom, CqlString};
+    use codec::response::UdtField;
+
+    fn address() -> UdtDefinition {
+        UdtDefinition {
+            keyspace: CqlString::try_from("ks").unwrap(),
+            name: CqlString::try_from("address").unwrap(),
+            fields: vec![
+                UdtField(CqlString::try_from("street").unwrap(), ColumnType::Varchar),
+                UdtField(CqlString::try_from("zip").unwrap(), ColumnType::Int),
+            ],
+        }
+    }
+
+    struct Address {
+        street: String,
+        zip: Option<i32>,
+    }
+
+    impl FromUdt for Address {
+        fn accepts_udt(def: &UdtDefinition) -> bool {
+            udt_field_accepts::<String>(def, "street") && udt_field_accepts::<Option<i32>>(def, "zip")
+        }
+
+        fn from_udt(value: &UdtValue) -> Result<Self> {
+            Ok(Address {
+                street: value.get("street")?,
+                zip: value.get("zip")?,
+            })
+        }
+    }
+
+    impl IntoUdt for Address {
+        fn udt_fields(&self) -> Vec<(&'static str, CqlBytes)> {
+            vec![("zip", self.zip.to_cql()), ("street", self.street.to_cql())]
+        }
+    }
+
+    #[test]
+    fn udt_roundtrip() {
+        let def = address();
+        let value = Address {
+            street: String::from("main"),
+            zip: Some(1),
+        };
+        let data = value.to_udt(&def).unwrap().as_option().unwrap();
+        let ctype = ColumnType::Udt(def);
+        assert!(Address::accepts(&ctype));
+        let decoded = Address::from_cql(&ctype, data).unwrap();
+        assert_eq!(decoded.street, "main");
+        assert_eq!(decoded.zip, Some(1));
+    }
+
+    #[test]
+    fn udt_missing_fields() {
+        let def = address();
+        let data = "main".to_cql();
+        let mut buf = BytesMut::with_capacity(16);
+        encode::bytes(&data, &mut buf);
+        let value = UdtValue::decode(&def, buf).unwrap();
+        assert_eq!(value.get::<Option<i32>>("zip").unwrap(), None);
+        assert!(value.get::<i32>("zip").is_err());
+        assert!(value.get::<String>("city").is_err());
+        assert!(value.get::<i32>("street").is_err());
+    }
+
+    #[test]
+    fn udt_type_checks() {
+        let mut def = address();
+        assert!(Address::accepts_udt(&def));
+        def.fields[1].1 = ColumnType::Varchar;
+        assert!(!Address::accepts_udt(&def));
+        assert!(!Address::accepts(&ColumnType::Varchar));
+
+        def.fields.pop();
+        let value = Address {
+            street: String::from("main"),
+            zip: None,
+        };
+        match *value.to_udt(&def).unwrap_err().kind() {
+            ErrorKind::NoSuchField(ref name) => assert_eq!(name, "zip"),
+            ref e => panic!("unexpected error {:?}", e),
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use codec::primitives::types::{CqlStringList, CqlString, CqlLongString, CqlStringMap, CqlStringMultiMap, CqlBytes,
-                               CqlConsistency};
+                               CqlBytesMap, CqlConsistency};
 use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use bytes::BytesMut;
 use byteorder::{ByteOrder, BigEndian};
 use codec::primitives::CqlFrom;
@@ -34,6 +35,14 @@ use self::Needed::*;
 
 pub type ParseResult<T> = Result<(BytesMut, T), Error>;
 
+pub fn byte(mut i: BytesMut) -> ParseResult<u8> {
+    if i.is_empty() {
+        return Err(Incomplete(Size(1)));
+    }
+    let databuf = i.split_to(1);
+    Ok((i, databuf[0]))
+}
+
 pub fn short(mut i: BytesMut) -> ParseResult<u16> {
     if i.len() < 2 {
         return Err(Incomplete(Size(2)));
@@ -83,6 +92,8 @@ pub fn bytes(buf: BytesMut) -> ParseResult<CqlBytes> {
     let (mut buf, len) = int(buf)?;
     if (buf.len() as isize) < len as isize {
         return Err(Incomplete(Size(len as usize)));
+    } else if len == -2 {
+        return Ok((buf, CqlBytes::unset_value()));
     } else if len < 0 {
         return Ok((buf, CqlBytes::null_value()));
     }
@@ -90,6 +101,37 @@ pub fn bytes(buf: BytesMut) -> ParseResult<CqlBytes> {
     Ok((buf, b))
 }
 
+pub fn short_bytes(buf: BytesMut) -> ParseResult<CqlBytes> {
+    let (mut buf, len) = short(buf)?;
+    if buf.len() < len as usize {
+        return Err(Incomplete(Size(len as usize)));
+    }
+    let b = CqlBytes::from(buf.split_to(len as usize));
+    Ok((buf, b))
+}
+
+pub fn inet(mut i: BytesMut) -> ParseResult<SocketAddr> {
+    if i.len() < 1 {
+        return Err(Incomplete(Size(1)));
+    }
+    let size = i.split_to(1)[0] as usize;
+    if i.len() < size {
+        return Err(Incomplete(Size(size)));
+    }
+    let addr = i.split_to(size);
+    let ip = match size {
+        4 => IpAddr::V4(Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3])),
+        16 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(addr.as_ref());
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return Err(ParseError(format!("Invalid inet address length: {}", size))),
+    };
+    let (i, port) = int(i)?;
+    Ok((i, SocketAddr::new(ip, port as u16)))
+}
+
 pub fn string_list(i: BytesMut) -> ParseResult<CqlStringList> {
     let (mut buf, len) = short(i)?;
     let mut v = Vec::new();
@@ -132,6 +174,21 @@ pub fn string_multimap(i: BytesMut) -> ParseResult<CqlStringMultiMap> {
     Ok((buf, unsafe { CqlStringMultiMap::unchecked_from(map) }))
 }
 
+pub fn bytes_map(i: BytesMut) -> ParseResult<CqlBytesMap> {
+    let (mut buf, len) = short(i)?;
+    let mut map = HashMap::new();
+
+    for _ in 0..len {
+        let (nb, key) = string(buf)?;
+        buf = nb;
+        let (nb, value) = bytes(buf)?;
+        buf = nb;
+        map.insert(key, value);
+    }
+
+    Ok((buf, unsafe { CqlBytesMap::unchecked_from(map) }))
+}
+
 pub fn consistency(mut i: BytesMut) -> ParseResult<CqlConsistency> {
     if i.len() < 2 {
         return Err(Incomplete(Size(2)));
@@ -151,6 +208,14 @@ mod test {
     #[allow(unused_imports)]
     use super::super::encode;
 
+    #[test]
+    fn byte_incomplete() {
+        assert_eq!(byte(vec![].into()).unwrap_err(), Incomplete(Size(1)));
+        let (rest, b) = byte(vec![7, 8].into()).unwrap();
+        assert_eq!(b, 7);
+        assert_eq!(&rest[..], &[8]);
+    }
+
     #[test]
     fn short_incomplete() {
         assert_eq!(short(vec![0].into()).unwrap_err(), Incomplete(Size(2)));
@@ -233,6 +298,54 @@ mod test {
         );
     }
 
+    #[test]
+    fn short_bytes_complete_and_incomplete() {
+        let v = cql_bytes!(1, 2, 3);
+        let mut b = BytesMut::with_capacity(64);
+        encode::short_bytes(&v, &mut b);
+        assert_eq!(&b[..], &[0x00, 0x03, 1, 2, 3][..]);
+
+        assert_eq!(
+            short_bytes(b.clone().split_to(4)).unwrap_err(),
+            Incomplete(Size(3))
+        );
+        let (b, res) = short_bytes(b).unwrap();
+        assert_eq!(b.len(), 0);
+        assert_eq!(res, v);
+    }
+
+    #[test]
+    fn inet_v4_and_v6() {
+        let b: BytesMut = vec![4, 127, 0, 0, 1, 0, 0, 0x23, 0x52, 0xff].into();
+        let (b, addr) = inet(b).unwrap();
+        assert_eq!(b.len(), 1);
+        assert_eq!(addr, "127.0.0.1:9042".parse().unwrap());
+
+        let mut v = vec![16];
+        v.extend(&[0u8; 15]);
+        v.extend(&[1, 0, 0, 0x23, 0x52]);
+        let (_, addr) = inet(v.into()).unwrap();
+        assert_eq!(addr, "[::1]:9042".parse().unwrap());
+
+        assert_eq!(
+            inet(vec![4, 127].into()).unwrap_err(),
+            Incomplete(Size(4))
+        );
+    }
+
+    #[test]
+    fn bytes_map_complete() {
+        let mut b = BytesMut::with_capacity(64);
+        encode::short(1, &mut b);
+        encode::string(&cql_string!("k"), &mut b);
+        encode::bytes(&cql_bytes!(1, 2), &mut b);
+
+        let (b, map) = bytes_map(b).unwrap();
+        assert_eq!(b.len(), 0);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get(&cql_string!("k")), Some(&cql_bytes!(1, 2)));
+    }
+
     // TODO: move tests from types here, cause it seems very similar
     //
     //    #[test]
@@ -1,5 +1,6 @@
 use bytes::{BufMut, BytesMut, BigEndian};
-use super::{CqlStringList, CqlLongString, CqlString, CqlBytes, CqlStringMap, CqlStringMultiMap, CqlConsistency};
+use super::{CqlStringList, CqlLongString, CqlString, CqlBytes, CqlBytesMap, CqlStringMap, CqlStringMultiMap,
+            CqlConsistency};
 
 pub fn short(v: u16, buf: &mut BytesMut) {
     buf.reserve(2);
@@ -33,6 +34,12 @@ pub fn bytes(b: &CqlBytes, buf: &mut BytesMut) {
     }
 }
 
+pub fn short_bytes(b: &CqlBytes, buf: &mut BytesMut) {
+    let b = b.as_bytes().unwrap_or(&[]);
+    short(b.len() as u16, buf);
+    buf.extend(b);
+}
+
 pub fn string_list(l: &CqlStringList, buf: &mut BytesMut) {
     short(l.len(), buf);
     for s in l.iter() {
@@ -56,6 +63,14 @@ pub fn string_multimap(m: &CqlStringMultiMap, buf: &mut BytesMut) {
     }
 }
 
+pub fn bytes_map(m: &CqlBytesMap, buf: &mut BytesMut) {
+    short(m.len(), buf);
+    for (k, v) in m.iter() {
+        string(k, buf);
+        bytes(v, buf);
+    }
+}
+
 pub fn consistency(v: &CqlConsistency, buf: &mut BytesMut) {
     short(v.as_short(), buf);
 }
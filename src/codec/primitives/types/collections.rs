@@ -142,3 +142,32 @@ impl CqlStringMultiMap {
         self.container.get(k)
     }
 }
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct CqlBytesMap {
+    container: HashMap<CqlString, CqlBytes>,
+}
+
+impl CqlFrom<CqlBytesMap, HashMap<CqlString, CqlBytes>> for CqlBytesMap {
+    unsafe fn unchecked_from(map: HashMap<CqlString, CqlBytes>) -> CqlBytesMap {
+        CqlBytesMap { container: map }
+    }
+
+    fn max_len() -> usize {
+        u16::max_value() as usize
+    }
+}
+
+impl CqlBytesMap {
+    pub fn len(&self) -> u16 {
+        self.container.len() as u16
+    }
+
+    pub fn iter(&self) -> ::std::collections::hash_map::Iter<CqlString, CqlBytes> {
+        self.container.iter()
+    }
+
+    pub fn get(&self, k: &CqlString) -> Option<&CqlBytes> {
+        self.container.get(k)
+    }
+}
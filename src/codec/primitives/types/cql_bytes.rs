@@ -5,11 +5,15 @@ use bytes::BytesMut;
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CqlBytes {
     buf: Option<BytesMut>,
+    unset: bool,
 }
 
 impl CqlBytes {
     pub fn from(buf: BytesMut) -> CqlBytes {
-        CqlBytes { buf: Some(buf) }
+        CqlBytes {
+            buf: Some(buf),
+            unset: false,
+        }
     }
 
     pub fn as_option(self) -> Option<BytesMut> {
@@ -19,6 +23,7 @@ impl CqlBytes {
     pub fn len(&self) -> i32 {
         match &self.buf {
             &Some(ref buf) => buf.as_ref().len() as i32,
+            &None if self.unset => -2,
             &None => -1,
         }
     }
@@ -31,13 +36,29 @@ impl CqlBytes {
     }
 
     pub fn null_value() -> CqlBytes {
-        CqlBytes { buf: None }
+        CqlBytes {
+            buf: None,
+            unset: false,
+        }
+    }
+
+    /// A value which leaves the bound variable untouched. Only valid as query value
+    /// with protocol version 4 and later.
+    pub fn unset_value() -> CqlBytes {
+        CqlBytes {
+            buf: None,
+            unset: true,
+        }
+    }
+
+    pub fn is_unset(&self) -> bool {
+        self.unset
     }
 }
 
 impl<'a> CqlFrom<CqlBytes, Vec<u8>> for CqlBytes {
     unsafe fn unchecked_from(vec: Vec<u8>) -> CqlBytes {
-        CqlBytes { buf: Some(vec.into()) }
+        CqlBytes::from(vec.into())
     }
 
     fn max_len() -> usize {
@@ -47,7 +68,7 @@ impl<'a> CqlFrom<CqlBytes, Vec<u8>> for CqlBytes {
 
 impl<'a> CqlFrom<CqlBytes, BytesMut> for CqlBytes {
     unsafe fn unchecked_from(vec: BytesMut) -> CqlBytes {
-        CqlBytes { buf: Some(vec) }
+        CqlBytes::from(vec)
     }
 
     fn max_len() -> usize {
@@ -83,6 +104,19 @@ mod test {
         assert_eq!(res.unwrap().1, s);
     }
 
+    #[test]
+    fn unset_value() {
+        let s = CqlBytes::unset_value();
+        let mut buf = BytesMut::with_capacity(64);
+        encode::bytes(&s, &mut buf);
+        assert_eq!(&buf[..], &[0xff, 0xff, 0xff, 0xfe][..]);
+
+        let buf = Vec::from(&buf[..]).into();
+        let res = decode::bytes(buf).unwrap().1;
+        assert!(res.is_unset());
+        assert_eq!(res, s);
+    }
+
     #[test]
     fn as_option() {
         let s = CqlBytes::null_value();
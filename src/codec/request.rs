@@ -1,7 +1,8 @@
-use codec::header::{ProtocolVersion, OpCode, Header, Version};
+use codec::header::{ProtocolVersion, OpCode, Header, Version, TRACING_FLAG};
 use std::collections::HashMap;
+use std::time::Duration;
 
-use codec::primitives::{CqlConsistency, CqlFrom, CqlStringMap, CqlString, CqlBytes, CqlLongString};
+use codec::primitives::{CqlConsistency, CqlFrom, CqlStringList, CqlStringMap, CqlString, CqlBytes, CqlLongString};
 use codec::primitives::encode;
 use bytes::{BufMut, BytesMut};
 
@@ -19,6 +20,10 @@ mod errors {
                 display("The current body length {} exceeded the \
                 maximum allowed length for a body", len)
             }
+            UnsetValueNotSupported(v: ::codec::header::ProtocolVersion) {
+                description("Unset values are not supported by the protocol version")
+                display("Unset values require protocol version 4 or later, got {:?}", v)
+            }
         }
     }
 }
@@ -29,15 +34,19 @@ pub trait CqlEncode {
     fn encode(&self, v: ProtocolVersion, f: &mut BytesMut) -> Result<usize>;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Message {
     Options,
     Startup(StartupMessage),
     AuthResponse(AuthResponseMessage),
     Query(QueryMessage),
+    Prepare(PrepareMessage),
+    Execute(ExecuteMessage),
+    Batch(BatchMessage),
+    Register(RegisterMessage),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StartupMessage {
     pub cql_version: CqlString,
     pub compression: Option<CqlString>,
@@ -66,7 +75,7 @@ impl CqlEncode for StartupMessage {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AuthResponseMessage {
     pub auth_data: CqlBytes,
 }
@@ -79,19 +88,30 @@ impl CqlEncode for AuthResponseMessage {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum QueryValues {
     Positional(Vec<CqlBytes>),
     Named(HashMap<CqlString, CqlBytes>),
 }
 
+fn check_unset<'a, I>(version: ProtocolVersion, values: I) -> Result<()>
+where
+    I: IntoIterator<Item = &'a CqlBytes>,
+{
+    if version == ProtocolVersion::Version3 && values.into_iter().any(|v| v.is_unset()) {
+        return Err(ErrorKind::UnsetValueNotSupported(version).into());
+    }
+    Ok(())
+}
+
 impl CqlEncode for QueryValues {
-    fn encode(&self, _v: ProtocolVersion, buf: &mut BytesMut) -> Result<usize> {
+    fn encode(&self, version: ProtocolVersion, buf: &mut BytesMut) -> Result<usize> {
         use self::QueryValues::*;
         let len = buf.len();
 
         match self {
             &Positional(ref values) => {
+                check_unset(version, values)?;
                 // TODO: possible overflow return ERR then
                 encode::short(values.len() as u16, buf);
                 for value in values {
@@ -99,6 +119,7 @@ impl CqlEncode for QueryValues {
                 }
             }
             &Named(ref values) => {
+                check_unset(version, values.values())?;
                 encode::short(values.len() as u16, buf);
                 for (key, value) in values {
                     encode::string(key, buf);
@@ -111,9 +132,10 @@ impl CqlEncode for QueryValues {
     }
 }
 
-#[derive(Debug)]
-pub struct QueryMessage {
-    pub query: CqlLongString,
+/// The parameters shared by QUERY and EXECUTE requests, encoded right after the query string
+/// or the prepared statement id respectively.
+#[derive(Debug, Clone)]
+pub struct QueryParameters {
     pub values: Option<QueryValues>,
     pub consistency: CqlConsistency,
     pub skip_metadata: bool,
@@ -123,15 +145,16 @@ pub struct QueryMessage {
     pub timestamp: Option<i64>,
 }
 
-impl CqlEncode for QueryMessage {
+impl CqlEncode for QueryParameters {
     fn encode(&self, version: ProtocolVersion, buf: &mut BytesMut) -> Result<usize> {
         let l = buf.len();
-        encode::long_string(&self.query, buf);
         encode::consistency(&self.consistency, buf);
 
         buf.put_u8(self.compute_flags());
 
-        self.values.as_ref().map(|v| v.encode(version, buf));
+        if let Some(ref v) = self.values {
+            v.encode(version, buf)?;
+        }
         self.page_size.map(|v| encode::int(v, buf));
         self.paging_state.as_ref().map(|v| encode::bytes(v, buf));
         self.serial_consistency.as_ref().map(|v| {
@@ -143,7 +166,7 @@ impl CqlEncode for QueryMessage {
     }
 }
 
-impl QueryMessage {
+impl QueryParameters {
     pub fn compute_flags(&self) -> u8 {
         let mut flags = 0x00;
 
@@ -156,7 +179,6 @@ impl QueryMessage {
         self.page_size.as_ref().map(|_| flags |= 0x04);
         self.paging_state.as_ref().map(|_| flags |= 0x08);
         self.serial_consistency.as_ref().map(|_| flags |= 0x10);
-        self.page_size.as_ref().map(|_| flags |= 0x04);
         self.timestamp.as_ref().map(|_| flags |= 0x20);
 
         if let Some(QueryValues::Named(_)) = self.values {
@@ -167,10 +189,9 @@ impl QueryMessage {
     }
 }
 
-impl Default for QueryMessage {
+impl Default for QueryParameters {
     fn default() -> Self {
-        QueryMessage {
-            query: CqlLongString::try_from("").expect("an empty string to be valid"),
+        QueryParameters {
             values: None,
             consistency: CqlConsistency::One,
             skip_metadata: false,
@@ -182,6 +203,248 @@ impl Default for QueryMessage {
     }
 }
 
+/// Identifies the partition a request reads or writes, which allows to send it to a replica.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutingKey {
+    /// The keyspace of the partition, which determines how it is replicated.
+    pub keyspace: Option<String>,
+    /// The partition key, serialized like a value of its type.
+    pub key: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryMessage {
+    pub query: CqlLongString,
+    pub parameters: QueryParameters,
+    /// Ask the server to trace this request. The response will carry the tracing id.
+    pub tracing: bool,
+    /// The partition this query operates on, if known. It is never sent to the server.
+    pub routing_key: Option<RoutingKey>,
+    /// True if applying the query more than once has the same effect as applying it once,
+    /// which allows to retry it after timeouts. It is never sent to the server.
+    pub idempotent: bool,
+    /// The time to wait for the response, instead of the default of the connection.
+    /// It is never sent to the server.
+    pub timeout: Option<Duration>,
+}
+
+impl CqlEncode for QueryMessage {
+    fn encode(&self, version: ProtocolVersion, buf: &mut BytesMut) -> Result<usize> {
+        let l = buf.len();
+        encode::long_string(&self.query, buf);
+        self.parameters.encode(version, buf)?;
+        Ok(buf.len() - l)
+    }
+}
+
+impl Default for QueryMessage {
+    fn default() -> Self {
+        QueryMessage {
+            query: CqlLongString::try_from("").expect("an empty string to be valid"),
+            parameters: QueryParameters::default(),
+            tracing: false,
+            routing_key: None,
+            idempotent: false,
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PrepareMessage {
+    pub query: CqlLongString,
+    /// Ask the server to trace this request. The response will carry the tracing id.
+    pub tracing: bool,
+}
+
+impl CqlEncode for PrepareMessage {
+    fn encode(&self, _v: ProtocolVersion, buf: &mut BytesMut) -> Result<usize> {
+        let l = buf.len();
+        encode::long_string(&self.query, buf);
+        Ok(buf.len() - l)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExecuteMessage {
+    /// The id of the prepared statement, as returned in the `Prepared` result.
+    pub id: CqlBytes,
+    pub parameters: QueryParameters,
+    /// Ask the server to trace this request. The response will carry the tracing id.
+    pub tracing: bool,
+    /// The partition the statement operates on, if known. It is never sent to the server.
+    pub routing_key: Option<RoutingKey>,
+    /// True if applying the statement more than once has the same effect as applying it once,
+    /// which allows to retry it after timeouts. It is never sent to the server.
+    pub idempotent: bool,
+    /// The time to wait for the response, instead of the default of the connection.
+    /// It is never sent to the server.
+    pub timeout: Option<Duration>,
+}
+
+impl CqlEncode for ExecuteMessage {
+    fn encode(&self, version: ProtocolVersion, buf: &mut BytesMut) -> Result<usize> {
+        let l = buf.len();
+        encode::short_bytes(&self.id, buf);
+        self.parameters.encode(version, buf)?;
+        Ok(buf.len() - l)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchType {
+    Logged,
+    Unlogged,
+    Counter,
+}
+
+impl BatchType {
+    pub fn as_u8(&self) -> u8 {
+        match *self {
+            BatchType::Logged => 0,
+            BatchType::Unlogged => 1,
+            BatchType::Counter => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum BatchQueryKind {
+    Query(CqlLongString),
+    /// The id of a prepared statement, as returned in the `Prepared` result.
+    Prepared(CqlBytes),
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchQuery {
+    pub kind: BatchQueryKind,
+    pub values: Vec<CqlBytes>,
+}
+
+impl CqlEncode for BatchQuery {
+    fn encode(&self, version: ProtocolVersion, buf: &mut BytesMut) -> Result<usize> {
+        check_unset(version, &self.values)?;
+        let l = buf.len();
+        match self.kind {
+            BatchQueryKind::Query(ref query) => {
+                buf.put_u8(0);
+                encode::long_string(query, buf);
+            }
+            BatchQueryKind::Prepared(ref id) => {
+                buf.put_u8(1);
+                encode::short_bytes(id, buf);
+            }
+        }
+        encode::short(self.values.len() as u16, buf);
+        for value in &self.values {
+            encode::bytes(value, buf);
+        }
+        Ok(buf.len() - l)
+    }
+}
+
+/// Note that named values are not supported, as the respective flag is known to be broken
+/// in protocol versions 3 and 4.
+#[derive(Debug, Clone)]
+pub struct BatchMessage {
+    pub batch_type: BatchType,
+    pub queries: Vec<BatchQuery>,
+    pub consistency: CqlConsistency,
+    pub serial_consistency: Option<CqlConsistency>,
+    pub timestamp: Option<i64>,
+    /// Ask the server to trace this request. The response will carry the tracing id.
+    pub tracing: bool,
+    /// The partition the batch operates on, if all of its statements share it.
+    /// It is never sent to the server.
+    pub routing_key: Option<RoutingKey>,
+    /// True if all statements of the batch are idempotent. It is never sent to the server.
+    pub idempotent: bool,
+    /// The time to wait for the response, instead of the default of the connection.
+    /// It is never sent to the server.
+    pub timeout: Option<Duration>,
+}
+
+impl CqlEncode for BatchMessage {
+    fn encode(&self, version: ProtocolVersion, buf: &mut BytesMut) -> Result<usize> {
+        let l = buf.len();
+        buf.put_u8(self.batch_type.as_u8());
+        // TODO: possible overflow return ERR then
+        encode::short(self.queries.len() as u16, buf);
+        for query in &self.queries {
+            query.encode(version, buf)?;
+        }
+        encode::consistency(&self.consistency, buf);
+
+        buf.put_u8(self.compute_flags());
+
+        self.serial_consistency.as_ref().map(|v| {
+            encode::consistency(&v, buf)
+        });
+        self.timestamp.map(|v| encode::long(v, buf));
+
+        Ok(buf.len() - l)
+    }
+}
+
+impl BatchMessage {
+    pub fn compute_flags(&self) -> u8 {
+        let mut flags = 0x00;
+
+        self.serial_consistency.as_ref().map(|_| flags |= 0x10);
+        self.timestamp.as_ref().map(|_| flags |= 0x20);
+
+        flags
+    }
+}
+
+impl Default for BatchMessage {
+    fn default() -> Self {
+        BatchMessage {
+            batch_type: BatchType::Logged,
+            queries: Vec::new(),
+            consistency: CqlConsistency::One,
+            serial_consistency: None,
+            timestamp: None,
+            tracing: false,
+            routing_key: None,
+            idempotent: false,
+            timeout: None,
+        }
+    }
+}
+
+/// The kinds of events a client may register for, to have them pushed by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventType {
+    TopologyChange,
+    StatusChange,
+    SchemaChange,
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            EventType::TopologyChange => "TOPOLOGY_CHANGE",
+            EventType::StatusChange => "STATUS_CHANGE",
+            EventType::SchemaChange => "SCHEMA_CHANGE",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RegisterMessage {
+    pub events: Vec<EventType>,
+}
+
+impl CqlEncode for RegisterMessage {
+    fn encode(&self, _v: ProtocolVersion, buf: &mut BytesMut) -> Result<usize> {
+        let l = buf.len();
+        let events = CqlStringList::try_from_iter(self.events.iter().map(|e| e.as_str()))?;
+        encode::string_list(&events, buf);
+        Ok(buf.len() - l)
+    }
+}
+
 impl Message {
     fn opcode(&self) -> OpCode {
         use self::Message::*;
@@ -190,6 +453,79 @@ impl Message {
             &Startup(_) => OpCode::Startup,
             &AuthResponse(_) => OpCode::AuthResponse,
             &Query(_) => OpCode::Query,
+            &Prepare(_) => OpCode::Prepare,
+            &Execute(_) => OpCode::Execute,
+            &Batch(_) => OpCode::Batch,
+            &Register(_) => OpCode::Register,
+        }
+    }
+
+    /// Returns true if the request asks to be traced.
+    pub fn is_traced(&self) -> bool {
+        use self::Message::*;
+        match *self {
+            Query(ref msg) => msg.tracing,
+            Prepare(ref msg) => msg.tracing,
+            Execute(ref msg) => msg.tracing,
+            Batch(ref msg) => msg.tracing,
+            Options | Startup(_) | AuthResponse(_) | Register(_) => false,
+        }
+    }
+
+    /// The partition the request operates on, if it is known.
+    pub fn routing_key(&self) -> Option<&RoutingKey> {
+        use self::Message::*;
+        match *self {
+            Query(ref msg) => msg.routing_key.as_ref(),
+            Execute(ref msg) => msg.routing_key.as_ref(),
+            Batch(ref msg) => msg.routing_key.as_ref(),
+            Options | Startup(_) | AuthResponse(_) | Prepare(_) | Register(_) => None,
+        }
+    }
+
+    /// Returns true if the request may be sent again after it possibly was applied already.
+    /// Queries, statements and batches are only idempotent if marked as such.
+    pub fn is_idempotent(&self) -> bool {
+        use self::Message::*;
+        match *self {
+            Query(ref msg) => msg.idempotent,
+            Execute(ref msg) => msg.idempotent,
+            Batch(ref msg) => msg.idempotent,
+            Options | Prepare(_) => true,
+            Startup(_) | AuthResponse(_) | Register(_) => false,
+        }
+    }
+
+    /// The time to wait for the response to this request, if it differs from the default.
+    pub fn timeout(&self) -> Option<Duration> {
+        use self::Message::*;
+        match *self {
+            Query(ref msg) => msg.timeout,
+            Execute(ref msg) => msg.timeout,
+            Batch(ref msg) => msg.timeout,
+            Options | Startup(_) | AuthResponse(_) | Prepare(_) | Register(_) => None,
+        }
+    }
+
+    /// The consistency the request is executed at, if it has one.
+    pub fn consistency(&self) -> Option<CqlConsistency> {
+        use self::Message::*;
+        match *self {
+            Query(ref msg) => Some(msg.parameters.consistency),
+            Execute(ref msg) => Some(msg.parameters.consistency),
+            Batch(ref msg) => Some(msg.consistency),
+            Options | Startup(_) | AuthResponse(_) | Prepare(_) | Register(_) => None,
+        }
+    }
+
+    /// Change the consistency of requests which have one, and do nothing otherwise.
+    pub fn set_consistency(&mut self, consistency: CqlConsistency) {
+        use self::Message::*;
+        match *self {
+            Query(ref mut msg) => msg.parameters.consistency = consistency,
+            Execute(ref mut msg) => msg.parameters.consistency = consistency,
+            Batch(ref mut msg) => msg.consistency = consistency,
+            Options | Startup(_) | AuthResponse(_) | Prepare(_) | Register(_) => {}
         }
     }
 }
@@ -201,6 +537,10 @@ impl CqlEncode for Message {
             Message::Startup(ref msg) => msg.encode(v, buf),
             Message::AuthResponse(ref msg) => msg.encode(v, buf),
             Message::Query(ref msg) => msg.encode(v, buf),
+            Message::Prepare(ref msg) => msg.encode(v, buf),
+            Message::Execute(ref msg) => msg.encode(v, buf),
+            Message::Batch(ref msg) => msg.encode(v, buf),
+            Message::Register(ref msg) => msg.encode(v, buf),
         }
     }
 }
@@ -220,6 +560,11 @@ pub fn cql_encode(
     }
     let len = len as u32;
 
+    let flags = if to_encode.is_traced() {
+        flags | TRACING_FLAG
+    } else {
+        flags
+    };
     let header = Header {
         version: Version::request(version),
         flags: flags,
@@ -308,13 +653,19 @@ mod test {
 
         let o = Message::Query(QueryMessage {
             query: CqlLongString::try_from("select * from system.local where key = 'local'").unwrap(),
-            values: None,
-            consistency: CqlConsistency::One,
-            skip_metadata: false,
-            page_size: Some(5000),
-            paging_state: None,
-            serial_consistency: None,
-            timestamp: Some(1486294317376770),
+            parameters: QueryParameters {
+                values: None,
+                consistency: CqlConsistency::One,
+                skip_metadata: false,
+                page_size: Some(5000),
+                paging_state: None,
+                serial_consistency: None,
+                timestamp: Some(1486294317376770),
+            },
+            tracing: false,
+            routing_key: None,
+            idempotent: false,
+            timeout: None,
         });
 
         cql_encode(Version3, flags, stream_id, o, &mut buf).unwrap();
@@ -323,9 +674,132 @@ mod test {
         assert_eq!(&buf[..], &expected_bytes[..]);
     }
 
+    #[test]
+    fn from_prepare_req() {
+        let mut buf = BytesMut::with_capacity(64);
+        let o = Message::Prepare(PrepareMessage {
+            query: CqlLongString::try_from("select key from system.local where key = ?").unwrap(),
+            tracing: false,
+        });
+
+        cql_encode(Version3, 0, 3, o, &mut buf).unwrap();
+
+        let expected_bytes = include_bytes!("../../tests/fixtures/v3/requests/prepare.msg");
+        assert_eq!(&buf[..], &expected_bytes[..]);
+    }
+
+    #[test]
+    fn from_execute_req() {
+        let mut buf = BytesMut::with_capacity(64);
+        let o = Message::Execute(ExecuteMessage {
+            id: cql_bytes!(0xca, 0xfe, 0xba, 0xbe),
+            parameters: QueryParameters {
+                values: Some(QueryValues::Positional(vec![cql_bytes!(b'l', b'o', b'c', b'a', b'l')])),
+                consistency: CqlConsistency::Quorum,
+                page_size: Some(100),
+                ..Default::default()
+            },
+            tracing: false,
+            routing_key: Some(RoutingKey {
+                keyspace: Some(String::from("system")),
+                key: b"local".to_vec(),
+            }),
+            idempotent: true,
+            timeout: Some(Duration::from_secs(1)),
+        });
+        assert_eq!(o.routing_key().map(|k| &k.key[..]), Some(&b"local"[..]));
+        assert!(o.is_idempotent());
+        assert_eq!(o.timeout(), Some(Duration::from_secs(1)));
+        assert_eq!(o.consistency(), Some(CqlConsistency::Quorum));
+
+        cql_encode(Version3, 0, 4, o, &mut buf).unwrap();
+
+        let expected_bytes = include_bytes!("../../tests/fixtures/v3/requests/execute.msg");
+        assert_eq!(&buf[..], &expected_bytes[..]);
+    }
+
+    #[test]
+    fn from_batch_req() {
+        let mut buf = BytesMut::with_capacity(128);
+        let o = Message::Batch(BatchMessage {
+            batch_type: BatchType::Unlogged,
+            queries: vec![
+                BatchQuery {
+                    kind: BatchQueryKind::Query(
+                        CqlLongString::try_from("insert into ks.t (k, v) values (?, ?)").unwrap()
+                    ),
+                    values: vec![cql_bytes!(0, 0, 0, 1), cql_bytes!(b'a')],
+                },
+                BatchQuery {
+                    kind: BatchQueryKind::Prepared(cql_bytes!(0xca, 0xfe, 0xba, 0xbe)),
+                    values: vec![cql_bytes!(0, 0, 0, 2)],
+                },
+            ],
+            consistency: CqlConsistency::Quorum,
+            serial_consistency: Some(CqlConsistency::LocalSerial),
+            timestamp: Some(1486294317376770),
+            tracing: false,
+            routing_key: None,
+            idempotent: false,
+            timeout: None,
+        });
+
+        cql_encode(Version3, 0, 5, o, &mut buf).unwrap();
+
+        let expected_bytes = include_bytes!("../../tests/fixtures/v3/requests/batch.msg");
+        assert_eq!(&buf[..], &expected_bytes[..]);
+    }
+
+    #[test]
+    fn from_register_req() {
+        let mut buf = BytesMut::with_capacity(64);
+        let o = Message::Register(RegisterMessage {
+            events: vec![
+                EventType::TopologyChange,
+                EventType::StatusChange,
+                EventType::SchemaChange,
+            ],
+        });
+
+        cql_encode(Version3, 0, 6, o, &mut buf).unwrap();
+
+        let expected_bytes = include_bytes!("../../tests/fixtures/v3/requests/register.msg");
+        assert_eq!(&buf[..], &expected_bytes[..]);
+    }
+
+    #[test]
+    fn traced_request_sets_header_flag() {
+        let mut buf = BytesMut::with_capacity(64);
+        let o = Message::Prepare(PrepareMessage {
+            query: CqlLongString::try_from("select key from system.local where key = ?").unwrap(),
+            tracing: true,
+        });
+        assert!(o.is_traced());
+        assert!(!Message::Options.is_traced());
+
+        cql_encode(Version3, 0, 3, o, &mut buf).unwrap();
+
+        let h = Header::try_from(&buf[..]).unwrap();
+        assert!(h.is_traced());
+        let expected_bytes = include_bytes!("../../tests/fixtures/v3/requests/prepare.msg");
+        assert_eq!(&buf[Header::encoded_len()..], &expected_bytes[Header::encoded_len()..]);
+    }
+
+    #[test]
+    fn batch_flags() {
+        let mut o = BatchMessage::default();
+        assert_eq!(o.compute_flags(), 0x00u8);
+
+        o.serial_consistency = Some(CqlConsistency::Serial);
+        assert_eq!(o.compute_flags(), 0x10u8);
+
+        o.timestamp = Some(1);
+        assert_eq!(o.compute_flags(), 0x30u8);
+    }
+
     #[test]
     fn query_flags() {
-        let mut o = QueryMessage::default();
+        let mut o = QueryParameters::default();
         assert_eq!(o.compute_flags(), 0x00u8);
 
         o.values = Some(QueryValues::Positional(Vec::new()));
@@ -378,6 +852,17 @@ mod test {
         assert_eq!(expected, buf);
     }
 
+    #[test]
+    fn encode_query_values_unset() {
+        let values = QueryValues::Positional(vec![CqlBytes::unset_value()]);
+
+        let mut buf = BytesMut::with_capacity(64);
+        assert!(values.encode(Version3, &mut buf).is_err());
+
+        values.encode(Version4, &mut buf).unwrap();
+        assert_eq!(vec![0x00, 0x01, 0xff, 0xff, 0xff, 0xfe], buf);
+    }
+
     #[test]
     fn encode_query_values_named() {
         let values = {
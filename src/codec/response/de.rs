@@ -0,0 +1,496 @@
+use super::*;
+use codec::primitives::decode;
+use codec::primitives::datatypes::{deserialize_bytesmut, CqlSerializable, Date, Decimal, FromCql, Time, Uuid, Varint};
+use serde::de::{self, Deserialize, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::de::value::SeqDeserializer;
+use std::fmt::Display;
+use std::net::IpAddr;
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        msg.to_string().into()
+    }
+}
+
+impl Row {
+    /// Deserialize the row into `T` with serde.
+    ///
+    /// Structs and maps are filled by column name, sequences and tuples in column order.
+    pub fn deserialize<'de, T: Deserialize<'de>>(&self, meta: &RowsMetadata) -> Result<T> {
+        T::deserialize(RowDeserializer::new(self, meta))
+    }
+}
+
+/// A serde `Deserializer` over the columns of a row.
+pub struct RowDeserializer<'a> {
+    row: &'a Row,
+    meta: &'a RowsMetadata,
+}
+
+impl<'a> RowDeserializer<'a> {
+    pub fn new(row: &'a Row, meta: &'a RowsMetadata) -> Self {
+        RowDeserializer {
+            row: row,
+            meta: meta,
+        }
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for RowDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let columns = self.row
+            .col_iter(self.meta)
+            .map(|(spec, value)| (spec.name(), spec.coltype(), value));
+        visitor.visit_map(Fields::new(columns))
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let cells = self.row.col_iter(self.meta).map(|(spec, value)| (spec.coltype(), value));
+        visitor.visit_seq(Cells::new(cells))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf option unit
+        unit_struct map struct enum identifier ignored_any
+    }
+}
+
+/// A serde `Deserializer` over a value of type `column_type`, which may be null.
+///
+/// Timestamps are read as milliseconds since the epoch, and uuids, decimals, varints, inets,
+/// dates and times as strings. Null collections are read as empty ones.
+pub struct CellDeserializer<'a> {
+    column_type: &'a ColumnType,
+    value: Option<BytesMut>,
+}
+
+impl<'a> CellDeserializer<'a> {
+    pub fn new(column_type: &'a ColumnType, value: Option<BytesMut>) -> Self {
+        CellDeserializer {
+            column_type: column_type,
+            value: value,
+        }
+    }
+
+    fn is_text(&self) -> bool {
+        match *self.column_type {
+            ColumnType::Varchar | ColumnType::Ascii => true,
+            _ => false,
+        }
+    }
+}
+
+/// The elements of a list or set, or the keys and values of a map if `per_element` is 2.
+fn elements(data: BytesMut, per_element: usize) -> Result<Vec<Option<BytesMut>>> {
+    let (mut data, n) = decode::int(data)?;
+    let n = n.max(0) as usize * per_element;
+    let mut elements = Vec::new();
+    for _ in 0..n {
+        let (rest, element) = deserialize_bytesmut(data)?;
+        elements.push(element);
+        data = rest;
+    }
+    Ok(elements)
+}
+
+/// The `n` fields of a tuple or user defined type, where missing trailing fields are null.
+fn fields(data: BytesMut, n: usize) -> Result<Vec<Option<BytesMut>>> {
+    let mut fields = Vec::new();
+    let mut data = data;
+    while !data.is_empty() && fields.len() < n {
+        let (rest, field) = deserialize_bytesmut(data)?;
+        fields.push(field);
+        data = rest;
+    }
+    fields.resize(n, None);
+    Ok(fields)
+}
+
+impl<'de, 'a> de::Deserializer<'de> for CellDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let data = match self.value {
+            Some(data) => data,
+            None => return visitor.visit_none(),
+        };
+        let column_type = self.column_type;
+        match *column_type {
+            ColumnType::Bigint | ColumnType::Counter => visitor.visit_i64(i64::from_cql(column_type, data)?),
+            ColumnType::Timestamp => visitor.visit_i64(i64::from_cql(&ColumnType::Bigint, data)?),
+            ColumnType::Int => visitor.visit_i32(i32::from_cql(column_type, data)?),
+            ColumnType::Smallint => visitor.visit_i16(i16::from_cql(column_type, data)?),
+            ColumnType::Tinyint => visitor.visit_i8(i8::from_cql(column_type, data)?),
+            ColumnType::Boolean => visitor.visit_bool(bool::from_cql(column_type, data)?),
+            ColumnType::Double => visitor.visit_f64(f64::from_cql(column_type, data)?),
+            ColumnType::Float => visitor.visit_f32(f32::from_cql(column_type, data)?),
+            ColumnType::Varchar | ColumnType::Ascii => visitor.visit_string(String::from_cql(column_type, data)?),
+            ColumnType::Inet => visitor.visit_string(IpAddr::from_cql(column_type, data)?.to_string()),
+            ColumnType::Uuid | ColumnType::Timeuuid => visitor.visit_string(format!("{:?}", Uuid::deserialize(data)?)),
+            ColumnType::Decimal => visitor.visit_string(format!("{:?}", Decimal::deserialize(data)?)),
+            ColumnType::Varint => visitor.visit_string(format!("{:?}", Varint::deserialize(data)?)),
+            ColumnType::Date => visitor.visit_string(format!("{:?}", Date::deserialize(data)?)),
+            ColumnType::Time => visitor.visit_string(format!("{:?}", Time::deserialize(data)?)),
+            ColumnType::Blob | ColumnType::Custom(_) => visitor.visit_byte_buf(data.to_vec()),
+            ColumnType::List(ref t) |
+            ColumnType::Set(ref t) => {
+                let elements = elements(data, 1)?;
+                visitor.visit_seq(Cells::new(elements.into_iter().map(|e| (&**t, e))))
+            }
+            ColumnType::Map(ref k, ref v) => {
+                let entries = elements(data, 2)?;
+                visitor.visit_map(Entries::new(k, v, entries))
+            }
+            ColumnType::Tuple(ref d) => {
+                let fields = fields(data, d.0.len())?;
+                visitor.visit_seq(Cells::new(d.0.iter().zip(fields)))
+            }
+            ColumnType::Udt(ref d) => {
+                let fields = fields(data, d.fields.len())?;
+                let fields = d.fields.iter().zip(fields).map(|(f, v)| (f.0.as_ref(), &f.1, v));
+                visitor.visit_map(Fields::new(fields))
+            }
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Some(_) => visitor.visit_some(self),
+            None => visitor.visit_none(),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Some(_) => self.deserialize_any(visitor),
+            None => visitor.visit_unit(),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match (self.column_type, self.value) {
+            (_, None) => visitor.visit_seq(Cells::new(::std::iter::empty())),
+            // Vectors of bytes are sequences to serde.
+            (&ColumnType::Blob, Some(data)) => SeqDeserializer::new(data.into_iter()).deserialize_any(visitor),
+            (column_type, value) => CellDeserializer::new(column_type, value).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Some(_) => self.deserialize_any(visitor),
+            None => visitor.visit_map(Fields::new(::std::iter::empty())),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Unit variants are read from text by their name.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        if !self.is_text() {
+            return self.deserialize_any(visitor);
+        }
+        match self.value {
+            Some(data) => {
+                let variant = String::from_cql(self.column_type, data)?;
+                visitor.visit_enum(variant.into_deserializer())
+            }
+            None => visitor.visit_none(),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf
+        unit_struct tuple_struct struct identifier ignored_any
+    }
+}
+
+/// The values of a row, list, set or tuple, with their types.
+struct Cells<I> {
+    iter: I,
+}
+
+impl<I> Cells<I> {
+    fn new(iter: I) -> Self {
+        Cells { iter: iter }
+    }
+}
+
+impl<'de, 'a, I> SeqAccess<'de> for Cells<I>
+where
+    I: Iterator<Item = (&'a ColumnType, Option<BytesMut>)>,
+{
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        match self.iter.next() {
+            Some((column_type, value)) => seed.deserialize(CellDeserializer::new(column_type, value)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The named values of a row or user defined type, with their types.
+struct Fields<'a, I> {
+    iter: I,
+    value: Option<(&'a ColumnType, Option<BytesMut>)>,
+}
+
+impl<'a, I> Fields<'a, I> {
+    fn new(iter: I) -> Self {
+        Fields {
+            iter: iter,
+            value: None,
+        }
+    }
+}
+
+impl<'de, 'a, I> MapAccess<'de> for Fields<'a, I>
+where
+    I: Iterator<Item = (&'a str, &'a ColumnType, Option<BytesMut>)>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.iter.next() {
+            Some((name, column_type, value)) => {
+                self.value = Some((column_type, value));
+                seed.deserialize(name.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let (column_type, value) = self.value.take().expect("a value to follow its key");
+        seed.deserialize(CellDeserializer::new(column_type, value))
+    }
+}
+
+/// The keys and values of a map, alternating.
+struct Entries<'a> {
+    key_type: &'a ColumnType,
+    value_type: &'a ColumnType,
+    iter: ::std::vec::IntoIter<Option<BytesMut>>,
+}
+
+impl<'a> Entries<'a> {
+    fn new(key_type: &'a ColumnType, value_type: &'a ColumnType, entries: Vec<Option<BytesMut>>) -> Self {
+        Entries {
+            key_type: key_type,
+            value_type: value_type,
+            iter: entries.into_iter(),
+        }
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for Entries<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.iter.next() {
+            Some(key) => seed.deserialize(CellDeserializer::new(self.key_type, key)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let value = self.iter.next().expect("a value to follow its key");
+        seed.deserialize(CellDeserializer::new(self.value_type, value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codec::primitives::{CqlBytes, CqlFrom, CqlString};
+    use codec::primitives::datatypes::ToCql;
+    use codec::primitives::encode;
+    use std::collections::HashMap;
+
+    fn spec(name: &str, column_type: ColumnType) -> ColumnSpec {
+        ColumnSpec::WithoutGlobalSpec {
+            table_spec: TableSpec::new("ks", "users"),
+            name: cql_string!(name),
+            column_type: column_type,
+        }
+    }
+
+    fn row(meta: &RowsMetadata, values: &[CqlBytes]) -> Row {
+        let mut buf = BytesMut::with_capacity(128);
+        for value in values {
+            encode::bytes(value, &mut buf);
+        }
+        Row::decode(buf, meta).unwrap().1.expect("a complete row")
+    }
+
+    fn address_type() -> ColumnType {
+        ColumnType::Udt(UdtDefinition {
+            keyspace: cql_string!("ks"),
+            name: cql_string!("address"),
+            fields: vec![
+                UdtField(cql_string!("street"), ColumnType::Varchar),
+                UdtField(cql_string!("zip"), ColumnType::Int),
+            ],
+        })
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Address {
+        street: String,
+        zip: Option<i32>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Role {
+        Admin,
+        Guest,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct User {
+        id: i64,
+        name: Option<String>,
+        role: Role,
+        tags: Vec<String>,
+        scores: HashMap<String, f64>,
+        address: Address,
+        point: (i32, bool),
+        avatar: Vec<u8>,
+        created: i64,
+    }
+
+    fn user_rows() -> RowsMetadata {
+        RowsMetadata {
+            column_spec: vec![
+                spec("id", ColumnType::Bigint),
+                spec("name", ColumnType::Varchar),
+                spec("role", ColumnType::Ascii),
+                spec("tags", ColumnType::List(Box::new(ColumnType::Varchar))),
+                spec(
+                    "scores",
+                    ColumnType::Map(Box::new(ColumnType::Varchar), Box::new(ColumnType::Double)),
+                ),
+                spec("address", address_type()),
+                spec(
+                    "point",
+                    ColumnType::Tuple(TupleDefinition(vec![ColumnType::Int, ColumnType::Boolean])),
+                ),
+                spec("avatar", ColumnType::Blob),
+                spec("created", ColumnType::Timestamp),
+            ],
+            columns_count: 9,
+            rows_count: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn struct_from_row() {
+        let meta = user_rows();
+        let mut scores = HashMap::new();
+        scores.insert("a", 1.5f64);
+        let row = row(
+            &meta,
+            &[
+                1i64.to_cql(),
+                CqlBytes::null_value(),
+                "Guest".to_cql(),
+                CqlBytes::null_value(),
+                scores.to_cql(),
+                ("main", 1i32).to_cql(),
+                (2i32, true).to_cql(),
+                vec![1u8, 2].to_cql(),
+                1000i64.to_cql(),
+            ],
+        );
+        let user: User = row.deserialize(&meta).unwrap();
+        assert_eq!(
+            user,
+            User {
+                id: 1,
+                name: None,
+                role: Role::Guest,
+                tags: Vec::new(),
+                scores: vec![(String::from("a"), 1.5)].into_iter().collect(),
+                address: Address {
+                    street: String::from("main"),
+                    zip: Some(1),
+                },
+                point: (2, true),
+                avatar: vec![1, 2],
+                created: 1000,
+            }
+        );
+    }
+
+    #[test]
+    fn tuple_from_row() {
+        let meta = RowsMetadata {
+            column_spec: vec![
+                spec("id", ColumnType::Smallint),
+                spec("uuid", ColumnType::Uuid),
+                spec("day", ColumnType::Date),
+            ],
+            columns_count: 3,
+            rows_count: 1,
+            ..Default::default()
+        };
+        let row = row(&meta, &[1i16.to_cql(), vec![0u8; 16].to_cql(), Date::new(1 << 31).to_cql()]);
+        let (id, uuid, day): (i16, String, String) = row.deserialize(&meta).unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(uuid, "00000000-0000-0000-0000-000000000000");
+        assert_eq!(day, "1970-01-01");
+    }
+
+    #[test]
+    fn missing_and_mismatched_columns() {
+        let meta = RowsMetadata {
+            column_spec: vec![spec("id", ColumnType::Varchar)],
+            columns_count: 1,
+            rows_count: 1,
+            ..Default::default()
+        };
+        let row = row(&meta, &["a".to_cql()]);
+        assert!(row.deserialize::<Address>(&meta).is_err());
+        assert!(row.deserialize::<(i32,)>(&meta).is_err());
+        assert_eq!(row.deserialize::<(String,)>(&meta).unwrap().0, "a");
+    }
+
+    #[test]
+    fn elements_with_a_count_larger_than_the_data() {
+        let data = BytesMut::from(&[0x7f, 0xff, 0xff, 0xff, 0, 0, 0, 1, 0][..]);
+        assert!(elements(data.clone(), 1).is_err());
+        assert!(elements(data, 2).is_err());
+    }
+}
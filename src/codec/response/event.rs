@@ -0,0 +1,260 @@
+use super::*;
+
+use codec::primitives::{CqlString, CqlStringList};
+use codec::header::ProtocolVersion;
+use codec::primitives::decode;
+use bytes::BytesMut;
+use std::net::SocketAddr;
+
+/// A message pushed by the server on stream -1, for all event types the connection
+/// registered for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventMessage {
+    TopologyChange(TopologyChangeEvent),
+    StatusChange(StatusChangeEvent),
+    SchemaChange(SchemaChangeEvent),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopologyChangeType {
+    NewNode,
+    RemovedNode,
+    MovedNode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopologyChangeEvent {
+    pub change_type: TopologyChangeType,
+    pub node: SocketAddr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusChangeType {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusChangeEvent {
+    pub change_type: StatusChangeType,
+    pub node: SocketAddr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaChangeType {
+    Created,
+    Updated,
+    Dropped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaChangeTarget {
+    Keyspace(CqlString),
+    Table { keyspace: CqlString, name: CqlString },
+    Type { keyspace: CqlString, name: CqlString },
+    /// Only sent from protocol version 4 onwards.
+    Function {
+        keyspace: CqlString,
+        name: CqlString,
+        arg_types: CqlStringList,
+    },
+    /// Only sent from protocol version 4 onwards.
+    Aggregate {
+        keyspace: CqlString,
+        name: CqlString,
+        arg_types: CqlStringList,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaChangeEvent {
+    pub change_type: SchemaChangeType,
+    pub target: SchemaChangeTarget,
+}
+
+fn unknown<T>(what: &str, value: &CqlString) -> Result<T> {
+    Err(
+        ErrorKind::ParserError(format!("Unknown {}: '{}'", what, value)).into(),
+    )
+}
+
+impl EventMessage {
+    fn decode_topology_change(buf: BytesMut) -> Result<EventMessage> {
+        let (buf, change_type) = decode::string(buf)?;
+        let (_, node) = decode::inet(buf)?;
+        let change_type = match change_type.as_ref() {
+            "NEW_NODE" => TopologyChangeType::NewNode,
+            "REMOVED_NODE" => TopologyChangeType::RemovedNode,
+            "MOVED_NODE" => TopologyChangeType::MovedNode,
+            _ => return unknown("topology change", &change_type),
+        };
+        Ok(EventMessage::TopologyChange(TopologyChangeEvent {
+            change_type: change_type,
+            node: node,
+        }))
+    }
+
+    fn decode_status_change(buf: BytesMut) -> Result<EventMessage> {
+        let (buf, change_type) = decode::string(buf)?;
+        let (_, node) = decode::inet(buf)?;
+        let change_type = match change_type.as_ref() {
+            "UP" => StatusChangeType::Up,
+            "DOWN" => StatusChangeType::Down,
+            _ => return unknown("status change", &change_type),
+        };
+        Ok(EventMessage::StatusChange(StatusChangeEvent {
+            change_type: change_type,
+            node: node,
+        }))
+    }
+
+    fn decode_schema_change(buf: BytesMut) -> Result<EventMessage> {
+        let (buf, change_type) = decode::string(buf)?;
+        let (buf, target) = decode::string(buf)?;
+        let change_type = match change_type.as_ref() {
+            "CREATED" => SchemaChangeType::Created,
+            "UPDATED" => SchemaChangeType::Updated,
+            "DROPPED" => SchemaChangeType::Dropped,
+            _ => return unknown("schema change", &change_type),
+        };
+        let (buf, keyspace) = decode::string(buf)?;
+        let target = match target.as_ref() {
+            "KEYSPACE" => SchemaChangeTarget::Keyspace(keyspace),
+            "TABLE" => {
+                let (_, name) = decode::string(buf)?;
+                SchemaChangeTarget::Table {
+                    keyspace: keyspace,
+                    name: name,
+                }
+            }
+            "TYPE" => {
+                let (_, name) = decode::string(buf)?;
+                SchemaChangeTarget::Type {
+                    keyspace: keyspace,
+                    name: name,
+                }
+            }
+            "FUNCTION" => {
+                let (buf, name) = decode::string(buf)?;
+                let (_, arg_types) = decode::string_list(buf)?;
+                SchemaChangeTarget::Function {
+                    keyspace: keyspace,
+                    name: name,
+                    arg_types: arg_types,
+                }
+            }
+            "AGGREGATE" => {
+                let (buf, name) = decode::string(buf)?;
+                let (_, arg_types) = decode::string_list(buf)?;
+                SchemaChangeTarget::Aggregate {
+                    keyspace: keyspace,
+                    name: name,
+                    arg_types: arg_types,
+                }
+            }
+            _ => return unknown("schema change target", &target),
+        };
+        Ok(EventMessage::SchemaChange(SchemaChangeEvent {
+            change_type: change_type,
+            target: target,
+        }))
+    }
+}
+
+impl CqlDecode<EventMessage> for EventMessage {
+    fn decode(_v: ProtocolVersion, buf: BytesMut) -> Result<EventMessage> {
+        let (buf, event_type) = decode::string(buf)?;
+        match event_type.as_ref() {
+            "TOPOLOGY_CHANGE" => Self::decode_topology_change(buf),
+            "STATUS_CHANGE" => Self::decode_status_change(buf),
+            "SCHEMA_CHANGE" => Self::decode_schema_change(buf),
+            _ => unknown("event type", &event_type),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use codec::header::Header;
+    use codec::primitives::CqlFrom;
+    use codec::header::ProtocolVersion::*;
+    use super::*;
+
+    fn skip_header(b: &[u8]) -> &[u8] {
+        &b[Header::encoded_len()..]
+    }
+
+    #[test]
+    fn decode_event_topology_change() {
+        let msg = include_bytes!("../../../tests/fixtures/v3/responses/event_topology_change.msg");
+        let buf = Vec::from(skip_header(&msg[..])).into();
+        let res = EventMessage::decode(Version3, buf).unwrap();
+        assert_eq!(
+            res,
+            EventMessage::TopologyChange(TopologyChangeEvent {
+                change_type: TopologyChangeType::NewNode,
+                node: "127.0.0.2:9042".parse().unwrap(),
+            })
+        );
+    }
+
+    #[test]
+    fn decode_event_status_change() {
+        let msg = include_bytes!("../../../tests/fixtures/v3/responses/event_status_change.msg");
+        let buf = Vec::from(skip_header(&msg[..])).into();
+        let res = EventMessage::decode(Version3, buf).unwrap();
+        assert_eq!(
+            res,
+            EventMessage::StatusChange(StatusChangeEvent {
+                change_type: StatusChangeType::Down,
+                node: "127.0.0.2:9042".parse().unwrap(),
+            })
+        );
+    }
+
+    #[test]
+    fn decode_event_schema_change() {
+        let msg = include_bytes!("../../../tests/fixtures/v3/responses/event_schema_change.msg");
+        let buf = Vec::from(skip_header(&msg[..]));
+
+        let res = EventMessage::decode(Version3, Vec::from(&buf[..20]).into());
+        assert!(res.is_err());
+
+        let res = EventMessage::decode(Version3, buf.into()).unwrap();
+        assert_eq!(
+            res,
+            EventMessage::SchemaChange(SchemaChangeEvent {
+                change_type: SchemaChangeType::Created,
+                target: SchemaChangeTarget::Table {
+                    keyspace: cql_string!("ks"),
+                    name: cql_string!("t"),
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn decode_event_schema_change_function() {
+        let msg = include_bytes!("../../../tests/fixtures/v4/responses/event_schema_change_function.msg");
+        let buf = Vec::from(skip_header(&msg[..])).into();
+        let res = EventMessage::decode(Version4, buf).unwrap();
+        assert_eq!(
+            res,
+            EventMessage::SchemaChange(SchemaChangeEvent {
+                change_type: SchemaChangeType::Dropped,
+                target: SchemaChangeTarget::Function {
+                    keyspace: cql_string!("ks"),
+                    name: cql_string!("f"),
+                    arg_types: CqlStringList::try_from_iter(vec!["int", "text"]).unwrap(),
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn decode_event_unknown_type() {
+        let mut buf = BytesMut::with_capacity(16);
+        ::codec::primitives::encode::string(&cql_string!("FOO"), &mut buf);
+        assert!(EventMessage::decode(Version3, buf).is_err());
+    }
+}
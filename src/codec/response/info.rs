@@ -0,0 +1,124 @@
+use super::*;
+
+use codec::primitives::{CqlBytesMap, CqlStringList};
+use codec::primitives::datatypes::{CqlSerializable, Uuid};
+use codec::header::{Header, ProtocolVersion};
+use codec::primitives::decode;
+use bytes::BytesMut;
+
+/// Information a response frame may carry in front of the actual message body, as indicated
+/// by the flags of its header.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ResponseInfo {
+    /// The id of the trace recorded for the request, if it asked to be traced.
+    pub tracing_id: Option<Uuid>,
+    /// Warnings generated by the server while processing the request.
+    pub warnings: Option<CqlStringList>,
+    /// A payload generated by a custom query handler on the server.
+    pub custom_payload: Option<CqlBytesMap>,
+}
+
+impl ResponseInfo {
+    /// Decode all information indicated by the header flags, and return the remaining
+    /// message body.
+    pub fn decode(header: &Header, buf: BytesMut) -> Result<(BytesMut, ResponseInfo)> {
+        let mut info = ResponseInfo::default();
+        let buf = if header.is_traced() {
+            let mut buf = buf;
+            if buf.len() < 16 {
+                return Err(
+                    ErrorKind::Incomplete(format!("Need 16 bytes for the tracing id")).into(),
+                );
+            }
+            info.tracing_id = Some(Uuid::deserialize(buf.split_to(16))?);
+            buf
+        } else {
+            buf
+        };
+        if header.version.version == ProtocolVersion::Version3 {
+            return Ok((buf, info));
+        }
+
+        let buf = if header.has_warnings() {
+            let (buf, warnings) = decode::string_list(buf)?;
+            info.warnings = Some(warnings);
+            buf
+        } else {
+            buf
+        };
+        let buf = if header.has_custom_payload() {
+            let (buf, payload) = decode::bytes_map(buf)?;
+            info.custom_payload = Some(payload);
+            buf
+        } else {
+            buf
+        };
+        Ok((buf, info))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use codec::header::{Header, Version, OpCode};
+    use codec::primitives::{CqlFrom, CqlString, CqlBytes};
+    use codec::primitives::encode;
+    use super::*;
+
+    fn header(version: Version, flags: u8) -> Header {
+        Header {
+            version: version,
+            flags: flags,
+            stream_id: 0,
+            op_code: OpCode::Result,
+            length: 0,
+        }
+    }
+
+    #[test]
+    fn decode_warnings_and_custom_payload() {
+        let mut buf = BytesMut::with_capacity(64);
+        encode::string_list(
+            &CqlStringList::try_from_iter(vec!["careful"]).unwrap(),
+            &mut buf,
+        );
+        encode::short(1, &mut buf);
+        encode::string(&cql_string!("k"), &mut buf);
+        encode::bytes(&cql_bytes!(1), &mut buf);
+        buf.extend(&[0x00, 0x00, 0x00, 0x01]);
+
+        let (buf, info) = ResponseInfo::decode(&header(Version::v4_response(), 0x0c), buf).unwrap();
+        assert_eq!(&buf[..], &[0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(
+            info.warnings.unwrap().iter().next(),
+            Some(&cql_string!("careful"))
+        );
+        assert_eq!(
+            info.custom_payload.unwrap().get(&cql_string!("k")),
+            Some(&cql_bytes!(1))
+        );
+    }
+
+    #[test]
+    fn decode_tracing_id() {
+        let id = [0u8, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        for version in vec![Version::v3_response(), Version::v4_response()] {
+            let mut buf = BytesMut::with_capacity(20);
+            buf.extend(&id[..]);
+            buf.extend(&[0x00, 0x00, 0x00, 0x01]);
+            let (buf, info) = ResponseInfo::decode(&header(version, 0x02), buf).unwrap();
+            assert_eq!(&buf[..], &[0x00, 0x00, 0x00, 0x01]);
+            assert_eq!(info.tracing_id, Some(Uuid::new(id)));
+        }
+
+        let buf: BytesMut = vec![0x00, 0x01].into();
+        assert!(ResponseInfo::decode(&header(Version::v3_response(), 0x02), buf).is_err());
+    }
+
+    #[test]
+    fn decode_ignores_flags_in_version3() {
+        let buf: BytesMut = vec![0x00, 0x00, 0x00, 0x01].into();
+        let (buf, info) = ResponseInfo::decode(&header(Version::v3_response(), 0x0c), buf).unwrap();
+        assert_eq!(buf.len(), 4);
+        assert_eq!(info, ResponseInfo::default());
+    }
+}
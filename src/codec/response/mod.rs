@@ -7,9 +7,23 @@ pub use self::result::*;
 mod row;
 pub use self::row::*;
 
+#[cfg(feature = "with-serde")]
+mod de;
+#[cfg(feature = "with-serde")]
+pub use self::de::*;
+
 mod simple_messages;
 pub use self::simple_messages::*;
 
+mod server_error;
+pub use self::server_error::*;
+
+mod event;
+pub use self::event::*;
+
+mod info;
+pub use self::info::*;
+
 mod errors {
     error_chain! {
         foreign_links {
@@ -28,6 +42,22 @@ mod errors {
                 description("Error during parsing")
                 display("{}", err)
             }
+            NoSuchColumn(column: String) {
+                description("The row has no such column")
+                display("The row has no column {}", column)
+            }
+            UnexpectedColumnType(column: String, column_type: String) {
+                description("The type of a column can't be converted into the requested type")
+                display("Column {} of type {} can't be converted into the requested type", column, column_type)
+            }
+            UnexpectedNull(column: String) {
+                description("A column is null, but the requested type can't represent null")
+                display("Column {} is null, but the requested type can't represent null", column)
+            }
+            UnexpectedColumnCount(expected: usize, actual: usize) {
+                description("The row has an unexpected amount of columns")
+                display("Expected a row of {} columns, got {}", expected, actual)
+            }
         }
     }
 }
@@ -13,6 +13,7 @@ pub enum ResultMessage {
     Rows { rows: Vec<Row>, meta: RowsMetadata },
     Keyspace(CqlString),
     SchemaChange(SchemaChangePayload),
+    Prepared(PreparedPayload),
     Void,
 }
 
@@ -40,6 +41,20 @@ impl<'a> ::serde::Serialize for ResultMessage {
                 s.serialize_field("options", payload.options.as_ref())?;
                 s.end()
             }
+            &ResultMessage::Prepared(ref payload) => {
+                use serde::ser::SerializeStruct;
+                let mut s = serializer.serialize_struct("Prepared", 3)?;
+                s.serialize_field("id", &payload.id.as_bytes())?;
+                s.serialize_field(
+                    "variables",
+                    &payload.meta.column_spec.iter().map(|c| c.colname()).collect::<Vec<_>>(),
+                )?;
+                s.serialize_field(
+                    "columns",
+                    &payload.result_meta.column_spec.iter().map(|c| c.colname()).collect::<Vec<_>>(),
+                )?;
+                s.end()
+            }
             &ResultMessage::Void => serializer.serialize_str("<void>"),
         }
     }
@@ -48,15 +63,21 @@ impl<'a> ::serde::Serialize for ResultMessage {
 impl CqlDecode<ResultMessage> for ResultMessage {
     fn decode(v: ProtocolVersion, buf: BytesMut) -> Result<ResultMessage> {
         let (buf, result_header) = ResultHeader::decode(v, buf)?;
-        let result_header = result_header.expect("should have all bytes when using full body decode");
+        let result_header = match result_header {
+            Some(header) => header,
+            None => return Err(ErrorKind::Incomplete(String::from("result header")).into()),
+        };
 
         Ok(match result_header {
             ResultHeader::Rows(rows_metadata) => {
                 let mut v = Vec::new();
                 let mut d = buf;
-                for _ in 0..rows_metadata.rows_count {
+                for i in 0..rows_metadata.rows_count {
                     let (buf, row) = Row::decode(d, &rows_metadata)?;
-                    v.push(row.expect("should not encounter unfinished row here"));
+                    match row {
+                        Some(row) => v.push(row),
+                        None => return Err(ErrorKind::Incomplete(format!("row {} of the result", i)).into()),
+                    }
                     d = buf
                 }
                 ResultMessage::Rows {
@@ -66,6 +87,7 @@ impl CqlDecode<ResultMessage> for ResultMessage {
             }
             ResultHeader::SetKeyspace(name) => ResultMessage::Keyspace(name),
             ResultHeader::SchemaChange(payload) => ResultMessage::SchemaChange(payload),
+            ResultHeader::Prepared(payload) => ResultMessage::Prepared(payload),
             ResultHeader::Void => ResultMessage::Void,
         })
     }
@@ -76,6 +98,7 @@ pub enum ResultHeader {
     Void,
     SetKeyspace(CqlString),
     SchemaChange(SchemaChangePayload),
+    Prepared(PreparedPayload),
     Rows(RowsMetadata),
 }
 
@@ -87,11 +110,26 @@ pub struct SchemaChangePayload {
 }
 
 #[derive(Debug, PartialEq, Eq)]
+pub struct PreparedPayload {
+    /// The id to use in subsequent EXECUTE and BATCH requests.
+    pub id: CqlBytes,
+    /// Describes the bind variables of the prepared statement.
+    pub meta: RowsMetadata,
+    /// The indexes of the bind variables which make up the partition key.
+    /// Only provided from protocol version 4 onwards, and empty otherwise.
+    pub pk_indexes: Vec<u16>,
+    /// Describes the rows returned when executing the statement.
+    pub result_meta: RowsMetadata,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RowsMetadata {
     pub global_tables_spec: Option<TableSpec>,
     pub paging_state: Option<CqlBytes>,
     pub no_metadata: bool,
     pub column_spec: Vec<ColumnSpec>,
+    /// The amount of columns of each row, which is also known if `column_spec` was omitted.
+    pub columns_count: i32,
     pub rows_count: i32,
 }
 
@@ -102,12 +140,13 @@ impl Default for RowsMetadata {
             paging_state: None,
             no_metadata: false,
             column_spec: Vec::new(),
+            columns_count: 0,
             rows_count: 0,
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TableSpec {
     keyspace: CqlString,
     table: CqlString,
@@ -122,7 +161,7 @@ impl TableSpec {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ColumnSpec {
     WithoutGlobalSpec {
         table_spec: TableSpec,
@@ -143,6 +182,14 @@ impl ColumnSpec {
         }
     }
 
+    /// The name of the column, without its table.
+    pub fn name(&self) -> &str {
+        match self {
+            &ColumnSpec::WithoutGlobalSpec { ref name, .. } => name.as_ref(),
+            &ColumnSpec::WithGlobalSpec { ref name, .. } => name.as_ref(),
+        }
+    }
+
     pub fn colname(&self) -> String {
         match self {
             &ColumnSpec::WithoutGlobalSpec {
@@ -155,7 +202,7 @@ impl ColumnSpec {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ColumnType {
     Custom(CqlString),
     Ascii,
@@ -173,6 +220,10 @@ pub enum ColumnType {
     Varint,
     Timeuuid,
     Inet,
+    Date,
+    Time,
+    Smallint,
+    Tinyint,
     List(Box<ColumnType>),
     Map(Box<ColumnType>, Box<ColumnType>),
     Set(Box<ColumnType>),
@@ -181,17 +232,17 @@ pub enum ColumnType {
 }
 
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TupleDefinition(pub Vec<ColumnType>);
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UdtDefinition {
     pub keyspace: CqlString,
     pub name: CqlString,
     pub fields: Vec<UdtField>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UdtField(pub CqlString, pub ColumnType);
 
 impl ColumnType {
@@ -220,6 +271,10 @@ impl ColumnType {
                 0x000E => (buf, Some(ColumnType::Varint)),
                 0x000F => (buf, Some(ColumnType::Timeuuid)),
                 0x0010 => (buf, Some(ColumnType::Inet)),
+                0x0011 => (buf, Some(ColumnType::Date)),
+                0x0012 => (buf, Some(ColumnType::Time)),
+                0x0013 => (buf, Some(ColumnType::Smallint)),
+                0x0014 => (buf, Some(ColumnType::Tinyint)),
                 0x0020 => {
                     let (buf, inner) = Self::decode(buf)?;
                     (buf, inner.map(|v| ColumnType::List(Box::new(v))))
@@ -286,7 +341,7 @@ impl ColumnType {
 }
 
 impl ResultHeader {
-    pub fn decode(_v: ProtocolVersion, buf: BytesMut) -> Result<(BytesMut, Option<ResultHeader>)> {
+    pub fn decode(v: ProtocolVersion, buf: BytesMut) -> Result<(BytesMut, Option<ResultHeader>)> {
         if buf.len() < 4 {
             Err(
                 ErrorKind::Incomplete(format!("Need 4 bytes for length")).into(),
@@ -302,8 +357,7 @@ impl ResultHeader {
                         ResultHeader::SchemaChange(c)
                     })
                 }
-                // TODO:
-                // 0x0004    Prepared: result to a PREPARE message.
+                0x0004 => Self::match_decode(Self::decode_prepared(v, buf), |p| ResultHeader::Prepared(p)),
                 _ => Ok((buf, None)),
             }
         }
@@ -334,15 +388,68 @@ impl ResultHeader {
         ))
     }
 
+    fn decode_prepared(v: ProtocolVersion, buf: BytesMut) -> decode::ParseResult<PreparedPayload> {
+        let (buf, id) = decode::short_bytes(buf)?;
+        let (buf, (meta, pk_indexes)) =
+            Self::decode_metadata_and_pk_indexes(buf, v != ProtocolVersion::Version3)?;
+        let (buf, result_meta) = Self::decode_metadata(buf)?;
+
+        Ok((
+            buf,
+            PreparedPayload {
+                id: id,
+                meta: meta,
+                pk_indexes: pk_indexes,
+                result_meta: result_meta,
+            },
+        ))
+    }
+
     fn decode_rows_metadata(buf: BytesMut) -> decode::ParseResult<RowsMetadata> {
+        let (buf, mut rows_metadata) = Self::decode_metadata(buf)?;
+        let (buf, rows_count) = decode::int(buf)?;
+        rows_metadata.rows_count = rows_count;
+
+        Ok((buf, rows_metadata))
+    }
+
+    fn decode_metadata(buf: BytesMut) -> decode::ParseResult<RowsMetadata> {
+        Self::decode_metadata_and_pk_indexes(buf, false).map(|(buf, (meta, _))| (buf, meta))
+    }
+
+    fn decode_metadata_and_pk_indexes(
+        buf: BytesMut,
+        with_pk_indexes: bool,
+    ) -> decode::ParseResult<(RowsMetadata, Vec<u16>)> {
         let (buf, flags) = decode::int(buf)?;
         let (buf, col_count) = decode::int(buf)?;
 
+        let mut pk_indexes = Vec::new();
+        let buf = if with_pk_indexes {
+            let (buf, pk_count) = decode::int(buf)?;
+            let mut b = buf;
+            for _ in 0..pk_count {
+                let (buf, index) = decode::short(b)?;
+                pk_indexes.push(index);
+                b = buf;
+            }
+            b
+        } else {
+            buf
+        };
+
         let mut rows_metadata = RowsMetadata::default();
 
-        if (flags & 0x0002) == 0x0002 {
-            rows_metadata.paging_state = Some(cql_bytes!(1, 2, 3));
-        }
+        // Has_more_pages
+        let buf = if (flags & 0x0002) == 0x0002 {
+            let (buf, paging_state) = decode::bytes(buf)?;
+            if paging_state.as_bytes().is_some() {
+                rows_metadata.paging_state = Some(paging_state);
+            }
+            buf
+        } else {
+            buf
+        };
 
         let buf = if (flags & 0x0001) == 0x0001 {
             let (buf, keyspace) = decode::string(buf)?;
@@ -357,6 +464,8 @@ impl ResultHeader {
         };
 
         rows_metadata.no_metadata = (flags & 0x0004) == 0x0004;
+        rows_metadata.columns_count = col_count;
+        let col_count = if rows_metadata.no_metadata { 0 } else { col_count };
 
         let mut columns = Vec::new();
         let mut b = buf;
@@ -401,10 +510,7 @@ impl ResultHeader {
 
         rows_metadata.column_spec = columns;
 
-        let (b, rows_count) = decode::int(b)?;
-        rows_metadata.rows_count = rows_count;
-
-        Ok((b, rows_metadata))
+        Ok((b, (rows_metadata, pk_indexes)))
     }
 }
 
@@ -510,6 +616,7 @@ mod test {
                     column_type: ColumnType::Map(Box::new(ColumnType::Uuid), Box::new(ColumnType::Blob)),
                 },
             ],
+            columns_count: 18,
             rows_count: 1,
         };
 
@@ -578,6 +685,68 @@ mod test {
         }
     }
 
+    #[test]
+    fn decode_result_rows_with_paging_state() {
+        let msg = include_bytes!("../../../tests/fixtures/v3/responses/result_rows_paging_state.msg");
+        let buf = Vec::from(skip_header(&msg[..])).into();
+
+        match ResultMessage::decode(Version3, buf).unwrap() {
+            ResultMessage::Rows { rows, meta } => {
+                assert_eq!(meta.paging_state, Some(cql_bytes!(1, 2, 3)));
+                assert_eq!(
+                    meta.global_tables_spec,
+                    Some(TableSpec {
+                        keyspace: cql_string!("ks"),
+                        table: cql_string!("t"),
+                    })
+                );
+                assert_eq!(meta.rows_count, 1);
+                assert_eq!(rows.len(), 1);
+                assert_eq!(rows[0].value_at(0).ok(), Some(Int::new(7)));
+            }
+            res => panic!("Expected rows, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn decode_result_rows_truncated() {
+        let msg = include_bytes!("../../../tests/fixtures/v3/responses/result_rows_paging_state.msg");
+        let body = skip_header(&msg[..]);
+        let buf = Vec::from(&body[..body.len() - 1]).into();
+
+        match ResultMessage::decode(Version3, buf).map_err(|e| e.0) {
+            Err(super::super::ErrorKind::Incomplete(_)) => {}
+            res => panic!("Expected an incomplete error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn decode_result_rows_no_metadata() {
+        use codec::primitives::encode;
+
+        let mut buf = BytesMut::with_capacity(64);
+        encode::int(0x0002, &mut buf);
+        encode::int(0x0004, &mut buf);
+        encode::int(2, &mut buf);
+        encode::int(1, &mut buf);
+        encode::bytes(&CqlBytes::try_from(vec![b'a']).unwrap(), &mut buf);
+        encode::bytes(&CqlBytes::null_value(), &mut buf);
+
+        match ResultMessage::decode(Version3, buf).unwrap() {
+            ResultMessage::Rows { rows, meta } => {
+                assert_eq!(meta.no_metadata, true);
+                assert_eq!(meta.column_spec.len(), 0);
+                assert_eq!(meta.columns_count, 2);
+                assert_eq!(rows.len(), 1);
+                let first: Varchar = rows[0].value_at(0).unwrap();
+                let second: Option<Varchar> = rows[0].value_at(1).unwrap();
+                assert_eq!(first, Varchar::try_from("a").unwrap());
+                assert_eq!(second, None);
+            }
+            res => panic!("Expected rows, got {:?}", res),
+        }
+    }
+
     // TODO: write test with chunking of result!!! random chunking?
 
     #[test]
@@ -610,6 +779,7 @@ mod test {
                     column_type: ColumnType::Varchar,
                 },
             ],
+            columns_count: 2,
             rows_count: 1,
         };
 
@@ -668,6 +838,62 @@ mod test {
         );
     }
 
+    #[test]
+    fn decode_result_header_prepared() {
+        let msg = include_bytes!("../../../tests/fixtures/v3/responses/result_prepared.msg");
+        let buf = Vec::from(skip_header(&msg[..]));
+
+        let res = ResultHeader::decode(Version3, Vec::from(&buf[0..8]).into());
+        assert!(res.is_err());
+
+        let res = ResultHeader::decode(Version3, buf.into()).unwrap();
+        assert_eq!(
+            res.1,
+            Some(ResultHeader::Prepared(PreparedPayload {
+                id: cql_bytes!(0xca, 0xfe, 0xba, 0xbe),
+                meta: RowsMetadata {
+                    global_tables_spec: Some(TableSpec::new("system", "local")),
+                    column_spec: vec![
+                        ColumnSpec::WithGlobalSpec {
+                            name: cql_string!("key"),
+                            column_type: ColumnType::Varchar,
+                        },
+                    ],
+                    columns_count: 1,
+                    ..Default::default()
+                },
+                pk_indexes: Vec::new(),
+                result_meta: RowsMetadata {
+                    global_tables_spec: Some(TableSpec::new("system", "local")),
+                    column_spec: vec![
+                        ColumnSpec::WithGlobalSpec {
+                            name: cql_string!("key"),
+                            column_type: ColumnType::Varchar,
+                        },
+                    ],
+                    columns_count: 1,
+                    ..Default::default()
+                },
+            }))
+        );
+    }
+
+    #[test]
+    fn decode_result_header_prepared_v4() {
+        let msg = include_bytes!("../../../tests/fixtures/v4/responses/result_prepared.msg");
+        let buf = Vec::from(skip_header(&msg[..]));
+
+        let res = ResultHeader::decode(Version4, buf.into()).unwrap();
+        if let Some(ResultHeader::Prepared(payload)) = res.1 {
+            assert_eq!(payload.pk_indexes, vec![0]);
+            assert_eq!(payload.meta.column_spec.len(), 2);
+            assert_eq!(payload.result_meta.no_metadata, true);
+            assert_eq!(payload.result_meta.column_spec.len(), 0);
+        } else {
+            panic!("Expected to have a prepared result");
+        }
+    }
+
     #[test]
     fn decode_column_type_custom() {
         let buf = vec![0x00, 0x00, 0x00, 0x02, 0x61, 0x62];
@@ -768,6 +994,30 @@ mod test {
         assert_eq!(res.1, Some(ColumnType::Inet));
     }
 
+    #[test]
+    fn decode_column_type_date() {
+        let res = ColumnType::decode((vec![0x00, 0x11]).into()).unwrap();
+        assert_eq!(res.1, Some(ColumnType::Date));
+    }
+
+    #[test]
+    fn decode_column_type_time() {
+        let res = ColumnType::decode((vec![0x00, 0x12]).into()).unwrap();
+        assert_eq!(res.1, Some(ColumnType::Time));
+    }
+
+    #[test]
+    fn decode_column_type_smallint() {
+        let res = ColumnType::decode((vec![0x00, 0x13]).into()).unwrap();
+        assert_eq!(res.1, Some(ColumnType::Smallint));
+    }
+
+    #[test]
+    fn decode_column_type_tinyint() {
+        let res = ColumnType::decode((vec![0x00, 0x14]).into()).unwrap();
+        assert_eq!(res.1, Some(ColumnType::Tinyint));
+    }
+
     #[test]
     fn decode_column_type_list() {
         let buf = vec![0x00, 0x20, 0x00, 0x10];
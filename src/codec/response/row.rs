@@ -1,9 +1,11 @@
-use codec::primitives::datatypes::CqlSerializable;
+use codec::primitives::datatypes::{CqlSerializable, FromCql};
 #[cfg(feature = "with-serde")]
 use codec::primitives::datatypes::SerializableCell;
 use codec::primitives::decode;
 use bytes::BytesMut;
+use byteorder::{BigEndian, ByteOrder};
 use codec::response::ColumnSpec;
+use std::fmt::Display;
 
 use super::*;
 
@@ -13,25 +15,104 @@ pub struct Row {
 }
 
 pub trait ValueAt<T> {
-    ///
-    /// panics on out of bounds
-    ///
+    /// Fails if there is no column `i`, or if it is null and `T` is no `Option`.
     fn value_at(&self, i: usize) -> Result<T>;
 }
 
+/// Identifies a column of a row, either by its index or by its name.
+pub trait ColumnIndex: Display {
+    fn index_in(&self, meta: &RowsMetadata) -> Option<usize>;
+}
+
+impl ColumnIndex for usize {
+    fn index_in(&self, meta: &RowsMetadata) -> Option<usize> {
+        if *self < meta.column_spec.len() {
+            Some(*self)
+        } else {
+            None
+        }
+    }
+}
+
+/// Names are matched without the table of the column.
+impl<'a> ColumnIndex for &'a str {
+    fn index_in(&self, meta: &RowsMetadata) -> Option<usize> {
+        meta.column_spec.iter().position(|c| c.name() == *self)
+    }
+}
+
+/// Conversion of whole rows into native values.
+pub trait FromRow: Sized {
+    fn from_row(row: &Row, meta: &RowsMetadata) -> Result<Self>;
+}
+
 impl Row {
+    /// Decode the next row, or return `None` along with the untouched `buf` if it does not
+    /// contain the whole row yet.
     pub fn decode(buf: BytesMut, header: &RowsMetadata) -> Result<(BytesMut, Option<Row>)> {
-        let clen = header.column_spec.len();
+        let clen = header.columns_count as usize;
         let mut v = Vec::with_capacity(clen);
 
-        let mut b = buf;
+        let mut rest = buf;
+        let mut b = match Row::encoded_len(&rest, header) {
+            Some(len) => rest.split_to(len),
+            None => return Ok((rest, None)),
+        };
         for _ in 0..clen {
             let (buf, bytes) = decode::bytes(b)?;
             v.push(bytes.as_option());
             b = buf
         }
 
-        Ok((b, Some(Row { raw_cols: v })))
+        Ok((rest, Some(Row { raw_cols: v })))
+    }
+
+    /// The amount of bytes the row at the beginning of `buf` occupies, or `None` if `buf`
+    /// does not contain all of it yet.
+    pub fn encoded_len(buf: &[u8], header: &RowsMetadata) -> Option<usize> {
+        let mut pos = 0;
+        for _ in 0..header.columns_count {
+            if buf.len() < pos + 4 {
+                return None;
+            }
+            let len = BigEndian::read_i32(&buf[pos..pos + 4]);
+            pos += 4;
+            if len > 0 {
+                pos += len as usize;
+            }
+        }
+        if pos > buf.len() { None } else { Some(pos) }
+    }
+
+    /// The value of `column`, converted into `T`.
+    ///
+    /// Fails if there is no such column, if its type can't be converted into `T`, or if it is
+    /// null and `T` is no `Option`.
+    pub fn get<C: ColumnIndex, T: FromCql>(&self, meta: &RowsMetadata, column: C) -> Result<T> {
+        let i = match column.index_in(meta) {
+            Some(i) if i < self.raw_cols.len() => i,
+            _ => return Err(ErrorKind::NoSuchColumn(column.to_string()).into()),
+        };
+        let column_type = meta.column_spec[i].coltype();
+        if !T::accepts(column_type) {
+            return Err(ErrorKind::UnexpectedColumnType(column.to_string(), format!("{:?}", column_type)).into());
+        }
+        match self.raw_cols[i].clone() {
+            Some(data) => Ok(T::from_cql(column_type, data)?),
+            None => T::from_null().ok_or_else(|| ErrorKind::UnexpectedNull(column.to_string()).into()),
+        }
+    }
+
+    /// Convert the whole row into `T`.
+    pub fn into_typed<T: FromRow>(&self, meta: &RowsMetadata) -> Result<T> {
+        T::from_row(self, meta)
+    }
+
+    fn raw_col(&self, i: usize) -> Result<Option<BytesMut>> {
+        match self.raw_cols.get(i) {
+            Some(col) => Ok(col.clone()),
+            None => Err(ErrorKind::NoSuchColumn(i.to_string()).into()),
+        }
     }
 
     pub fn col_iter<'a>(&'a self, meta: &'a RowsMetadata) -> RowIterator<'a> {
@@ -46,22 +127,53 @@ impl Row {
 
 impl<T: CqlSerializable> ValueAt<T> for Row {
     fn value_at(&self, i: usize) -> Result<T> {
-        // TODO: no clone, maybe?
-        Ok(T::deserialize(self.raw_cols[i].clone().expect(
-            "Caller expected non-optional value",
-        ))?)
+        match self.raw_col(i)? {
+            Some(b) => Ok(T::deserialize(b)?),
+            None => Err(ErrorKind::UnexpectedNull(i.to_string()).into()),
+        }
     }
 }
 
 impl<U: CqlSerializable> ValueAt<Option<U>> for Row {
     fn value_at(&self, i: usize) -> Result<Option<U>> {
-        Ok(match self.raw_cols[i].clone() {
+        Ok(match self.raw_col(i)? {
             Some(b) => Some(U::deserialize(b)?),
             None => None,
         })
     }
 }
 
+macro_rules! tuple_from_row {
+    ($len: expr, $($name: ident: $i: expr),+) => {
+        /// Tuples are read from rows with the same amount of columns, in order.
+        impl<$($name: FromCql),+> FromRow for ($($name,)+) {
+            fn from_row(row: &Row, meta: &RowsMetadata) -> Result<Self> {
+                if meta.column_spec.len() != $len {
+                    return Err(ErrorKind::UnexpectedColumnCount($len, meta.column_spec.len()).into());
+                }
+                Ok(($(row.get::<usize, $name>(meta, $i)?,)+))
+            }
+        }
+    }
+}
+
+tuple_from_row!(1, A: 0);
+tuple_from_row!(2, A: 0, B: 1);
+tuple_from_row!(3, A: 0, B: 1, C: 2);
+tuple_from_row!(4, A: 0, B: 1, C: 2, D: 3);
+tuple_from_row!(5, A: 0, B: 1, C: 2, D: 3, E: 4);
+tuple_from_row!(6, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+tuple_from_row!(7, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+tuple_from_row!(8, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+tuple_from_row!(9, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8);
+tuple_from_row!(10, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9);
+tuple_from_row!(11, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10);
+tuple_from_row!(12, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11);
+tuple_from_row!(13, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12);
+tuple_from_row!(14, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13);
+tuple_from_row!(15, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13, O: 14);
+tuple_from_row!(16, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13, O: 14, P: 15);
+
 pub struct RowIterator<'a> {
     meta: &'a RowsMetadata,
     row: &'a Row,
@@ -117,6 +229,7 @@ mod test {
     use bytes::BytesMut;
     use super::super::{ColumnSpec, RowsMetadata, ColumnType, TableSpec};
     use std::fmt::Write;
+    use super::super::ErrorKind;
 
     fn as_bytes<T: CqlSerializable>(data: &T) -> Option<BytesMut> {
         let mut bytes = BytesMut::with_capacity(128);
@@ -205,6 +318,7 @@ mod test {
                     column_type: ColumnType::Double,
                 },
             ],
+            columns_count: 3,
             rows_count: 1,
         };
 
@@ -234,7 +348,110 @@ mod test {
         );
     }
 
-    // TODO: Test for Errorcase
+    #[test]
+    fn decode_incomplete_row() {
+        let meta = RowsMetadata {
+            column_spec: vec![
+                ColumnSpec::WithGlobalSpec {
+                    name: cql_string!("a"),
+                    column_type: ColumnType::Int,
+                },
+                ColumnSpec::WithGlobalSpec {
+                    name: cql_string!("b"),
+                    column_type: ColumnType::Int,
+                },
+            ],
+            columns_count: 2,
+            rows_count: 1,
+            ..Default::default()
+        };
+        let mut buf = BytesMut::with_capacity(32);
+        buf.extend_from_slice(&[0, 0, 0, 4, 0, 0, 0, 42, 0xff, 0xff, 0xff, 0xff, 1]);
+
+        for len in 0..12 {
+            assert_eq!(Row::encoded_len(&buf[..len], &meta), None);
+            let (rest, row) = Row::decode(BytesMut::from(&buf[..len]), &meta).unwrap();
+            assert!(row.is_none());
+            assert_eq!(rest.len(), len);
+        }
+        assert_eq!(Row::encoded_len(&buf, &meta), Some(12));
+
+        let (rest, row) = Row::decode(buf, &meta).unwrap();
+        let row = row.unwrap();
+        assert_eq!(&rest[..], &[1]);
+        assert_eq!(row.value_at(0).ok(), Some(Int::new(42)));
+        assert_eq!(row.value_at(1).ok(), Some(None::<Int>));
+    }
+
+    fn typed_row() -> (Row, RowsMetadata) {
+        let meta = RowsMetadata {
+            column_spec: vec![
+                ColumnSpec::WithoutGlobalSpec {
+                    table_spec: TableSpec::new("ks", "testtable"),
+                    name: cql_string!("id"),
+                    column_type: ColumnType::Int,
+                },
+                ColumnSpec::WithoutGlobalSpec {
+                    table_spec: TableSpec::new("ks", "testtable"),
+                    name: cql_string!("name"),
+                    column_type: ColumnType::Varchar,
+                },
+                ColumnSpec::WithoutGlobalSpec {
+                    table_spec: TableSpec::new("ks", "testtable"),
+                    name: cql_string!("tags"),
+                    column_type: ColumnType::List(Box::new(ColumnType::Varchar)),
+                },
+            ],
+            columns_count: 3,
+            rows_count: 1,
+            ..Default::default()
+        };
+        let row = Row {
+            raw_cols: vec![
+                as_bytes(&Int::new(42)),
+                None,
+                ToCql::to_cql(&vec!["a", "b"]).as_option(),
+            ],
+        };
+        (row, meta)
+    }
+
+    #[test]
+    fn row_get() {
+        let (row, meta) = typed_row();
+        assert_eq!(row.get::<_, i32>(&meta, 0).unwrap(), 42);
+        assert_eq!(row.get::<_, i32>(&meta, "id").unwrap(), 42);
+        assert_eq!(row.get::<_, Option<String>>(&meta, "name").unwrap(), None);
+        assert_eq!(row.get::<_, Vec<String>>(&meta, "tags").unwrap(), vec!["a", "b"]);
+
+        let (a, b, c): (i32, Option<String>, Vec<String>) = row.into_typed(&meta).unwrap();
+        assert_eq!((a, b, c.len()), (42, None, 2));
+    }
+
+    #[test]
+    fn row_get_errors() {
+        let (row, meta) = typed_row();
+        match *row.get::<_, i32>(&meta, "missing").unwrap_err().kind() {
+            ErrorKind::NoSuchColumn(ref c) => assert_eq!(c, "missing"),
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        assert!(row.get::<_, i32>(&meta, 3).is_err());
+        match *row.get::<_, i64>(&meta, "id").unwrap_err().kind() {
+            ErrorKind::UnexpectedColumnType(ref c, _) => assert_eq!(c, "id"),
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        match *row.get::<_, String>(&meta, 1).unwrap_err().kind() {
+            ErrorKind::UnexpectedNull(ref c) => assert_eq!(c, "1"),
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        match *row.into_typed::<(i32, String)>(&meta).unwrap_err().kind() {
+            ErrorKind::UnexpectedColumnCount(2, 3) => {}
+            ref e => panic!("unexpected error {:?}", e),
+        }
+
+        assert!(ValueAt::<Int>::value_at(&row, 1).is_err());
+        assert!(ValueAt::<Option<Int>>::value_at(&row, 5).is_err());
+    }
 }
 
 #[cfg(feature = "with-serde")]
@@ -277,6 +494,7 @@ mod serde_testing {
                     column_type: ColumnType::Double,
                 },
             ],
+            columns_count: 3,
             rows_count: 1,
         };
 
@@ -0,0 +1,352 @@
+use super::*;
+
+use codec::primitives::{CqlBytes, CqlConsistency, CqlString, CqlStringList};
+use codec::primitives::decode;
+use bytes::BytesMut;
+
+/// The kind of write which timed out or failed, as reported in Write_timeout and
+/// Write_failure errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteType {
+    /// A non-batched, non-counter write.
+    Simple,
+    /// A logged batch, whose batch log was written successfully.
+    Batch,
+    UnloggedBatch,
+    Counter,
+    /// The write to the batch log of a logged batch.
+    BatchLog,
+    /// A compare-and-set write. Only sent from protocol version 4 onwards.
+    Cas,
+    /// Any write type introduced by servers newer than this library.
+    Other(CqlString),
+}
+
+impl WriteType {
+    fn from_string(s: CqlString) -> WriteType {
+        match s.as_ref() {
+            "SIMPLE" => WriteType::Simple,
+            "BATCH" => WriteType::Batch,
+            "UNLOGGED_BATCH" => WriteType::UnloggedBatch,
+            "COUNTER" => WriteType::Counter,
+            "BATCH_LOG" => WriteType::BatchLog,
+            "CAS" => WriteType::Cas,
+            _ => WriteType::Other(s),
+        }
+    }
+}
+
+/// The error an ERROR message reports, along with the additional information the server sends
+/// for some of them. See section 9 of the protocol specification for details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CqlErrorKind {
+    ServerError,
+    ProtocolError,
+    BadCredentials,
+    Unavailable {
+        consistency: CqlConsistency,
+        /// The amount of nodes which have to be alive to achieve `consistency`.
+        required: i32,
+        /// The amount of replicas known to be alive when the request was processed.
+        alive: i32,
+    },
+    Overloaded,
+    IsBootstrapping,
+    TruncateError,
+    WriteTimeout {
+        consistency: CqlConsistency,
+        /// The amount of nodes which acknowledged the write.
+        received: i32,
+        /// The amount of acknowledgements required to achieve `consistency`.
+        block_for: i32,
+        write_type: WriteType,
+    },
+    ReadTimeout {
+        consistency: CqlConsistency,
+        /// The amount of nodes which answered the read.
+        received: i32,
+        /// The amount of responses required to achieve `consistency`.
+        block_for: i32,
+        /// True if the replica asked for data responded.
+        data_present: bool,
+    },
+    /// Only sent from protocol version 4 onwards.
+    ReadFailure {
+        consistency: CqlConsistency,
+        received: i32,
+        block_for: i32,
+        /// The amount of nodes which failed to execute the read.
+        num_failures: i32,
+        data_present: bool,
+    },
+    /// Only sent from protocol version 4 onwards.
+    FunctionFailure {
+        keyspace: CqlString,
+        function: CqlString,
+        arg_types: CqlStringList,
+    },
+    /// Only sent from protocol version 4 onwards.
+    WriteFailure {
+        consistency: CqlConsistency,
+        received: i32,
+        block_for: i32,
+        /// The amount of nodes which failed to execute the write.
+        num_failures: i32,
+        write_type: WriteType,
+    },
+    SyntaxError,
+    Unauthorized,
+    Invalid,
+    ConfigError,
+    AlreadyExists {
+        keyspace: CqlString,
+        /// Empty if the keyspace itself already existed.
+        table: CqlString,
+    },
+    /// The statement with the given id is not prepared on the host, and must be prepared
+    /// again before executing it.
+    Unprepared { id: CqlBytes },
+    /// An error code unknown to this library.
+    Unknown(i32),
+}
+
+impl CqlErrorKind {
+    /// Decode the error with the given `code` from the remainder of the ERROR message, which
+    /// follows its message text.
+    pub fn decode(code: i32, buf: BytesMut) -> Result<CqlErrorKind> {
+        use self::CqlErrorKind::*;
+        use super::error_code::*;
+        Ok(match code {
+            SERVER_ERROR => ServerError,
+            PROTOCOL_ERROR => ProtocolError,
+            BAD_CREDENTIALS => BadCredentials,
+            UNAVAILABLE => {
+                let (buf, consistency) = decode::consistency(buf)?;
+                let (buf, required) = decode::int(buf)?;
+                let (_, alive) = decode::int(buf)?;
+                Unavailable {
+                    consistency: consistency,
+                    required: required,
+                    alive: alive,
+                }
+            }
+            OVERLOADED => Overloaded,
+            IS_BOOTSTRAPPING => IsBootstrapping,
+            TRUNCATE_ERROR => TruncateError,
+            WRITE_TIMEOUT => {
+                let (buf, consistency) = decode::consistency(buf)?;
+                let (buf, received) = decode::int(buf)?;
+                let (buf, block_for) = decode::int(buf)?;
+                let (_, write_type) = decode::string(buf)?;
+                WriteTimeout {
+                    consistency: consistency,
+                    received: received,
+                    block_for: block_for,
+                    write_type: WriteType::from_string(write_type),
+                }
+            }
+            READ_TIMEOUT => {
+                let (buf, consistency) = decode::consistency(buf)?;
+                let (buf, received) = decode::int(buf)?;
+                let (buf, block_for) = decode::int(buf)?;
+                let (_, data_present) = decode::byte(buf)?;
+                ReadTimeout {
+                    consistency: consistency,
+                    received: received,
+                    block_for: block_for,
+                    data_present: data_present != 0,
+                }
+            }
+            READ_FAILURE => {
+                let (buf, consistency) = decode::consistency(buf)?;
+                let (buf, received) = decode::int(buf)?;
+                let (buf, block_for) = decode::int(buf)?;
+                let (buf, num_failures) = decode::int(buf)?;
+                let (_, data_present) = decode::byte(buf)?;
+                ReadFailure {
+                    consistency: consistency,
+                    received: received,
+                    block_for: block_for,
+                    num_failures: num_failures,
+                    data_present: data_present != 0,
+                }
+            }
+            FUNCTION_FAILURE => {
+                let (buf, keyspace) = decode::string(buf)?;
+                let (buf, function) = decode::string(buf)?;
+                let (_, arg_types) = decode::string_list(buf)?;
+                FunctionFailure {
+                    keyspace: keyspace,
+                    function: function,
+                    arg_types: arg_types,
+                }
+            }
+            WRITE_FAILURE => {
+                let (buf, consistency) = decode::consistency(buf)?;
+                let (buf, received) = decode::int(buf)?;
+                let (buf, block_for) = decode::int(buf)?;
+                let (buf, num_failures) = decode::int(buf)?;
+                let (_, write_type) = decode::string(buf)?;
+                WriteFailure {
+                    consistency: consistency,
+                    received: received,
+                    block_for: block_for,
+                    num_failures: num_failures,
+                    write_type: WriteType::from_string(write_type),
+                }
+            }
+            SYNTAX_ERROR => SyntaxError,
+            UNAUTHORIZED => Unauthorized,
+            INVALID => Invalid,
+            CONFIG_ERROR => ConfigError,
+            ALREADY_EXISTS => {
+                let (buf, keyspace) = decode::string(buf)?;
+                let (_, table) = decode::string(buf)?;
+                AlreadyExists {
+                    keyspace: keyspace,
+                    table: table,
+                }
+            }
+            UNPREPARED => {
+                let (_, id) = decode::short_bytes(buf)?;
+                Unprepared { id: id }
+            }
+            code => Unknown(code),
+        })
+    }
+
+    /// Returns true if sending the same request again may succeed, possibly on another host.
+    ///
+    /// Timeouts are considered retryable, yet a timed out write may still have been applied,
+    /// which makes it safe to retry only if the request is idempotent.
+    /// `Unprepared` requires the statement to be prepared again before retrying.
+    pub fn is_retryable(&self) -> bool {
+        use self::CqlErrorKind::*;
+        match *self {
+            ServerError | Unavailable { .. } | Overloaded | IsBootstrapping |
+            WriteTimeout { .. } | ReadTimeout { .. } | Unprepared { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use codec::header::Header;
+    use codec::header::ProtocolVersion::*;
+    use codec::primitives::{CqlFrom, CqlBytes};
+    use super::*;
+
+    fn decode_fixture(v: ::codec::header::ProtocolVersion, msg: &[u8]) -> ErrorMessage {
+        let buf = Vec::from(&msg[Header::encoded_len()..]).into();
+        ErrorMessage::decode(v, buf).unwrap()
+    }
+
+    #[test]
+    fn decode_unavailable() {
+        let msg = include_bytes!("../../../tests/fixtures/v3/responses/error_unavailable.msg");
+        let res = decode_fixture(Version3, &msg[..]);
+        assert_eq!(
+            res.kind,
+            CqlErrorKind::Unavailable {
+                consistency: CqlConsistency::Quorum,
+                required: 2,
+                alive: 1,
+            }
+        );
+        assert!(res.kind.is_retryable());
+    }
+
+    #[test]
+    fn decode_write_timeout() {
+        let msg = include_bytes!("../../../tests/fixtures/v3/responses/error_write_timeout.msg");
+        let res = decode_fixture(Version3, &msg[..]);
+        assert_eq!(
+            res.kind,
+            CqlErrorKind::WriteTimeout {
+                consistency: CqlConsistency::LocalQuorum,
+                received: 1,
+                block_for: 2,
+                write_type: WriteType::BatchLog,
+            }
+        );
+    }
+
+    #[test]
+    fn decode_read_timeout() {
+        let msg = include_bytes!("../../../tests/fixtures/v3/responses/error_read_timeout.msg");
+        let res = decode_fixture(Version3, &msg[..]);
+        assert_eq!(
+            res.kind,
+            CqlErrorKind::ReadTimeout {
+                consistency: CqlConsistency::One,
+                received: 0,
+                block_for: 1,
+                data_present: false,
+            }
+        );
+    }
+
+    #[test]
+    fn decode_already_exists() {
+        let msg = include_bytes!("../../../tests/fixtures/v3/responses/error_already_exists.msg");
+        let res = decode_fixture(Version3, &msg[..]);
+        assert_eq!(
+            res.kind,
+            CqlErrorKind::AlreadyExists {
+                keyspace: cql_string!("ks"),
+                table: cql_string!("t"),
+            }
+        );
+        assert!(!res.kind.is_retryable());
+    }
+
+    #[test]
+    fn decode_unprepared() {
+        let msg = include_bytes!("../../../tests/fixtures/v3/responses/error_unprepared.msg");
+        let res = decode_fixture(Version3, &msg[..]);
+        assert_eq!(res.kind, CqlErrorKind::Unprepared { id: cql_bytes!(1, 2) });
+    }
+
+    #[test]
+    fn decode_read_failure() {
+        let msg = include_bytes!("../../../tests/fixtures/v4/responses/error_read_failure.msg");
+        let res = decode_fixture(Version4, &msg[..]);
+        assert_eq!(
+            res.kind,
+            CqlErrorKind::ReadFailure {
+                consistency: CqlConsistency::Quorum,
+                received: 0,
+                block_for: 2,
+                num_failures: 1,
+                data_present: true,
+            }
+        );
+        assert!(!res.kind.is_retryable());
+    }
+
+    #[test]
+    fn decode_function_failure() {
+        let msg = include_bytes!("../../../tests/fixtures/v4/responses/error_function_failure.msg");
+        let res = decode_fixture(Version4, &msg[..]);
+        assert_eq!(
+            res.kind,
+            CqlErrorKind::FunctionFailure {
+                keyspace: cql_string!("ks"),
+                function: cql_string!("f"),
+                arg_types: CqlStringList::try_from_iter(vec!["int", "text"]).unwrap(),
+            }
+        );
+    }
+
+    #[test]
+    fn decode_truncated_payload() {
+        let mut buf = BytesMut::with_capacity(4);
+        buf.extend_from_slice(&[0, 4, 0, 0]);
+        assert!(CqlErrorKind::decode(error_code::UNAVAILABLE, buf).is_err());
+        assert_eq!(
+            CqlErrorKind::decode(0x4242, BytesMut::new()).unwrap(),
+            CqlErrorKind::Unknown(0x4242)
+        );
+    }
+}
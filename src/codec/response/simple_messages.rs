@@ -74,19 +74,48 @@ impl CqlDecode<AuthSuccessMessage> for AuthSuccessMessage {
     }
 }
 
+/// The error codes an ERROR message may carry, see section 9 of the protocol specification.
+pub mod error_code {
+    pub const SERVER_ERROR: i32 = 0x0000;
+    pub const PROTOCOL_ERROR: i32 = 0x000A;
+    pub const BAD_CREDENTIALS: i32 = 0x0100;
+    pub const UNAVAILABLE: i32 = 0x1000;
+    pub const OVERLOADED: i32 = 0x1001;
+    pub const IS_BOOTSTRAPPING: i32 = 0x1002;
+    pub const TRUNCATE_ERROR: i32 = 0x1003;
+    pub const WRITE_TIMEOUT: i32 = 0x1100;
+    pub const READ_TIMEOUT: i32 = 0x1200;
+    /// Only sent from protocol version 4 onwards.
+    pub const READ_FAILURE: i32 = 0x1300;
+    /// Only sent from protocol version 4 onwards.
+    pub const FUNCTION_FAILURE: i32 = 0x1400;
+    /// Only sent from protocol version 4 onwards.
+    pub const WRITE_FAILURE: i32 = 0x1500;
+    pub const SYNTAX_ERROR: i32 = 0x2000;
+    pub const UNAUTHORIZED: i32 = 0x2100;
+    pub const INVALID: i32 = 0x2200;
+    pub const CONFIG_ERROR: i32 = 0x2300;
+    pub const ALREADY_EXISTS: i32 = 0x2400;
+    pub const UNPREPARED: i32 = 0x2500;
+}
+
 #[derive(Debug)]
 pub struct ErrorMessage {
     pub code: i32,
     pub text: CqlString,
+    /// The error `code` refers to, along with its additional information.
+    pub kind: CqlErrorKind,
 }
 
 impl CqlDecode<ErrorMessage> for ErrorMessage {
     fn decode(_v: ProtocolVersion, buf: BytesMut) -> Result<ErrorMessage> {
         let (buf, code) = decode::int(buf)?;
-        let (_, text) = decode::string(buf)?;
+        let (buf, text) = decode::string(buf)?;
+        let kind = CqlErrorKind::decode(code, buf)?;
         Ok(ErrorMessage {
             code: code,
             text: text,
+            kind: kind,
         })
     }
 }
@@ -96,7 +125,7 @@ impl CqlDecode<ErrorMessage> for ErrorMessage {
 mod test {
     use codec::header::Header;
     use codec::header::ProtocolVersion::*;
-    use codec::primitives::{CqlStringMultiMap, CqlStringList, CqlString};
+    use codec::primitives::{CqlStringMultiMap, CqlStringList, CqlString, CqlConsistency};
     use super::*;
 
     fn skip_header(b: &[u8]) -> &[u8] {
@@ -155,9 +184,30 @@ mod test {
         let res = ErrorMessage::decode(Version3, buf).unwrap();
 
         assert_eq!(res.code, 256);
+        assert_eq!(res.kind, CqlErrorKind::BadCredentials);
         assert_eq!(
             res.text,
             cql_string!("Username and/or password are incorrect")
         );
     }
+
+    #[test]
+    fn decode_error_message_write_failure() {
+        let msg = include_bytes!("../../../tests/fixtures/v4/responses/error_write_failure.msg");
+        let buf = Vec::from(skip_header(&msg[..])).into();
+        let res = ErrorMessage::decode(Version4, buf).unwrap();
+
+        assert_eq!(res.code, error_code::WRITE_FAILURE);
+        assert_eq!(res.text, cql_string!("Operation failed"));
+        assert_eq!(
+            res.kind,
+            CqlErrorKind::WriteFailure {
+                consistency: CqlConsistency::LocalQuorum,
+                received: 1,
+                block_for: 2,
+                num_failures: 1,
+                write_type: WriteType::Simple,
+            }
+        );
+    }
 }
@@ -5,13 +5,14 @@ extern crate quick_error;
 extern crate byteorder;
 
 #[cfg(feature = "with-serde")]
+#[macro_use]
 extern crate serde;
 
 #[cfg(feature = "with-serde")]
 #[macro_use]
 extern crate serde_derive;
 
-#[cfg_attr(feature = "with-openssl", macro_use)]
+#[macro_use]
 extern crate futures;
 extern crate tokio_core;
 extern crate tokio_service;
@@ -20,6 +21,11 @@ extern crate tokio_io;
 extern crate bytes;
 extern crate chrono;
 
+#[cfg(feature = "with-lz4")]
+extern crate lz4;
+#[cfg(feature = "with-snappy")]
+extern crate snap;
+
 #[cfg(feature = "with-openssl")]
 extern crate tokio_openssl;
 #[cfg(feature = "with-openssl")]
@@ -11,3 +11,31 @@ macro_rules! cql_string {
         CqlString::try_from($s).unwrap()
     };
 }
+
+/// Build positional `QueryValues` from values implementing `ToCql`, like
+/// `values![id, "name", None::<i32>]`.
+#[macro_export]
+macro_rules! values {
+    ($($value: expr),* $(,)*) => {
+        $crate::codec::request::QueryValues::Positional(vec![
+            $($crate::codec::primitives::datatypes::ToCql::to_cql(&$value)),*
+        ])
+    };
+}
+
+/// Build named `QueryValues` from values implementing `ToCql`, like
+/// `named_values!{"id" => id, "name" => "a"}`.
+#[macro_export]
+macro_rules! named_values {
+    ($($name: expr => $value: expr),* $(,)*) => {{
+        let mut values = ::std::collections::HashMap::new();
+        $(
+            values.insert(
+                <$crate::codec::primitives::CqlString as $crate::codec::primitives::CqlFrom<_, &str>>::try_from($name)
+                    .expect("a valid variable name"),
+                $crate::codec::primitives::datatypes::ToCql::to_cql(&$value),
+            );
+        )*
+        $crate::codec::request::QueryValues::Named(values)
+    }};
+}
@@ -1,27 +1,36 @@
 use codec::request;
+use codec::response::{EventMessage, ResultMessage};
 use codec::header::ProtocolVersion;
 use codec::authentication::Credentials;
+use codec::primitives::{CqlFrom, CqlLongString};
 use tokio_service::Service;
-use futures::Future;
+use futures::{future, Future};
+use futures::sync::mpsc::{self, UnboundedSender, UnboundedReceiver};
 use tokio_core::reactor::Handle;
-use tokio_proto::util::client_proxy::{Response as ClientProxyResponse, ClientProxy};
+use tokio_proto::util::client_proxy::ClientProxy;
 use tokio_proto::streaming::Message;
 use tokio_proto::streaming::multiplex::ClientProto;
 use tokio_proto::TcpClient;
 use tokio_io::{AsyncRead, AsyncWrite};
 use tokio_io::codec::Framed;
+use std::cell::Cell;
 use std::io;
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 use semver;
 use super::ssl;
 
 use super::error::*;
-use super::messages::{RequestMessage, ResponseMessage, ChunkedMessage, StreamingMessage};
-use super::codec::{CqlCodec, CqlCodecDebuggingOptions};
-use super::handshake::interpret_response_and_handle;
+use super::messages::{RequestMessage, ResponseMessage, ChunkedMessage, StreamingMessage, RowStream};
+use super::utils::{io_err, with_timeout};
+use super::paging::RowPages;
+use super::codec::{CqlCodec, CqlCodecDebuggingOptions, OrphanedRequests};
+use super::handshake::{interpret_response_and_handle, negotiate_protocol_version,
+                       check_protocol_version};
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct CqlProto {
+    /// The highest protocol version to try when connecting.
     pub version: ProtocolVersion,
     pub debug: Option<CqlCodecDebuggingOptions>,
 }
@@ -44,15 +53,233 @@ impl<T: AsyncRead + AsyncWrite + 'static> ClientProto<T> for CqlProto {
     }
 }
 
+/// The protocol bound to each connection, which routes server pushes into the event channel
+/// of its `ClientHandle`.
+#[derive(Debug, Clone)]
+struct ConnectionProto {
+    protocol: CqlProto,
+    events: UnboundedSender<EventMessage>,
+    orphaned: OrphanedRequests,
+}
+
+impl<T: AsyncRead + AsyncWrite + 'static> ClientProto<T> for ConnectionProto {
+    type Request = request::Message;
+    type RequestBody = request::Message;
+    type Response = StreamingMessage;
+    type ResponseBody = ChunkedMessage;
+    type Error = io::Error;
+
+    type Transport = Framed<T, CqlCodec>;
+    type BindTransport = io::Result<Self::Transport>;
+
+    fn bind_transport(&self, io: T) -> Self::BindTransport {
+        let codec = CqlCodec::new(
+            self.protocol.version,
+            self.protocol.debug.clone().unwrap_or_default(),
+        ).with_event_sink(self.events.clone())
+            .with_orphaned_requests(self.orphaned.clone());
+        Ok(io.framed(codec))
+    }
+}
+
+/// A stream of all events the server pushes to a connection, once registered for them.
+pub type EventStream = UnboundedReceiver<EventMessage>;
+
+/// Sends requests over a connection, and receives their responses.
+type Transport = Service<
+    Request = RequestMessage,
+    Response = ResponseMessage,
+    Error = io::Error,
+    Future = Box<Future<Item = ResponseMessage, Error = io::Error>>,
+>;
+
+struct ProxyTransport(ClientProxy<RequestMessage, ResponseMessage, io::Error>);
+
+impl Service for ProxyTransport {
+    type Request = RequestMessage;
+    type Response = ResponseMessage;
+    type Error = io::Error;
+    type Future = Box<Future<Item = Self::Response, Error = Self::Error>>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        Box::new(self.0.call(req))
+    }
+}
+
+/// A connection to a single node.
+///
+/// A request is cancelled by dropping its future. The server is not told about it, as the
+/// protocol doesn't allow that, and its response is dropped once it arrives. Requests which
+/// timed out are counted until their response arrives, and the connection is closed once too
+/// many of them are pending.
+///
+/// A closed connection stays closed, which ends the stream of its events. See `Pool` for a
+/// wrapper which replaces lost connections.
 pub struct ClientHandle {
-    inner: Box<
-        Service<
-            Request = RequestMessage,
-            Response = ResponseMessage,
-            Error = io::Error,
-            Future = ClientProxyResponse<ResponseMessage, io::Error>,
-        >,
-    >,
+    inner: Box<Transport>,
+    version: ProtocolVersion,
+    events: Option<EventStream>,
+    reactor: Handle,
+    timeout: Option<Duration>,
+    heartbeat_interval: Option<Duration>,
+    last_request: Cell<Instant>,
+    orphaned: OrphanedRequests,
+}
+
+impl ClientHandle {
+    /// The protocol version agreed upon with the server during the handshake.
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.version
+    }
+
+    /// Ask the server to push the given kinds of events to this connection.
+    /// They can be received through the stream obtained by `take_events()`.
+    pub fn register(&self, events: Vec<request::EventType>) -> Box<Future<Item = (), Error = Error>> {
+        let msg = request::Message::Register(request::RegisterMessage { events: events });
+        Box::new(self.call(msg).map_err(|e| e.into()).and_then(|res| match res {
+            StreamingMessage::Ready => Ok(()),
+            StreamingMessage::Error(msg) => Err(ErrorKind::CqlError(msg.code, msg.text.into()).into()),
+            msg => Err(
+                io_err(format!("Unexpected response to REGISTER: {:?}", msg)).into(),
+            ),
+        }))
+    }
+
+    /// Stream all rows returned by `query`, which are fetched in pages of `page_size` rows.
+    /// See `RowPages` for how to resume from a saved paging state.
+    pub fn query_pages(&self, query: request::QueryMessage, page_size: i32) -> RowPages<&ClientHandle> {
+        RowPages::new(self, query, page_size)
+    }
+
+    /// Make `keyspace` the keyspace unqualified table names of later requests resolve to. The
+    /// name is used as is, and is case-sensitive.
+    pub fn use_keyspace(&self, keyspace: &str) -> Box<Future<Item = (), Error = Error>> {
+        let query = format!("USE \"{}\"", keyspace.replace('"', "\"\""));
+        let msg = request::Message::Query(request::QueryMessage {
+            query: CqlLongString::try_from(query.as_str()).expect("a keyspace name to fit into a query"),
+            idempotent: true,
+            ..Default::default()
+        });
+        Box::new(self.call(msg).map_err(|e| e.into()).and_then(|res| match res {
+            StreamingMessage::Result(ResultMessage::Keyspace(_), _) => Ok(()),
+            StreamingMessage::Error(msg) => Err(ErrorKind::CqlError(msg.code, msg.text.into()).into()),
+            msg => Err(io_err(format!("Unexpected response to USE: {:?}", msg)).into()),
+        }))
+    }
+
+    /// Returns the stream of events pushed by the server, which can only be taken once.
+    pub fn take_events(&mut self) -> Option<EventStream> {
+        self.events.take()
+    }
+
+    /// The time since the last request was sent through this connection.
+    pub fn idle_time(&self) -> Duration {
+        self.last_request.get().elapsed()
+    }
+
+    /// How long the connection may be idle before a heartbeat should be sent, as configured
+    /// in the `ConnectOptions`.
+    pub fn heartbeat_interval(&self) -> Option<Duration> {
+        self.heartbeat_interval
+    }
+
+    /// Send an OPTIONS request to check that the connection is still usable. It fails unless
+    /// the server answers within the request timeout of the connection, or the heartbeat
+    /// interval if there is none.
+    pub fn heartbeat(&self) -> Box<Future<Item = (), Error = io::Error>> {
+        self.last_request.set(Instant::now());
+        let res = self.inner.call(request::Message::Options.into()).map(From::from);
+        let res = res.and_then(|res| match res {
+            StreamingMessage::Supported(_) => Ok(()),
+            msg => Err(io_err(format!("Unexpected response to heartbeat: {:?}", msg))),
+        });
+        match self.timeout.or(self.heartbeat_interval) {
+            Some(timeout) => self.with_timeout(res, timeout),
+            None => Box::new(res),
+        }
+    }
+
+    /// Fail `res` once `timeout` elapsed, and count the request as orphaned until its response
+    /// arrives.
+    fn with_timeout<F>(&self, res: F, timeout: Duration) -> Box<Future<Item = F::Item, Error = io::Error>>
+    where
+        F: Future<Error = io::Error> + 'static,
+    {
+        let orphaned = self.orphaned.clone();
+        let reactor = self.reactor.clone();
+        with_timeout(res, timeout, &self.reactor, move |res| {
+            orphaned.add();
+            reactor.spawn(res.then(move |_| {
+                orphaned.remove();
+                Ok(())
+            }));
+        })
+    }
+}
+
+#[cfg(test)]
+impl ClientHandle {
+    /// A connection which answers each request with `respond`. Its events are sent through the
+    /// returned sender, and dropping that closes the connection.
+    pub fn fake<F>(
+        version: ProtocolVersion,
+        handle: &Handle,
+        options: &ConnectOptions,
+        respond: F,
+    ) -> (ClientHandle, UnboundedSender<EventMessage>)
+    where
+        F: Fn(request::Message) -> io::Result<StreamingMessage> + 'static,
+    {
+        ClientHandle::fake_with(version, handle, options, move |req| Box::new(future::result(respond(req))))
+    }
+
+    /// Like `fake()`, but each request is answered once the future returned by `respond`
+    /// completes.
+    pub fn fake_with<F>(
+        version: ProtocolVersion,
+        handle: &Handle,
+        options: &ConnectOptions,
+        respond: F,
+    ) -> (ClientHandle, UnboundedSender<EventMessage>)
+    where
+        F: Fn(request::Message) -> Box<Future<Item = StreamingMessage, Error = io::Error>> + 'static,
+    {
+        let (tx, rx) = mpsc::unbounded();
+        let client_handle = ClientHandle {
+            inner: Box::new(FakeTransport(Box::new(respond))),
+            version: version,
+            events: Some(rx),
+            reactor: handle.clone(),
+            timeout: options.request_timeout,
+            heartbeat_interval: options.heartbeat_interval,
+            last_request: Cell::new(Instant::now()),
+            orphaned: OrphanedRequests::default(),
+        };
+        (client_handle, tx)
+    }
+
+    /// Pretend that the last request was sent `idle` ago.
+    pub fn set_idle_time(&self, idle: Duration) {
+        self.last_request.set(Instant::now() - idle);
+    }
+}
+
+#[cfg(test)]
+struct FakeTransport(Box<Fn(request::Message) -> Box<Future<Item = StreamingMessage, Error = io::Error>>>);
+
+#[cfg(test)]
+impl Service for FakeTransport {
+    type Request = RequestMessage;
+    type Response = ResponseMessage;
+    type Error = io::Error;
+    type Future = Box<Future<Item = Self::Response, Error = Self::Error>>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        let req = match req {
+            Message::WithoutBody(req) | Message::WithBody(req, _) => req,
+        };
+        Box::new((self.0)(req).map(Message::WithoutBody))
+    }
 }
 
 impl From<request::Message> for RequestMessage {
@@ -65,7 +292,10 @@ impl From<ResponseMessage> for StreamingMessage {
     fn from(msg: ResponseMessage) -> Self {
         match msg {
             Message::WithoutBody(res) => res,
-            Message::WithBody(_head, bodystream) => StreamingMessage::Partial(bodystream),
+            Message::WithBody(StreamingMessage::Result(ResultMessage::Rows { meta, .. }, info), body) => {
+                StreamingMessage::Partial(RowStream::new(meta, info, body))
+            }
+            Message::WithBody(head, _) => unreachable!("Only Rows results are streamed, got {:?}", head),
         }
     }
 }
@@ -76,8 +306,27 @@ impl Service for ClientHandle {
     type Error = io::Error;
     type Future = Box<Future<Item = Self::Response, Error = Self::Error>>;
 
+    /// The timeout of `req` or the one of the connection only applies to receiving the
+    /// response, but not to the rows of a streamed result.
+    fn call(&self, req: Self::Request) -> Self::Future {
+        self.last_request.set(Instant::now());
+        let timeout = req.timeout().or(self.timeout);
+        let res = self.inner.call(req.into()).map(From::from);
+        match timeout {
+            Some(timeout) => self.with_timeout(res, timeout),
+            None => Box::new(res),
+        }
+    }
+}
+
+impl<'a> Service for &'a ClientHandle {
+    type Request = request::Message;
+    type Response = StreamingMessage;
+    type Error = io::Error;
+    type Future = Box<Future<Item = Self::Response, Error = Self::Error>>;
+
     fn call(&self, req: Self::Request) -> Self::Future {
-        Box::new(self.inner.call(req.into()).map(From::from))
+        (*self).call(req)
     }
 }
 
@@ -89,13 +338,11 @@ pub struct Client {
 
 #[cfg(not(feature = "with-openssl"))]
 fn ssl_client(
-    _protocol: CqlProto,
+    _protocol: ConnectionProto,
     _addr: &SocketAddr,
     _handle: &Handle,
     _tls: ssl::Options,
 ) -> Box<Future<Item = ClientProxy<RequestMessage, ResponseMessage, io::Error>, Error = io::Error>> {
-    use futures::future;
-    use super::utils::io_err;
     Box::new(future::err(io_err(
         "Please compile this library with \
                                                      --features=ssl",
@@ -104,7 +351,7 @@ fn ssl_client(
 
 #[cfg(feature = "with-openssl")]
 fn ssl_client(
-    protocol: CqlProto,
+    protocol: ConnectionProto,
     addr: &SocketAddr,
     handle: &Handle,
     tls: ssl::Options,
@@ -113,42 +360,214 @@ fn ssl_client(
     Box::new(SslClient::new(protocol, tls).connect(addr, handle))
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct ConnectOptions {
     pub creds: Option<Credentials>,
     pub tls: Option<ssl::Options>,
     pub desired_cql_version: Option<semver::Version>,
+    /// The time to wait for the response to a request which doesn't set its own timeout.
+    /// `None` waits forever. The default is 12 seconds.
+    pub request_timeout: Option<Duration>,
+    /// The time a connection may be idle before a heartbeat is sent through it, to keep it
+    /// open and to find out if it is still usable. `None` sends no heartbeats. The default is
+    /// 30 seconds.
+    ///
+    /// Heartbeats are sent by `Pool` and `Cluster`, and not by plain connections.
+    pub heartbeat_interval: Option<Duration>,
+    /// The keyspace to use once the handshake is done, as if by `ClientHandle::use_keyspace`.
+    pub keyspace: Option<String>,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        ConnectOptions {
+            creds: None,
+            tls: None,
+            desired_cql_version: None,
+            request_timeout: Some(Duration::from_secs(12)),
+            heartbeat_interval: Some(Duration::from_secs(30)),
+            keyspace: None,
+        }
+    }
 }
 
 impl Client {
+    /// Connect using the protocol version configured in `CqlProto`, or any lower one the server
+    /// supports. The version agreed upon is available through `ClientHandle::protocol_version()`.
     pub fn connect(
         self,
         addr: &SocketAddr,
         handle: &Handle,
         options: ConnectOptions,
     ) -> Box<Future<Item = ClientHandle, Error = Error>> {
-        let ConnectOptions {
-            creds,
-            tls,
-            desired_cql_version,
-        } = options;
-        let ret = match tls {
-            Some(tls) => ssl_client(self.protocol, addr, handle, tls),
-            None => Box::new(TcpClient::new(self.protocol).connect(addr, handle)),
-        }.map(|client_proxy| {
-            ClientHandle { inner: Box::new(client_proxy) }
+        let addr = *addr;
+        let handle = handle.clone();
+        let version = self.protocol.version;
+        negotiate_protocol_version(version, move |version| {
+            let protocol = CqlProto {
+                version: version,
+                ..self.protocol.clone()
+            };
+            connect_with(protocol, &addr, &handle, options.clone())
         })
-            .and_then(|client_handle| {
-                client_handle.call(request::Message::Options).map(|r| {
-                    (r, client_handle)
-                })
-            })
-            .map_err(|e| e.into())
-            .and_then(|(res, ch)| {
-                interpret_response_and_handle(ch, res, creds, desired_cql_version)
+    }
+}
+
+fn connect_with(
+    protocol: CqlProto,
+    addr: &SocketAddr,
+    handle: &Handle,
+    options: ConnectOptions,
+) -> Box<Future<Item = ClientHandle, Error = Error>> {
+    let ConnectOptions {
+        creds,
+        tls,
+        desired_cql_version,
+        request_timeout,
+        heartbeat_interval,
+        keyspace,
+    } = options;
+    let version = protocol.version;
+    let reactor = handle.clone();
+    let (tx, rx) = mpsc::unbounded();
+    let orphaned = OrphanedRequests::default();
+    let protocol = ConnectionProto {
+        protocol: protocol,
+        events: tx,
+        orphaned: orphaned.clone(),
+    };
+    let ret = match tls {
+        Some(tls) => ssl_client(protocol, addr, handle, tls),
+        None => Box::new(TcpClient::new(protocol).connect(addr, handle)),
+    }.map(move |client_proxy| {
+        ClientHandle {
+            inner: Box::new(ProxyTransport(client_proxy)),
+            version: version,
+            events: Some(rx),
+            reactor: reactor,
+            timeout: request_timeout,
+            heartbeat_interval: heartbeat_interval,
+            last_request: Cell::new(Instant::now()),
+            orphaned: orphaned,
+        }
+    })
+        .map_err(|e| e.into())
+        .and_then(move |client_handle| {
+            client_handle.call(request::Message::Options).then(move |r| {
+                check_protocol_version(version, r).map(|r| (r, client_handle))
             })
-            .and_then(|ch| Ok(ch));
+        })
+        .and_then(|(res, ch)| {
+            interpret_response_and_handle(ch, res, creds, desired_cql_version)
+        })
+        .and_then(move |ch| -> Box<Future<Item = ClientHandle, Error = Error>> {
+            match keyspace {
+                Some(keyspace) => Box::new(ch.use_keyspace(&keyspace).map(move |_| ch)),
+                None => Box::new(future::ok(ch)),
+            }
+        });
+
+    Box::new(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codec::header::ProtocolVersion::*;
+    use futures::sync::oneshot;
+    use tokio_core::reactor::{Core, Timeout};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// A connection whose responses never arrive.
+    fn unresponsive(core: &Core, request_timeout: Option<Duration>) -> ClientHandle {
+        let options = ConnectOptions {
+            request_timeout: request_timeout,
+            ..Default::default()
+        };
+        let (client_handle, _events) = ClientHandle::fake_with(Version3, &core.handle(), &options, |_| {
+            Box::new(future::empty())
+        });
+        client_handle
+    }
+
+    fn query(timeout: Option<Duration>) -> request::Message {
+        request::Message::Query(request::QueryMessage {
+            timeout: timeout,
+            ..Default::default()
+        })
+    }
+
+    /// Whether `req` is still waiting for its response after 50 milliseconds.
+    fn waits(core: &mut Core, client_handle: &ClientHandle, req: request::Message) -> bool {
+        let wait = Timeout::new(Duration::from_millis(50), &core.handle()).unwrap();
+        match core.run(client_handle.call(req).select2(wait)) {
+            Ok(future::Either::B(_)) => true,
+            Ok(future::Either::A(_)) | Err(future::Either::A(_)) => false,
+            Err(future::Either::B((err, _))) => panic!("failed to wait: {}", err),
+        }
+    }
+
+    #[test]
+    fn requests_time_out_after_the_timeout_of_the_connection() {
+        let mut core = Core::new().unwrap();
+        let client_handle = unresponsive(&core, Some(Duration::from_millis(10)));
+        let err = core.run(client_handle.call(request::Message::Options)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        let err = core.run(client_handle.call(query(None))).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn the_timeout_of_requests_overrides_the_one_of_the_connection() {
+        let mut core = Core::new().unwrap();
+        let client_handle = unresponsive(&core, Some(Duration::from_secs(60)));
+        let err = core.run(client_handle.call(query(Some(Duration::from_millis(10)))))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let client_handle = unresponsive(&core, Some(Duration::from_millis(10)));
+        assert!(waits(&mut core, &client_handle, query(Some(Duration::from_secs(60)))));
+    }
+
+    #[test]
+    fn requests_without_timeout_wait() {
+        let mut core = Core::new().unwrap();
+        let client_handle = unresponsive(&core, None);
+        assert!(waits(&mut core, &client_handle, request::Message::Options));
+        assert!(waits(&mut core, &client_handle, query(None)));
+        assert_eq!(client_handle.orphaned.count(), 0);
+    }
+
+    #[test]
+    fn counts_timed_out_requests_until_their_response_arrives() {
+        let mut core = Core::new().unwrap();
+        let responses = Rc::new(RefCell::new(Vec::new()));
+        let pending = responses.clone();
+        let options = ConnectOptions {
+            request_timeout: Some(Duration::from_millis(10)),
+            ..Default::default()
+        };
+        let (client_handle, _events) = ClientHandle::fake_with(Version3, &core.handle(), &options, move |_| {
+            let (tx, rx) = oneshot::channel();
+            pending.borrow_mut().push(tx);
+            Box::new(rx.map_err(|_| io_err("connection closed")))
+        });
+
+        for _ in 0..2 {
+            let err = core.run(client_handle.call(request::Message::Options)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        }
+        assert_eq!(client_handle.orphaned.count(), 2);
+
+        let late = responses.borrow_mut().remove(0);
+        late.send(StreamingMessage::Ready).unwrap();
+        core.turn(Some(Duration::from_millis(1)));
+        assert_eq!(client_handle.orphaned.count(), 1);
 
-        Box::new(ret)
+        // A connection which is closed fails the requests still pending.
+        responses.borrow_mut().clear();
+        core.turn(Some(Duration::from_millis(1)));
+        assert_eq!(client_handle.orphaned.count(), 0);
     }
 }
@@ -0,0 +1,849 @@
+//! Discovery of all nodes of a cluster, starting from a few contact points.
+//!
+//! One connection, the control connection, is used to read the `system.local` and
+//! `system.peers` tables, and to receive the events which indicate that nodes joined, left or
+//! changed their status. If the control connection is lost or doesn't answer a heartbeat, it is
+//! opened again to any node which is up, or to one of the contact points.
+use codec::request::{self, EventType, QueryMessage, QueryParameters};
+use codec::response::{self, EventMessage, Row, StatusChangeType, TopologyChangeEvent, TopologyChangeType,
+                      ValueAt};
+use codec::primitives::{CqlFrom, CqlLongString};
+use codec::primitives::datatypes::{Inet, Map, Set, Text, Uuid};
+use futures::{future, Future, Stream};
+use futures::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_service::Service;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+
+use super::client::{Client, ClientHandle, ConnectOptions, EventStream};
+use super::error::*;
+use super::partitioner::{Partitioner, ReplicationStrategy, TokenRing};
+use super::policies::ReconnectionPolicy;
+use super::utils::{into_rows, io_err};
+
+const LOCAL_QUERY: &str = "SELECT data_center, rack, tokens, release_version, host_id, partitioner \
+                           FROM system.local WHERE key = 'local'";
+const PEERS_QUERY: &str = "SELECT peer, rpc_address, data_center, rack, tokens, release_version, \
+                           host_id FROM system.peers";
+const KEYSPACES_QUERY: &str = "SELECT keyspace_name, replication FROM system_schema.keyspaces";
+
+/// A node of the cluster, as described by the `system.local` or `system.peers` table.
+#[cfg_attr(feature = "with-serde", derive(Serialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    /// The address clients connect to, which uses the port of the contact points.
+    pub address: SocketAddr,
+    pub host_id: Option<Uuid>,
+    pub datacenter: Option<String>,
+    pub rack: Option<String>,
+    /// The tokens owned by the node, in the string representation of the partitioner.
+    pub tokens: Vec<String>,
+    pub release_version: Option<String>,
+    /// False if the server reported the node to be down.
+    pub is_up: bool,
+}
+
+impl Node {
+    fn from_row(address: SocketAddr, row: &Row, offset: usize) -> Result<Node> {
+        let tokens: Option<Set<Text>> = row.value_at(offset + 2)?;
+        Ok(Node {
+            address: address,
+            host_id: row.value_at(offset + 4)?,
+            datacenter: text_at(row, offset)?,
+            rack: text_at(row, offset + 1)?,
+            tokens: match tokens {
+                Some(tokens) => {
+                    let tokens = tokens.values().map_err(response::Error::from)?;
+                    tokens.iter().map(|t| t.as_ref().to_string()).collect()
+                }
+                None => Vec::new(),
+            },
+            release_version: text_at(row, offset + 3)?,
+            is_up: true,
+        })
+    }
+}
+
+/// A change of the state of a node, as observed by a `Cluster`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostEvent {
+    Up(SocketAddr),
+    Down(SocketAddr),
+    /// The node joined the cluster.
+    Added(SocketAddr),
+    /// The node left the cluster.
+    Removed(SocketAddr),
+    /// The tokens of the node changed.
+    Moved(SocketAddr),
+}
+
+fn text_at(row: &Row, i: usize) -> Result<Option<String>> {
+    let text: Option<Text> = row.value_at(i)?;
+    Ok(text.map(|t| t.as_ref().to_string()))
+}
+
+struct Shared {
+    control: RefCell<ClientHandle>,
+    control_address: Cell<SocketAddr>,
+    /// Incremented whenever the control connection is lost, to ignore later signs of the loss
+    /// of a connection which was replaced already.
+    control_generation: Cell<usize>,
+    nodes: RefCell<HashMap<SocketAddr, Node>>,
+    partitioner: Cell<Option<Partitioner>>,
+    subscribers: RefCell<Vec<UnboundedSender<HostEvent>>>,
+    client: Client,
+    contact_points: Vec<SocketAddr>,
+    options: ConnectOptions,
+    reactor: Handle,
+    reconnection: Rc<ReconnectionPolicy>,
+}
+
+/// The nodes of a cluster, which are kept current using the events pushed to the control
+/// connection.
+///
+/// Clusters are cheap to clone, and all clones share the same state.
+#[derive(Clone)]
+pub struct Cluster {
+    shared: Rc<Shared>,
+}
+
+impl Cluster {
+    /// Open the control connection to the first reachable contact point, and read all nodes
+    /// of the cluster through it.
+    ///
+    /// If the control connection is lost later, `reconnection` decides how long to wait
+    /// between failed attempts to open it again.
+    pub fn connect(
+        client: Client,
+        contact_points: Vec<SocketAddr>,
+        handle: &Handle,
+        options: ConnectOptions,
+        reconnection: Rc<ReconnectionPolicy>,
+    ) -> Box<Future<Item = Cluster, Error = Error>> {
+        let reactor = handle.clone();
+        Box::new(
+            open_control(client.clone(), contact_points.clone(), handle.clone(), options.clone()).and_then(
+                move |(addr, control, events)| {
+                    let cluster = Cluster::new(client, contact_points, &reactor, options, reconnection, addr, control);
+                    watch_control(&cluster.shared, events);
+                    cluster.refresh().map(|_| cluster)
+                },
+            ),
+        )
+    }
+
+    /// A cluster without any known nodes, whose control connection to `addr` is `control`.
+    pub(crate) fn new(
+        client: Client,
+        contact_points: Vec<SocketAddr>,
+        handle: &Handle,
+        options: ConnectOptions,
+        reconnection: Rc<ReconnectionPolicy>,
+        addr: SocketAddr,
+        control: ClientHandle,
+    ) -> Cluster {
+        Cluster {
+            shared: Rc::new(Shared {
+                control: RefCell::new(control),
+                control_address: Cell::new(addr),
+                control_generation: Cell::new(0),
+                nodes: RefCell::new(HashMap::new()),
+                partitioner: Cell::new(None),
+                subscribers: RefCell::new(Vec::new()),
+                client: client,
+                contact_points: contact_points,
+                options: options,
+                reactor: handle.clone(),
+                reconnection: reconnection,
+            }),
+        }
+    }
+
+    /// The address of the node the control connection is connected to.
+    pub fn control_address(&self) -> SocketAddr {
+        self.shared.control_address.get()
+    }
+
+    /// A stream of all changes of the state of nodes from now on.
+    pub fn host_events(&self) -> UnboundedReceiver<HostEvent> {
+        let (tx, rx) = mpsc::unbounded();
+        self.shared.subscribers.borrow_mut().push(tx);
+        rx
+    }
+
+    /// Mark the node at `address` as up or down, which is used if the state was observed by
+    /// other means than the events of the server, like losing all connections to the node.
+    pub fn set_up(&self, address: &SocketAddr, up: bool) {
+        set_up(&self.shared, address, up)
+    }
+
+    /// All nodes currently known, ordered by address.
+    pub fn nodes(&self) -> Vec<Node> {
+        let mut nodes: Vec<_> = self.shared.nodes.borrow().values().cloned().collect();
+        nodes.sort_by_key(|n| n.address);
+        nodes
+    }
+
+    pub fn node(&self, address: &SocketAddr) -> Option<Node> {
+        self.shared.nodes.borrow().get(address).cloned()
+    }
+
+    /// The partitioner of the cluster, or `None` if it is not supported.
+    pub fn partitioner(&self) -> Option<Partitioner> {
+        self.shared.partitioner.get()
+    }
+
+    /// A token ring of all nodes currently known, without any replication strategies.
+    pub fn token_ring(&self) -> Result<TokenRing> {
+        match self.partitioner() {
+            Some(partitioner) => TokenRing::new(partitioner, &self.nodes()),
+            None => Err(io_err("The partitioner of the cluster is not supported").into()),
+        }
+    }
+
+    /// A token ring of all nodes currently known, which uses the replication strategies of
+    /// all keyspaces, as read from `system_schema.keyspaces`. If they can't be read, the ring
+    /// has none.
+    pub fn token_ring_with_replication(&self) -> Box<Future<Item = TokenRing, Error = Error>> {
+        let cluster = self.clone();
+        Box::new(replication(&self.shared.control.borrow()).then(move |res| {
+            let mut ring = cluster.token_ring()?;
+            match res {
+                Ok(keyspaces) => {
+                    for (keyspace, strategy) in keyspaces {
+                        ring.set_replication(&keyspace, strategy);
+                    }
+                }
+                Err(err) => warn!("failed to read the replication of keyspaces: {}", err),
+            }
+            Ok(ring)
+        }))
+    }
+
+    /// Read all nodes from the `system.local` and `system.peers` tables again.
+    /// Nodes which were reported to be down remain so.
+    pub fn refresh(&self) -> Box<Future<Item = (), Error = Error>> {
+        refresh(&self.shared)
+    }
+}
+
+fn connect_any(
+    client: Client,
+    mut contact_points: Vec<SocketAddr>,
+    handle: Handle,
+    options: ConnectOptions,
+) -> Box<Future<Item = (SocketAddr, ClientHandle), Error = Error>> {
+    if contact_points.is_empty() {
+        return Box::new(future::err(io_err("No contact point could be reached").into()));
+    }
+    let addr = contact_points.remove(0);
+    Box::new(
+        client
+            .clone()
+            .connect(&addr, &handle, options.clone())
+            .map(move |control| (addr, control))
+            .or_else(move |err| {
+                warn!("failed to connect to contact point {}: {}", addr, err);
+                connect_any(client, contact_points, handle, options)
+            }),
+    )
+}
+
+/// Connect to the first reachable of `contact_points`, and register for the events needed to
+/// keep the nodes current.
+fn open_control(
+    client: Client,
+    contact_points: Vec<SocketAddr>,
+    handle: Handle,
+    options: ConnectOptions,
+) -> Box<Future<Item = (SocketAddr, ClientHandle, EventStream), Error = Error>> {
+    Box::new(connect_any(client, contact_points, handle, options).and_then(|(addr, mut control)| {
+        let events = control.take_events().expect("a new connection to have events");
+        control
+            .register(vec![EventType::TopologyChange, EventType::StatusChange])
+            .map(move |_| (addr, control, events))
+    }))
+}
+
+/// Open the control connection again, to any node which is up or one of the contact points.
+/// `attempts` is the amount of attempts which failed already.
+fn reconnect_control(shared: &Rc<Shared>, attempts: u32) {
+    let mut candidates: Vec<_> = shared
+        .nodes
+        .borrow()
+        .values()
+        .filter(|n| n.is_up)
+        .map(|n| n.address)
+        .collect();
+    candidates.sort();
+    for addr in &shared.contact_points {
+        if !candidates.contains(addr) {
+            candidates.push(*addr);
+        }
+    }
+    let weak = Rc::downgrade(shared);
+    let control = open_control(
+        shared.client.clone(),
+        candidates,
+        shared.reactor.clone(),
+        shared.options.clone(),
+    );
+    shared.reactor.spawn(control.then(move |res| -> Box<Future<Item = (), Error = ()>> {
+        let shared = match weak.upgrade() {
+            Some(shared) => shared,
+            None => return Box::new(future::ok(())),
+        };
+        match res {
+            Ok((addr, control, events)) => {
+                debug!("opened control connection to {}", addr);
+                *shared.control.borrow_mut() = control;
+                shared.control_address.set(addr);
+                watch_control(&shared, events);
+                Box::new(refresh(&shared).or_else(|err| {
+                    warn!("failed to refresh nodes after reconnecting: {}", err);
+                    Ok(())
+                }))
+            }
+            Err(err) => {
+                warn!("failed to reopen the control connection: {}", err);
+                let delay = shared.reconnection.next_delay(attempts);
+                let timeout = match Timeout::new(delay, &shared.reactor) {
+                    Ok(timeout) => timeout,
+                    Err(err) => {
+                        warn!("failed to schedule reopening the control connection: {}", err);
+                        return Box::new(future::ok(()));
+                    }
+                };
+                let weak = Rc::downgrade(&shared);
+                Box::new(timeout.then(move |_| {
+                    if let Some(shared) = weak.upgrade() {
+                        reconnect_control(&shared, attempts + 1);
+                    }
+                    Ok(())
+                }))
+            }
+        }
+    }));
+}
+
+/// Handle the events of the current control connection, and send heartbeats through it.
+fn watch_control(shared: &Rc<Shared>, events: EventStream) {
+    let generation = shared.control_generation.get();
+    shared.reactor.spawn(handle_events(Rc::downgrade(shared), events, generation));
+    if let Some(interval) = shared.control.borrow().heartbeat_interval() {
+        schedule_heartbeat(shared, generation, interval);
+    }
+}
+
+/// Open the control connection again, unless that happened already since the lost connection
+/// was opened as `generation`.
+fn control_lost(shared: &Rc<Shared>, generation: usize) {
+    if shared.control_generation.get() != generation {
+        return;
+    }
+    shared.control_generation.set(generation + 1);
+    reconnect_control(shared, 0);
+}
+
+/// Send a heartbeat through the control connection once it was idle for `interval`, and open
+/// it again if the heartbeat isn't answered.
+fn schedule_heartbeat(shared: &Rc<Shared>, generation: usize, interval: Duration) {
+    let idle = shared.control.borrow().idle_time();
+    let timeout = match Timeout::new(interval.checked_sub(idle).unwrap_or_default(), &shared.reactor) {
+        Ok(timeout) => timeout,
+        Err(err) => {
+            warn!("failed to schedule heartbeat of the control connection: {}", err);
+            return;
+        }
+    };
+    let weak = Rc::downgrade(shared);
+    shared.reactor.spawn(timeout.then(move |_| -> Box<Future<Item = (), Error = ()>> {
+        let shared = match weak.upgrade() {
+            Some(ref shared) if shared.control_generation.get() == generation => shared.clone(),
+            _ => return Box::new(future::ok(())),
+        };
+        if shared.control.borrow().idle_time() < interval {
+            schedule_heartbeat(&shared, generation, interval);
+            return Box::new(future::ok(()));
+        }
+        let heartbeat = shared.control.borrow().heartbeat();
+        let weak = Rc::downgrade(&shared);
+        Box::new(heartbeat.then(move |res| {
+            if let Some(shared) = weak.upgrade() {
+                match res {
+                    Ok(()) => schedule_heartbeat(&shared, generation, interval),
+                    Err(err) => {
+                        warn!("the control connection is defunct: {}", err);
+                        control_lost(&shared, generation);
+                    }
+                }
+            }
+            Ok(())
+        }))
+    }));
+}
+
+fn emit(shared: &Shared, event: HostEvent) {
+    debug!("host event: {:?}", event);
+    shared
+        .subscribers
+        .borrow_mut()
+        .retain(|s| s.unbounded_send(event).is_ok());
+}
+
+fn set_up(shared: &Shared, address: &SocketAddr, up: bool) {
+    let changed = match shared.nodes.borrow_mut().get_mut(address) {
+        Some(ref mut node) if node.is_up != up => {
+            node.is_up = up;
+            true
+        }
+        _ => false,
+    };
+    if changed {
+        emit(shared, if up { HostEvent::Up(*address) } else { HostEvent::Down(*address) });
+    }
+}
+
+fn query(control: &ClientHandle, query: &'static str) -> Box<Future<Item = Vec<Row>, Error = Error>> {
+    let msg = request::Message::Query(QueryMessage {
+        query: CqlLongString::try_from(query).expect("a valid query"),
+        parameters: QueryParameters::default(),
+        tracing: false,
+        routing_key: None,
+        idempotent: true,
+        timeout: None,
+    });
+    Box::new(control.call(msg).map_err(|e| e.into()).and_then(
+        |res| into_rows(res, "discovering nodes"),
+    ))
+}
+
+/// The replication strategy of each keyspace. Keyspaces whose strategy can't be interpreted are
+/// left out.
+fn replication(control: &ClientHandle) -> Box<Future<Item = HashMap<String, ReplicationStrategy>, Error = Error>> {
+    Box::new(query(control, KEYSPACES_QUERY).and_then(|rows| {
+        let mut keyspaces = HashMap::new();
+        for row in &rows {
+            let keyspace: Text = row.value_at(0)?;
+            let options: Option<Map<Text, Text>> = row.value_at(1)?;
+            let options: HashMap<String, String> = match options {
+                Some(options) => {
+                    let entries = options.entries().map_err(response::Error::from)?;
+                    entries
+                        .into_iter()
+                        .filter_map(|(k, v)| v.map(|v| (k.as_ref().to_string(), v.as_ref().to_string())))
+                        .collect()
+                }
+                None => HashMap::new(),
+            };
+            match ReplicationStrategy::from_options(&options) {
+                Some(strategy) => {
+                    keyspaces.insert(keyspace.as_ref().to_string(), strategy);
+                }
+                None => warn!("ignoring invalid replication of keyspace {}: {:?}", keyspace.as_ref(), options),
+            }
+        }
+        Ok(keyspaces)
+    }))
+}
+
+fn refresh(shared: &Rc<Shared>) -> Box<Future<Item = (), Error = Error>> {
+    let weak = Rc::downgrade(shared);
+    let local = query(&shared.control.borrow(), LOCAL_QUERY);
+    let peers = query(&shared.control.borrow(), PEERS_QUERY);
+    Box::new(local.join(peers).and_then(move |(local, peers)| {
+        let shared = match weak.upgrade() {
+            Some(shared) => shared,
+            None => return Ok(()),
+        };
+        let control_address = shared.control_address.get();
+        let port = control_address.port();
+        let mut nodes = HashMap::new();
+        if let Some(row) = local.first() {
+            let partitioner = text_at(row, 5)?;
+            shared.partitioner.set(partitioner.as_ref().and_then(|p| Partitioner::from_class_name(p)));
+            let node = Node::from_row(control_address, row, 0)?;
+            nodes.insert(node.address, node);
+        }
+        for row in &peers {
+            let peer: Inet = row.value_at(0)?;
+            let rpc_address: Option<Inet> = row.value_at(1)?;
+            // Nodes listening on all interfaces are reachable through their peer address.
+            let ip = match rpc_address.map(IpAddr::from) {
+                Some(ip) if !ip.is_unspecified() => ip,
+                _ => IpAddr::from(peer),
+            };
+            let node = Node::from_row(SocketAddr::new(ip, port), row, 2)?;
+            nodes.insert(node.address, node);
+        }
+        let mut changes = Vec::new();
+        {
+            let mut known = shared.nodes.borrow_mut();
+            for (address, node) in &mut nodes {
+                match known.get(address) {
+                    Some(previous) => {
+                        node.is_up = previous.is_up;
+                        let tokens: HashSet<_> = node.tokens.iter().collect();
+                        if previous.tokens.iter().collect::<HashSet<_>>() != tokens {
+                            changes.push(HostEvent::Moved(*address));
+                        }
+                    }
+                    None => changes.push(HostEvent::Added(*address)),
+                }
+            }
+            changes.extend(known.keys().filter(|a| !nodes.contains_key(a)).map(|a| HostEvent::Removed(*a)));
+            debug!("discovered {} nodes", nodes.len());
+            *known = nodes;
+        }
+        for event in changes {
+            emit(&shared, event);
+        }
+        Ok(())
+    }))
+}
+
+/// Apply all events to the nodes of `shared`, until the cluster is gone. Once the control
+/// connection opened as `generation` is closed, it is opened again.
+fn handle_events<S>(shared: Weak<Shared>, events: S, generation: usize) -> Box<Future<Item = (), Error = ()>>
+where
+    S: Stream<Item = EventMessage, Error = ()> + 'static,
+{
+    let cluster = shared.clone();
+    let events = events.for_each(move |event| -> Box<Future<Item = (), Error = ()>> {
+        let shared = match shared.upgrade() {
+            Some(shared) => shared,
+            None => return Box::new(future::err(())),
+        };
+        match event {
+            EventMessage::TopologyChange(TopologyChangeEvent {
+                change_type: TopologyChangeType::RemovedNode,
+                node,
+            }) => {
+                let removed = shared.nodes.borrow_mut().remove(&node).is_some();
+                if removed {
+                    emit(&shared, HostEvent::Removed(node));
+                }
+            }
+            EventMessage::TopologyChange(_) => {
+                return Box::new(refresh(&shared).or_else(|err| {
+                    warn!("failed to refresh nodes after topology change: {}", err);
+                    Ok(())
+                }));
+            }
+            EventMessage::StatusChange(change) => {
+                set_up(&shared, &change.node, change.change_type == StatusChangeType::Up);
+            }
+            EventMessage::SchemaChange(_) => {}
+        }
+        Box::new(future::ok(()))
+    });
+    Box::new(events.then(move |res| {
+        if let (Ok(()), Some(shared)) = (res, cluster.upgrade()) {
+            debug!("control connection opened as generation {} was closed", generation);
+            control_lost(&shared, generation);
+        }
+        Ok(())
+    }))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use bytes::BytesMut;
+    use codec::header::ProtocolVersion;
+    use codec::primitives::{encode, CqlBytes, CqlStringMultiMap};
+    use codec::primitives::datatypes::ToCql;
+    use codec::response::{ResponseInfo, ResultMessage, RowsMetadata, StatusChangeEvent, SupportedMessage};
+    use tokio_core::reactor::Core;
+    use std::io;
+    use super::super::client::CqlProto;
+    use super::super::messages::StreamingMessage;
+    use super::super::partitioner::Token;
+    use super::super::policies::ExponentialReconnection;
+
+    fn address(ip: &str) -> SocketAddr {
+        SocketAddr::new(ip.parse().unwrap(), 9042)
+    }
+
+    /// A cluster whose control connection to 10.0.0.1 answers requests with `respond`. It has
+    /// no contact points, which is why it can't be opened again once lost.
+    fn cluster<F>(core: &Core, interval: Duration, respond: F) -> (Cluster, UnboundedSender<EventMessage>)
+    where
+        F: Fn(request::Message) -> io::Result<StreamingMessage> + 'static,
+    {
+        let options = ConnectOptions {
+            heartbeat_interval: Some(interval),
+            ..Default::default()
+        };
+        let (control, events) = ClientHandle::fake(ProtocolVersion::Version3, &core.handle(), &options, respond);
+        let client = Client {
+            protocol: CqlProto {
+                version: ProtocolVersion::Version3,
+                debug: None,
+            },
+        };
+        let reconnection = Rc::new(ExponentialReconnection::default());
+        let cluster = Cluster::new(
+            client,
+            Vec::new(),
+            &core.handle(),
+            options,
+            reconnection,
+            address("10.0.0.1"),
+            control,
+        );
+        (cluster, events)
+    }
+
+    fn supported() -> StreamingMessage {
+        StreamingMessage::Supported(SupportedMessage(CqlStringMultiMap::try_from(HashMap::new()).unwrap()))
+    }
+
+    fn settle(core: &mut Core) {
+        for _ in 0..10 {
+            core.turn(Some(Duration::from_millis(1)));
+        }
+    }
+
+    #[test]
+    fn sends_heartbeats_through_idle_control_connection() {
+        let mut core = Core::new().unwrap();
+        let interval = Duration::from_secs(60);
+        let heartbeats = Rc::new(Cell::new(0));
+        let count = heartbeats.clone();
+        let (cluster, _events) = cluster(&core, interval, move |_| {
+            count.set(count.get() + 1);
+            Ok(supported())
+        });
+
+        schedule_heartbeat(&cluster.shared, 0, interval);
+        settle(&mut core);
+        assert_eq!(heartbeats.get(), 0);
+
+        cluster.shared.control.borrow().set_idle_time(interval);
+        schedule_heartbeat(&cluster.shared, 0, interval);
+        settle(&mut core);
+        assert_eq!(heartbeats.get(), 1);
+        assert_eq!(cluster.shared.control_generation.get(), 0);
+    }
+
+    #[test]
+    fn reopens_control_connection_which_misses_heartbeats() {
+        let mut core = Core::new().unwrap();
+        let (cluster, _events) = cluster(&core, Duration::from_millis(10), |_| {
+            Err(io::Error::new(io::ErrorKind::TimedOut, "timed out"))
+        });
+
+        schedule_heartbeat(&cluster.shared, 0, Duration::from_millis(10));
+        let wait = Timeout::new(Duration::from_millis(50), &core.handle()).unwrap();
+        core.run(wait).unwrap();
+        assert_eq!(cluster.shared.control_generation.get(), 1);
+
+        // Heartbeats of the lost connection are ignored.
+        schedule_heartbeat(&cluster.shared, 0, Duration::from_millis(10));
+        let wait = Timeout::new(Duration::from_millis(50), &core.handle()).unwrap();
+        core.run(wait).unwrap();
+        assert_eq!(cluster.shared.control_generation.get(), 1);
+    }
+
+    pub fn rows(rows: &[Vec<CqlBytes>]) -> StreamingMessage {
+        let meta = RowsMetadata {
+            columns_count: rows.first().map_or(0, |r| r.len() as i32),
+            rows_count: rows.len() as i32,
+            ..Default::default()
+        };
+        let rows = rows.iter()
+            .map(|values| {
+                let mut buf = BytesMut::with_capacity(128);
+                for value in values {
+                    encode::bytes(value, &mut buf);
+                }
+                Row::decode(buf, &meta).unwrap().1.unwrap()
+            })
+            .collect();
+        StreamingMessage::Result(ResultMessage::Rows { rows: rows, meta: meta }, ResponseInfo::default())
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    /// The row of 10.0.0.1 in `system.local`, which owns the tokens -100 and 100.
+    pub fn local() -> Vec<CqlBytes> {
+        vec![
+            "dc1".to_cql(),
+            "rack1".to_cql(),
+            vec!["-100", "100"].to_cql(),
+            "3.11.4".to_cql(),
+            CqlBytes::null_value(),
+            "org.apache.cassandra.dht.Murmur3Partitioner".to_cql(),
+        ]
+    }
+
+    pub fn peer(peer: &str, rpc_address: &str, token: &str) -> Vec<CqlBytes> {
+        vec![
+            ip(peer).to_cql(),
+            ip(rpc_address).to_cql(),
+            "dc2".to_cql(),
+            CqlBytes::null_value(),
+            vec![token].to_cql(),
+            "3.11.4".to_cql(),
+            CqlBytes::null_value(),
+        ]
+    }
+
+    /// The row of `keyspace` in `system_schema.keyspaces`, which uses the SimpleStrategy with
+    /// `replication_factor`.
+    pub fn keyspace(keyspace: &str, replication_factor: &str) -> Vec<CqlBytes> {
+        let mut replication = HashMap::new();
+        replication.insert("class", "org.apache.cassandra.locator.SimpleStrategy");
+        replication.insert("replication_factor", replication_factor);
+        vec![keyspace.to_cql(), replication.to_cql()]
+    }
+
+    /// Answer the queries reading the nodes and keyspaces of a cluster with the local node
+    /// 10.0.0.1, the rows of `peers` and the rows of `keyspaces`.
+    pub fn describe(
+        req: request::Message,
+        peers: &[Vec<CqlBytes>],
+        keyspaces: &[Vec<CqlBytes>],
+    ) -> io::Result<StreamingMessage> {
+        match req {
+            request::Message::Query(ref query) if query.query.as_ref() == LOCAL_QUERY => Ok(rows(&[local()])),
+            request::Message::Query(ref query) if query.query.as_ref() == PEERS_QUERY => Ok(rows(peers)),
+            request::Message::Query(ref query) if query.query.as_ref() == KEYSPACES_QUERY => Ok(rows(keyspaces)),
+            req => panic!("unexpected request {:?}", req),
+        }
+    }
+
+    /// A cluster whose control connection answers the queries of `refresh()` with the local
+    /// node 10.0.0.1 and the rows of `peers`.
+    fn discovering(core: &Core, peers: Rc<RefCell<Vec<Vec<CqlBytes>>>>) -> (Cluster, UnboundedSender<EventMessage>) {
+        let keyspaces = vec![keyspace("ks", "2"), keyspace("invalid", "x")];
+        cluster(core, Duration::from_secs(60), move |req| describe(req, &peers.borrow(), &keyspaces))
+    }
+
+    fn received(core: &mut Core, events: &mut UnboundedReceiver<HostEvent>, count: u64) -> Vec<HostEvent> {
+        core.run(events.by_ref().take(count).collect()).unwrap()
+    }
+
+    #[test]
+    fn refreshes_nodes() {
+        let mut core = Core::new().unwrap();
+        let peers = Rc::new(RefCell::new(vec![
+            peer("10.0.0.2", "0.0.0.0", "0"),
+            peer("10.0.0.3", "192.168.0.3", "50"),
+        ]));
+        let (cluster, _events) = discovering(&core, peers.clone());
+        let mut events = cluster.host_events();
+
+        core.run(cluster.refresh()).unwrap();
+        let nodes = cluster.nodes();
+        assert_eq!(
+            nodes.iter().map(|n| n.address).collect::<Vec<_>>(),
+            vec![address("10.0.0.1"), address("10.0.0.2"), address("192.168.0.3")]
+        );
+        assert_eq!(nodes[0].datacenter, Some(String::from("dc1")));
+        assert_eq!(nodes[0].rack, Some(String::from("rack1")));
+        let mut tokens = nodes[0].tokens.clone();
+        tokens.sort();
+        assert_eq!(tokens, vec![String::from("-100"), String::from("100")]);
+        assert_eq!(nodes[1].datacenter, Some(String::from("dc2")));
+        assert_eq!(nodes[1].rack, None);
+        assert_eq!(nodes[2].tokens, vec![String::from("50")]);
+        assert_eq!(nodes[2].release_version, Some(String::from("3.11.4")));
+        assert!(nodes.iter().all(|n| n.is_up && n.host_id.is_none()));
+        assert_eq!(cluster.partitioner(), Some(Partitioner::Murmur3));
+        let mut added = received(&mut core, &mut events, 3);
+        added.sort_by_key(|e| format!("{:?}", e));
+        assert_eq!(
+            added,
+            vec![
+                HostEvent::Added(address("10.0.0.1")),
+                HostEvent::Added(address("10.0.0.2")),
+                HostEvent::Added(address("192.168.0.3")),
+            ]
+        );
+
+        cluster.set_up(&address("10.0.0.2"), false);
+        peers.borrow_mut().pop();
+        core.run(cluster.refresh()).unwrap();
+        let nodes = cluster.nodes();
+        assert_eq!(nodes.len(), 2);
+        assert!(!nodes[1].is_up);
+        assert_eq!(
+            received(&mut core, &mut events, 2),
+            vec![HostEvent::Down(address("10.0.0.2")), HostEvent::Removed(address("192.168.0.3"))]
+        );
+    }
+
+    #[test]
+    fn builds_token_ring_with_replication() {
+        let mut core = Core::new().unwrap();
+        let peers = Rc::new(RefCell::new(vec![peer("10.0.0.2", "0.0.0.0", "0")]));
+        let (cluster, _events) = discovering(&core, peers);
+        core.run(cluster.refresh()).unwrap();
+
+        let ring = core.run(cluster.token_ring_with_replication()).unwrap();
+        let token = Token::Murmur3(-50);
+        assert_eq!(ring.replicas_of_token(Some("ks"), &token), vec![address("10.0.0.2"), address("10.0.0.1")]);
+        // Keyspaces with an invalid replication only use the owner of a token.
+        assert_eq!(ring.replicas_of_token(Some("invalid"), &token), vec![address("10.0.0.2")]);
+    }
+
+    #[test]
+    fn handles_events() {
+        let mut core = Core::new().unwrap();
+        let peers = Rc::new(RefCell::new(vec![peer("10.0.0.2", "0.0.0.0", "0")]));
+        let (cluster, _events) = discovering(&core, peers.clone());
+        core.run(cluster.refresh()).unwrap();
+        let mut events = cluster.host_events();
+        let (tx, rx) = mpsc::unbounded();
+        core.handle().spawn(handle_events(Rc::downgrade(&cluster.shared), rx, 0));
+
+        let status = |change_type, ip: &str| {
+            EventMessage::StatusChange(StatusChangeEvent {
+                change_type: change_type,
+                node: address(ip),
+            })
+        };
+        let topology = |change_type, ip: &str| {
+            EventMessage::TopologyChange(TopologyChangeEvent {
+                change_type: change_type,
+                node: address(ip),
+            })
+        };
+        tx.unbounded_send(status(StatusChangeType::Down, "10.0.0.2")).unwrap();
+        assert_eq!(received(&mut core, &mut events, 1), vec![HostEvent::Down(address("10.0.0.2"))]);
+        assert!(!cluster.node(&address("10.0.0.2")).unwrap().is_up);
+
+        // Events of unknown nodes, or which don't change anything, are ignored.
+        tx.unbounded_send(status(StatusChangeType::Down, "10.0.0.2")).unwrap();
+        tx.unbounded_send(status(StatusChangeType::Up, "10.0.0.9")).unwrap();
+        tx.unbounded_send(status(StatusChangeType::Up, "10.0.0.2")).unwrap();
+        assert_eq!(received(&mut core, &mut events, 1), vec![HostEvent::Up(address("10.0.0.2"))]);
+        assert!(cluster.node(&address("10.0.0.2")).unwrap().is_up);
+
+        peers.borrow_mut().push(peer("10.0.0.4", "10.0.0.4", "20"));
+        tx.unbounded_send(topology(TopologyChangeType::NewNode, "10.0.0.4")).unwrap();
+        assert_eq!(received(&mut core, &mut events, 1), vec![HostEvent::Added(address("10.0.0.4"))]);
+        assert_eq!(cluster.node(&address("10.0.0.4")).unwrap().tokens, vec![String::from("20")]);
+
+        peers.borrow_mut()[1] = peer("10.0.0.4", "10.0.0.4", "30");
+        tx.unbounded_send(topology(TopologyChangeType::MovedNode, "10.0.0.4")).unwrap();
+        assert_eq!(received(&mut core, &mut events, 1), vec![HostEvent::Moved(address("10.0.0.4"))]);
+        assert_eq!(cluster.node(&address("10.0.0.4")).unwrap().tokens, vec![String::from("30")]);
+
+        tx.unbounded_send(topology(TopologyChangeType::RemovedNode, "10.0.0.2")).unwrap();
+        assert_eq!(received(&mut core, &mut events, 1), vec![HostEvent::Removed(address("10.0.0.2"))]);
+        assert_eq!(cluster.nodes().len(), 2);
+
+        drop(tx);
+        settle(&mut core);
+        assert_eq!(cluster.shared.control_generation.get(), 1);
+    }
+}
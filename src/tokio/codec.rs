@@ -1,26 +1,104 @@
 use codec::request::{self, cql_encode};
-use codec::header::{Header, ProtocolVersion, Direction};
+use codec::header::{self, Header, ProtocolVersion, Direction, Version};
 use std::path::PathBuf;
 use std::fs::{File, OpenOptions};
 use tokio_proto::streaming::multiplex::{RequestId, Frame};
 use tokio_io::codec::{Decoder, Encoder};
 use bytes::BytesMut;
-use std::{io, mem};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::{cmp, i16, io, mem};
 use std::io::Write;
 use codec::header::OpCode;
 use codec::response::{self, CqlDecode};
+use codec::compression::Compression;
+use futures::sync::mpsc::UnboundedSender;
 use super::utils::io_err;
 
 
 // FIXME - don't use pub here, fix imports
 pub use super::messages::*;
 pub use super::error::*;
-#[derive(PartialEq, Debug, Clone)]
+/// The stream id the server uses for all EVENT messages it pushes.
+const EVENT_STREAM_ID: i16 = -1;
+
+#[derive(Debug, Clone)]
 pub struct CqlCodec {
     state: Machine,
     flags: u8,
     version: ProtocolVersion,
     debug: CqlCodecDebuggingOptions,
+    events: Option<UnboundedSender<response::EventMessage>>,
+    compression: Option<Compression>,
+    stream_ids: StreamIds,
+    orphaned: OrphanedRequests,
+}
+
+/// Once this many requests timed out without their responses having arrived, the connection is
+/// closed by failing the next request sent through it.
+const MAX_ORPHANED_REQUESTS: usize = 256;
+
+/// Counts the requests of a connection which timed out, and whose responses did not arrive yet.
+/// Their stream ids remain in use until then, see `StreamIds`.
+///
+/// The `ClientHandle` of the connection keeps the count, which its codec checks.
+#[derive(Debug, Clone, Default)]
+pub struct OrphanedRequests(Rc<Cell<usize>>);
+
+impl OrphanedRequests {
+    pub fn add(&self) {
+        self.0.set(self.0.get() + 1);
+    }
+
+    /// Called once the response to an orphaned request arrived, or the connection was closed.
+    pub fn remove(&self) {
+        self.0.set(self.0.get() - 1);
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.get()
+    }
+}
+
+/// Maps the ids tokio-proto assigns to requests, which grow without bounds, to the stream ids
+/// of the protocol.
+///
+/// A stream id is only used again once the response to it was received, even if the request
+/// was cancelled or timed out, as a late response would otherwise be mistaken for the response
+/// to the next request using it. The id of a request whose response never arrives is never
+/// released, which is why the connection is closed once `MAX_ORPHANED_REQUESTS` requests timed
+/// out that way.
+#[derive(Debug, Clone, Default)]
+struct StreamIds {
+    requests: HashMap<i16, RequestId>,
+    next: i16,
+}
+
+impl StreamIds {
+    fn allocate(&mut self, request: RequestId) -> io::Result<i16> {
+        if self.requests.len() > i16::MAX as usize {
+            return Err(io_err("All stream ids are in use by requests in flight"));
+        }
+        // Ids are handed out in turn, so the one of a response which just arrived is not
+        // reused right away.
+        loop {
+            let id = self.next;
+            self.next = if id == i16::MAX { 0 } else { id + 1 };
+            if !self.requests.contains_key(&id) {
+                self.requests.insert(id, request);
+                return Ok(id);
+            }
+        }
+    }
+
+    fn request(&self, stream_id: i16) -> Option<RequestId> {
+        self.requests.get(&stream_id).cloned()
+    }
+
+    fn release(&mut self, stream_id: i16) -> Option<RequestId> {
+        self.requests.remove(&stream_id)
+    }
 }
 
 #[derive(PartialEq, Debug, Clone, Default)]
@@ -30,10 +108,26 @@ pub struct CqlCodecDebuggingOptions {
     pub frames_count: usize,
 }
 
+/// Rows results with a larger body are streamed, instead of waiting for the whole frame.
+const STREAMING_THRESHOLD: usize = 64 * 1024;
+
 #[derive(PartialEq, Debug, Clone)]
 enum Machine {
     NeedHeader,
-    WithHeader { header: Header, body_len: usize },
+    WithHeader {
+        header: Header,
+        body_len: usize,
+        /// False if the frame has to be decoded once it was received completely.
+        streamable: bool,
+    },
+    StreamingRows {
+        id: RequestId,
+        stream_id: i16,
+        meta: response::RowsMetadata,
+        rows_left: i32,
+        /// The amount of bytes of the frame body which were not consumed yet.
+        body_left: usize,
+    },
 }
 
 impl CqlCodec {
@@ -43,9 +137,109 @@ impl CqlCodec {
             flags: 0,
             version: v,
             debug: debug,
+            events: None,
+            compression: None,
+            stream_ids: StreamIds::default(),
+            orphaned: OrphanedRequests::default(),
+        }
+    }
+
+    /// Close the connection once too many of the requests counted by `orphaned` are pending.
+    pub fn with_orphaned_requests(mut self, orphaned: OrphanedRequests) -> Self {
+        self.orphaned = orphaned;
+        self
+    }
+
+    /// Server pushes will be sent to the given sink instead of being dropped.
+    pub fn with_event_sink(mut self, events: UnboundedSender<response::EventMessage>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    fn dispatch_event(&mut self, version: ProtocolVersion, buf: BytesMut) -> io::Result<()> {
+        let event = response::EventMessage::decode(version, buf).map_err(io_err)?;
+        debug!("decoded event: {:?}", event);
+        let receiver_gone = match self.events {
+            Some(ref sink) => sink.unbounded_send(event).is_err(),
+            None => false,
+        };
+        if receiver_gone {
+            self.events = None;
+        }
+        Ok(())
+    }
+
+    /// Replace the body of the frame encoded at `start` with its compressed version.
+    fn compress_frame(&self, c: Compression, start: usize, dst: &mut BytesMut) -> io::Result<()> {
+        let body_start = start + Header::encoded_len();
+        let body = c.compress(&dst[body_start..]).map_err(io_err)?;
+        let mut h = Header::try_from(&dst[start..body_start]).map_err(io_err)?;
+        h.flags |= header::COMPRESSION_FLAG;
+        h.length = body.len() as u32;
+        dst[start..body_start].copy_from_slice(&h.encode().map_err(io_err)?);
+        dst.truncate(body_start);
+        dst.extend_from_slice(&body);
+        Ok(())
+    }
+
+    fn decompress_body(&self, body: BytesMut) -> io::Result<BytesMut> {
+        match self.compression {
+            Some(c) => c.decompress(&body).map(BytesMut::from).map_err(io_err),
+            None => Err(io_err(
+                "Received a compressed frame, but no compression was negotiated",
+            )),
         }
     }
 
+    fn is_streamable(&self, h: &Header) -> bool {
+        h.op_code == OpCode::Result && self.stream_ids.request(h.stream_id as i16).is_some() &&
+            !h.is_compressed() &&
+            h.version.version == self.version &&
+            h.length as usize > STREAMING_THRESHOLD &&
+            self.debug.dump_decoded_frames_into.is_none()
+    }
+
+    /// Decode the beginning of a partially received Rows result and switch to streaming its
+    /// rows. Returns `None` if the body is no Rows result, or its metadata was not received yet.
+    fn start_streaming(&mut self, h: &Header, src: &mut BytesMut) -> Option<CodecInputFrame> {
+        let available = cmp::min(src.len(), h.length as usize);
+        let body = BytesMut::from(&src[..available]);
+        let (body, info) = match response::ResponseInfo::decode(h, body) {
+            Ok(res) => res,
+            Err(_) => return None,
+        };
+        let (body, meta) = match response::ResultHeader::decode(h.version.version, body) {
+            Ok((body, Some(response::ResultHeader::Rows(meta)))) => (body, meta),
+            _ => return None,
+        };
+        log_warnings(h, &info);
+        let consumed = available - body.len();
+        src.split_to(consumed);
+
+        let id = self.stream_ids.request(h.stream_id as i16).expect("streamable frames to be requested");
+        self.state = Machine::StreamingRows {
+            id: id,
+            stream_id: h.stream_id as i16,
+            meta: meta.clone(),
+            rows_left: meta.rows_count,
+            body_left: h.length as usize - consumed,
+        };
+        let msg = Frame::Message {
+            id: id,
+            message: StreamingMessage::Result(
+                response::ResultMessage::Rows {
+                    rows: Vec::new(),
+                    meta: meta,
+                },
+                info,
+            ),
+            body: true,
+            solo: false,
+        };
+        debug!("decoded head of streamed msg: {:?}", msg);
+        Some(msg)
+    }
+
     fn do_encode_debug(&mut self, buf: &mut BytesMut) -> io::Result<()> {
         if let Some(path) = self.debug.dump_encoded_frames_into.clone() {
             let h = Header::try_from(buf.as_ref()).expect("header encoded at beginning of buf");
@@ -76,6 +270,14 @@ impl CqlCodec {
     }
 }
 
+fn log_warnings(h: &Header, info: &response::ResponseInfo) {
+    if let Some(ref warnings) = info.warnings {
+        for warning in warnings.iter() {
+            warn!("server warning on stream-id {}: {}", h.stream_id, warning);
+        }
+    }
+}
+
 fn open_at(path: PathBuf) -> io::Result<File> {
     OpenOptions::new()
         .read(false)
@@ -114,15 +316,33 @@ impl Decoder for CqlCodec {
                     "As a client protocol, I can only handle response decoding"
                 );
                 let len = h.length;
+                let streamable = self.is_streamable(&h);
                 self.state = WithHeader {
                     header: h,
                     body_len: len as usize,
+                    streamable: streamable,
                 };
 
                 return self.decode(src);
             }
-            WithHeader { body_len, .. } => {
+            StreamingRows { .. } => self.decode_rows(src),
+            WithHeader { body_len, streamable, .. } => {
                 if body_len as usize > src.len() {
+                    // The metadata is expected to fit into the first part of the body.
+                    if streamable && src.len() >= STREAMING_THRESHOLD {
+                        let h = match self.state {
+                            WithHeader { ref header, .. } => header.clone(),
+                            _ => unreachable!(),
+                        };
+                        if let Some(frame) = self.start_streaming(&h, src) {
+                            return Ok(Some(frame));
+                        }
+                        self.state = WithHeader {
+                            header: h,
+                            body_len: body_len,
+                            streamable: false,
+                        };
+                    }
                     return Ok(None);
                 }
                 let h = match mem::replace(&mut self.state, NeedHeader) {
@@ -130,16 +350,58 @@ impl Decoder for CqlCodec {
                     _ => unreachable!(),
                 };
                 self.do_decode_debug(&h, &src, body_len)?;
-                /* TODO: implement version mismatch test */
                 let code = h.op_code.clone();
                 let version = h.version.version;
-                assert_stream_id(h.stream_id);
+                // Servers reject protocol versions they don't know with an ERROR in their own
+                // version, which is decoded as usual. Anything else is failed on its stream.
+                if version != self.version && code != OpCode::Error {
+                    src.split_to(body_len);
+                    let id = match self.response_id(&h) {
+                        Some(id) => id,
+                        None => return self.decode(src),
+                    };
+                    let err = header::ErrorKind::VersionMismatch(
+                        Version::request(self.version).encode(),
+                        Version::request(version).encode(),
+                    );
+                    return Ok(Some(Frame::Error {
+                        id: id,
+                        error: io::Error::new(io::ErrorKind::Other, header::Error::from(err)),
+                    }));
+                }
+                let body = src.split_to(body_len);
+                let body = if h.is_compressed() {
+                    self.decompress_body(body)?
+                } else {
+                    body
+                };
+                let (body, info) = response::ResponseInfo::decode(&h, body).map_err(io_err)?;
+                log_warnings(&h, &info);
+                match (h.stream_id as i16, code.clone()) {
+                    (EVENT_STREAM_ID, OpCode::Event) => {
+                        self.dispatch_event(version, body)?;
+                        return self.decode(src);
+                    }
+                    (id, _) if id < 0 => {
+                        return Err(io_err(format!(
+                            "Received {:?} on stream-id {}, which is reserved for server events",
+                            code,
+                            id
+                        )))
+                    }
+                    _ => {}
+                }
+                let id = match self.response_id(&h) {
+                    Some(id) => id,
+                    None => return self.decode(src),
+                };
                 let msg = Frame::Message {
-                    id: h.stream_id as RequestId,
+                    id: id,
                     /* TODO: verify amount of consumed bytes equals the ones actually parsed */
-                    message: decode_complete_message_by_opcode(version, code, src.split_to(body_len))
-                        .map_err(io_err)?
-                        .into(),
+                    message: match decode_complete_message_by_opcode(version, code, body).map_err(io_err)? {
+                        response::Message::Result(msg) => StreamingMessage::Result(msg, info),
+                        msg => msg.into(),
+                    },
                     body: false,
                     solo: false,
                 };
@@ -150,6 +412,69 @@ impl Decoder for CqlCodec {
     }
 }
 
+impl CqlCodec {
+    /// The id of the request a complete response belongs to, whose stream id is released.
+    fn response_id(&mut self, h: &Header) -> Option<RequestId> {
+        let id = self.stream_ids.release(h.stream_id as i16);
+        if id.is_none() {
+            warn!("dropping {:?} on stream-id {}, which no request is waiting for", h.op_code, h.stream_id);
+        }
+        id
+    }
+
+    /// Decode all rows of the streamed result which were received completely.
+    fn decode_rows(&mut self, src: &mut BytesMut) -> io::Result<Option<CodecInputFrame>> {
+        let (id, stream_id, finished, chunk) = match self.state {
+            Machine::StreamingRows {
+                id,
+                stream_id,
+                ref meta,
+                ref mut rows_left,
+                ref mut body_left,
+            } => {
+                let mut rows = Vec::new();
+                while *rows_left > 0 {
+                    let available = cmp::min(src.len(), *body_left);
+                    let len = match response::Row::encoded_len(&src[..available], meta) {
+                        Some(len) => len,
+                        None if available == *body_left => {
+                            return Err(io_err(format!(
+                                "Rows result on stream-id {} ended with {} rows left",
+                                stream_id,
+                                rows_left
+                            )))
+                        }
+                        None => break,
+                    };
+                    let (_, row) = response::Row::decode(src.split_to(len), meta).map_err(io_err)?;
+                    rows.push(row.expect("complete row"));
+                    *rows_left -= 1;
+                    *body_left -= len;
+                }
+                // Anything following the rows is skipped, as it can't be interpreted.
+                let finished = *rows_left == 0 && src.len() >= *body_left;
+                if finished && rows.is_empty() {
+                    src.split_to(*body_left);
+                }
+                (id, stream_id, finished, rows)
+            }
+            _ => unreachable!(),
+        };
+        if !chunk.is_empty() {
+            return Ok(Some(Frame::Body {
+                id: id,
+                chunk: Some(ChunkedMessage::Rows(chunk)),
+            }));
+        }
+        if finished {
+            self.state = Machine::NeedHeader;
+            self.stream_ids.release(stream_id);
+            return Ok(Some(Frame::Body { id: id, chunk: None }));
+        }
+        Ok(None)
+    }
+}
+
 impl Encoder for CqlCodec {
     type Item = CodecOutputFrame;
     type Error = io::Error;
@@ -158,16 +483,39 @@ impl Encoder for CqlCodec {
         match item {
             Frame::Message { id, message, .. } => {
                 debug!("encoded msg: {:?}", message);
-                assert_stream_id(id as u16);
-                let res = cql_encode(
-                    self.version,
-                    self.flags,
-                    id as u16, /* FIXME safe cast */
-                    message,
-                    dst,
-                ).map_err(io_err);
-                self.do_encode_debug(dst)?;
-                res
+                let orphaned = self.orphaned.count();
+                if orphaned >= MAX_ORPHANED_REQUESTS {
+                    return Err(io_err(format!(
+                        "Closing the connection, as {} requests timed out without a response",
+                        orphaned
+                    )));
+                }
+                let stream_id = self.stream_ids.allocate(id)?;
+                // The STARTUP message itself is never compressed, only what follows it.
+                let negotiated = match message {
+                    request::Message::Startup(ref msg) => Some(msg.compression.as_ref().and_then(
+                        |c| Compression::from_name(c.as_ref()),
+                    )),
+                    _ => None,
+                };
+                let start = dst.len();
+                let encoded = match cql_encode(self.version, self.flags, stream_id as u16, message, dst) {
+                    Ok(_) => match self.compression {
+                        Some(c) => self.compress_frame(c, start, dst),
+                        None => Ok(()),
+                    },
+                    Err(err) => Err(io_err(err)),
+                };
+                if let Err(err) = encoded {
+                    // The request is never sent, which is why no response will release its id.
+                    self.stream_ids.release(stream_id);
+                    dst.truncate(start);
+                    return Err(err);
+                }
+                if let Some(c) = negotiated {
+                    self.compression = c;
+                }
+                self.do_encode_debug(dst)
             }
             Frame::Error { error, .. } => Err(error),
             Frame::Body { .. } => panic!("Streaming of Requests is not currently supported"),
@@ -175,18 +523,6 @@ impl Encoder for CqlCodec {
     }
 }
 
-fn assert_stream_id(id: u16) {
-    // TODO This should not be an assertion, but just a result to be returned.
-    // The actual goal is to gain control over the domain of our request IDs, which right
-    // now is not present when clients use the service call interface.
-    // This should only be possible if there are more than i16::max_value() requests in flight!
-    assert!(
-        id as i16 > -1,
-        "stream-id {} was negative, which makes it a broadcast id with a special meaning",
-        id
-    );
-}
-
 fn decode_complete_message_by_opcode(
     version: ProtocolVersion,
     code: OpCode,
@@ -203,3 +539,258 @@ fn decode_complete_message_by_opcode(
         _ => panic!("need to implement all opcodes returned in the header"),
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codec::primitives::{encode, CqlBytes, CqlFrom};
+    use codec::primitives::datatypes::{Blob, TryFrom};
+    use codec::request::{QueryMessage, QueryValues};
+    use codec::response::{ResultMessage, ValueAt};
+
+    /// A codec waiting for the responses to request 7 on stream-id 0, and request 8 on
+    /// stream-id 1.
+    fn codec() -> CqlCodec {
+        let mut codec = CqlCodec::new(ProtocolVersion::Version3, CqlCodecDebuggingOptions::default());
+        codec.stream_ids.allocate(7).unwrap();
+        codec.stream_ids.allocate(8).unwrap();
+        codec
+    }
+
+    fn frame(version: Version, flags: u8, stream_id: u16, op_code: OpCode, body: &[u8]) -> BytesMut {
+        let h = Header {
+            version: version,
+            flags: flags,
+            stream_id: stream_id,
+            op_code: op_code,
+            length: body.len() as u32,
+        };
+        let mut buf = BytesMut::from(&h.encode().unwrap()[..]);
+        buf.extend_from_slice(body);
+        buf
+    }
+
+    /// The body of a Rows result without metadata, whose single column is `size` times
+    /// the index of the row. `rows` rows are announced, but only `sent` are included.
+    fn rows_body(rows: i32, sent: i32, size: usize) -> BytesMut {
+        let mut buf = BytesMut::with_capacity(16 + sent as usize * (size + 4));
+        encode::int(0x0002, &mut buf);
+        encode::int(0x0004, &mut buf);
+        encode::int(1, &mut buf);
+        encode::int(rows, &mut buf);
+        for i in 0..sent {
+            encode::bytes(&CqlBytes::try_from(vec![i as u8; size]).unwrap(), &mut buf);
+        }
+        buf
+    }
+
+    /// Feed `data` to `codec` in chunks of `chunk` bytes, and decode all frames after each
+    /// one. Returns the frames along with whether stream-id 0 was still taken after each.
+    fn decode_chunks(codec: &mut CqlCodec, data: &[u8], chunk: usize) -> io::Result<Vec<(CodecInputFrame, bool)>> {
+        let mut src = BytesMut::with_capacity(data.len());
+        let mut frames = Vec::new();
+        for part in data.chunks(chunk) {
+            src.extend_from_slice(part);
+            while let Some(frame) = codec.decode(&mut src)? {
+                frames.push((frame, codec.stream_ids.request(0).is_some()));
+            }
+        }
+        assert!(src.is_empty());
+        Ok(frames)
+    }
+
+    #[test]
+    fn streams_large_rows_results() {
+        let mut codec = codec();
+        let mut data = frame(Version::v3_response(), 0, 0, OpCode::Result, &rows_body(100, 100, 1000));
+        data.extend_from_slice(&frame(Version::v3_response(), 0, 1, OpCode::Ready, &[]));
+        let frames = decode_chunks(&mut codec, &data, 10_000).unwrap();
+        let mut frames = frames.into_iter();
+
+        match frames.next() {
+            Some((Frame::Message {
+                      id: 7,
+                      message: StreamingMessage::Result(ResultMessage::Rows { rows, meta }, _),
+                      body: true,
+                      ..
+                  },
+                  true)) => {
+                assert!(rows.is_empty());
+                assert_eq!(meta.rows_count, 100);
+            }
+            frame => panic!("Expected the head of the rows, got {:?}", frame),
+        }
+        let mut received = 0;
+        loop {
+            match frames.next() {
+                Some((Frame::Body { id: 7, chunk: Some(ChunkedMessage::Rows(rows)) }, true)) => {
+                    assert!(!rows.is_empty());
+                    for row in rows {
+                        let value: Blob = row.value_at(0).unwrap();
+                        assert_eq!(value, Blob::try_from(vec![received; 1000]).unwrap());
+                        received += 1;
+                    }
+                }
+                Some((Frame::Body { id: 7, chunk: None }, false)) => break,
+                frame => panic!("Expected rows, got {:?}", frame),
+            }
+        }
+        assert_eq!(received, 100);
+        assert_eq!(codec.state, Machine::NeedHeader);
+        match frames.next() {
+            Some((Frame::Message { id: 8, message: StreamingMessage::Ready, body: false, .. }, false)) => {}
+            frame => panic!("Expected the following frame, got {:?}", frame),
+        }
+        assert!(frames.next().is_none());
+        assert!(codec.stream_ids.requests.is_empty());
+    }
+
+    #[test]
+    fn streamed_rows_results_fail_if_rows_are_missing() {
+        let mut codec = codec();
+        let data = frame(Version::v3_response(), 0, 0, OpCode::Result, &rows_body(100, 99, 1000));
+        let err = decode_chunks(&mut codec, &data, 10_000).unwrap_err();
+        assert_eq!(format!("{}", err), "Rows result on stream-id 0 ended with 1 rows left");
+    }
+
+    #[test]
+    fn small_rows_results_are_not_streamed() {
+        let mut codec = codec();
+        let data = frame(Version::v3_response(), 0, 0, OpCode::Result, &rows_body(10, 10, 1000));
+        let frames = decode_chunks(&mut codec, &data, 1000).unwrap();
+        assert_eq!(frames.len(), 1);
+        match frames[0] {
+            (Frame::Message {
+                 id: 7,
+                 message: StreamingMessage::Result(ResultMessage::Rows { ref rows, .. }, _),
+                 body: false,
+                 ..
+             },
+             false) => assert_eq!(rows.len(), 10),
+            ref frame => panic!("Expected all rows at once, got {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn compressed_rows_results_are_not_streamed() {
+        let mut codec = codec();
+        let data = frame(
+            Version::v3_response(),
+            header::COMPRESSION_FLAG,
+            0,
+            OpCode::Result,
+            &rows_body(100, 100, 1000),
+        );
+        // Nothing is decoded before the whole frame was received, which is when decompressing
+        // it fails, as no compression was negotiated.
+        let mut src = BytesMut::from(&data[..data.len() - 1]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        src.extend_from_slice(&data[data.len() - 1..]);
+        let err = codec.decode(&mut src).unwrap_err();
+        assert_eq!(format!("{}", err), "Received a compressed frame, but no compression was negotiated");
+    }
+
+    #[test]
+    fn rows_results_of_another_version_are_not_streamed() {
+        let mut codec = codec();
+        let mut data = frame(Version::v4_response(), 0, 0, OpCode::Result, &rows_body(100, 100, 1000));
+        data.extend_from_slice(&frame(Version::v3_response(), 0, 1, OpCode::Ready, &[]));
+        let frames = decode_chunks(&mut codec, &data, 10_000).unwrap();
+        assert_eq!(frames.len(), 2);
+        match frames[0] {
+            (Frame::Error { id: 7, ref error }, false) => {
+                let expected = header::Error::from(header::ErrorKind::VersionMismatch(0x03, 0x04));
+                assert_eq!(format!("{}", error), format!("{}", expected))
+            }
+            ref frame => panic!("Expected a version mismatch, got {:?}", frame),
+        }
+        match frames[1] {
+            (Frame::Message { id: 8, message: StreamingMessage::Ready, .. }, false) => {}
+            ref frame => panic!("Expected the following frame, got {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn stream_ids_are_reused_once_released() {
+        let mut ids = StreamIds::default();
+        assert_eq!(ids.allocate(7).unwrap(), 0);
+        assert_eq!(ids.allocate(8).unwrap(), 1);
+        assert_eq!(ids.request(1), Some(8));
+        assert_eq!(ids.release(0), Some(7));
+        assert_eq!(ids.release(0), None);
+
+        ids.next = i16::MAX;
+        assert_eq!(ids.allocate(9).unwrap(), i16::MAX);
+        // Wraps around, and skips the id still in use.
+        assert_eq!(ids.allocate(10).unwrap(), 0);
+        assert_eq!(ids.allocate(11).unwrap(), 2);
+    }
+
+    #[test]
+    fn stream_ids_are_limited() {
+        let mut ids = StreamIds::default();
+        for request in 0..(i16::MAX as u64 + 1) {
+            ids.allocate(request).unwrap();
+        }
+        assert!(ids.allocate(0).is_err());
+        ids.release(42);
+        assert_eq!(ids.allocate(0).unwrap(), 42);
+    }
+
+    #[test]
+    fn fails_once_too_many_requests_are_orphaned() {
+        let orphaned = OrphanedRequests::default();
+        let mut codec = CqlCodec::new(ProtocolVersion::Version3, CqlCodecDebuggingOptions::default())
+            .with_orphaned_requests(orphaned.clone());
+        let options = |id| Frame::Message {
+            id: id,
+            message: request::Message::Options,
+            body: false,
+            solo: false,
+        };
+        let mut buf = BytesMut::with_capacity(64);
+        for _ in 0..MAX_ORPHANED_REQUESTS {
+            orphaned.add();
+        }
+        let err = codec.encode(options(7), &mut buf).unwrap_err();
+        assert_eq!(
+            format!("{}", err),
+            "Closing the connection, as 256 requests timed out without a response"
+        );
+        assert!(buf.is_empty());
+        assert!(codec.stream_ids.requests.is_empty());
+
+        orphaned.remove();
+        codec.encode(options(8), &mut buf).unwrap();
+        assert_eq!(codec.stream_ids.request(0), Some(8));
+    }
+
+    #[test]
+    fn stream_ids_are_released_if_encoding_fails() {
+        let mut codec = CqlCodec::new(ProtocolVersion::Version3, CqlCodecDebuggingOptions::default());
+        let mut query = QueryMessage::default();
+        query.parameters.values = Some(QueryValues::Positional(vec![CqlBytes::unset_value()]));
+        let mut buf = BytesMut::with_capacity(64);
+        let frame = Frame::Message {
+            id: 7,
+            message: request::Message::Query(query),
+            body: false,
+            solo: false,
+        };
+        assert!(codec.encode(frame, &mut buf).is_err());
+        assert!(buf.is_empty());
+        assert!(codec.stream_ids.requests.is_empty());
+
+        let frame = Frame::Message {
+            id: 8,
+            message: request::Message::Options,
+            body: false,
+            solo: false,
+        };
+        codec.encode(frame, &mut buf).unwrap();
+        assert_eq!(Header::try_from(buf.as_ref()).unwrap().stream_id, 1);
+        assert_eq!(codec.stream_ids.request(1), Some(8));
+        assert_eq!(codec.stream_ids.requests.len(), 1);
+    }
+}
@@ -1,14 +1,37 @@
 use super::client::ClientHandle as ComplexClientHandle;
 use super::messages::StreamingMessage;
 use tokio_service::Service;
-use futures::Future;
+use futures::{future, Future};
 use codec::{response, request};
+use codec::header::ProtocolVersion;
+use codec::primitives::datatypes::Uuid;
+use super::tracing::{fetch_trace, Trace};
+use super::paging::RowPages;
+use super::error::Error;
 use std::io;
 
 pub struct ClientHandle {
     inner: ComplexClientHandle,
 }
 
+impl ClientHandle {
+    /// The protocol version agreed upon with the server during the handshake.
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.inner.protocol_version()
+    }
+
+    /// Stream all rows returned by `query`, which are fetched in pages of `page_size` rows.
+    pub fn query_pages(&self, query: request::QueryMessage, page_size: i32) -> RowPages<&ComplexClientHandle> {
+        self.inner.query_pages(query, page_size)
+    }
+
+    /// Fetch the trace of a request which was sent with tracing enabled.
+    /// See `tracing::fetch_trace()` for details.
+    pub fn fetch_trace(&self, session_id: Uuid) -> Box<Future<Item = Option<Trace>, Error = Error>> {
+        fetch_trace(&self.inner, session_id)
+    }
+}
+
 impl From<ComplexClientHandle> for ClientHandle {
     fn from(f: ComplexClientHandle) -> Self {
         ClientHandle { inner: f }
@@ -21,34 +44,84 @@ pub enum Message {
     Error(response::ErrorMessage),
     AuthSuccess(response::AuthSuccessMessage),
     Authenticate(response::AuthenticateMessage),
-    Result(response::ResultMessage),
+    Result(response::ResultMessage, response::ResponseInfo),
     Ready,
 }
 
-impl From<StreamingMessage> for Message {
-    fn from(f: StreamingMessage) -> Self {
-        match f {
-            StreamingMessage::Ready => Message::Ready,
-            StreamingMessage::Supported(msg) => Message::Supported(msg),
-            StreamingMessage::Error(msg) => Message::Error(msg),
-            StreamingMessage::AuthSuccess(msg) => Message::AuthSuccess(msg),
-            StreamingMessage::Authenticate(msg) => Message::Authenticate(msg),
-            StreamingMessage::Result(msg) => Message::Result(msg),
-            StreamingMessage::Partial(_stream) => {
-                // TODO: exhaust stream and build a singular response in a blocking fashion
-                panic!("TODO: Implement StreamingMessage::Partial in easy-client")
-            }
-        }
-    }
-}
-
 impl Service for ClientHandle {
     type Request = request::Message;
     type Response = Message;
     type Error = io::Error;
     type Future = Box<Future<Item = Self::Response, Error = Self::Error>>;
 
+    /// The rows of a streamed result are all received before the response is returned.
     fn call(&self, req: Self::Request) -> Self::Future {
-        Box::new(self.inner.call(req).map(From::from))
+        Box::new(self.inner.call(req).and_then(|res| {
+            let res = match res {
+                StreamingMessage::Partial(rows) => {
+                    return Box::new(rows.into_result().map(|(msg, info)| Message::Result(msg, info))) as Self::Future
+                }
+                StreamingMessage::Ready => Message::Ready,
+                StreamingMessage::Supported(msg) => Message::Supported(msg),
+                StreamingMessage::Error(msg) => Message::Error(msg),
+                StreamingMessage::AuthSuccess(msg) => Message::AuthSuccess(msg),
+                StreamingMessage::Authenticate(msg) => Message::Authenticate(msg),
+                StreamingMessage::Result(msg, info) => Message::Result(msg, info),
+            };
+            Box::new(future::ok(res))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codec::header::ProtocolVersion::*;
+    use codec::primitives::encode;
+    use codec::primitives::datatypes::{Text, ToCql};
+    use codec::response::{ResponseInfo, ResultMessage, Row, RowsMetadata, ValueAt};
+    use super::super::client::ConnectOptions;
+    use super::super::messages::{ChunkedMessage, RowStream};
+    use futures::sync::mpsc;
+    use tokio_core::reactor::Core;
+    use bytes::BytesMut;
+    use std::cell::RefCell;
+
+    fn row(meta: &RowsMetadata, value: &str) -> Row {
+        let mut buf = BytesMut::with_capacity(16);
+        encode::bytes(&value.to_cql(), &mut buf);
+        Row::decode(buf, meta).unwrap().1.unwrap()
+    }
+
+    #[test]
+    fn receives_all_rows_of_streamed_results() {
+        let mut core = Core::new().unwrap();
+        let meta = RowsMetadata {
+            columns_count: 1,
+            rows_count: 3,
+            ..Default::default()
+        };
+        let (tx, rx) = mpsc::channel(2);
+        tx.clone().try_send(Ok(ChunkedMessage::Rows(vec![row(&meta, "a"), row(&meta, "b")]))).unwrap();
+        tx.clone().try_send(Ok(ChunkedMessage::Rows(vec![row(&meta, "c")]))).unwrap();
+        drop(tx);
+        let body = Some(RowStream::new(meta.clone(), ResponseInfo::default(), rx.into()));
+        let body = RefCell::new(body);
+        let options = ConnectOptions::default();
+        let (client_handle, _events) = ComplexClientHandle::fake(Version3, &core.handle(), &options, move |_| {
+            Ok(StreamingMessage::Partial(body.borrow_mut().take().unwrap()))
+        });
+        let client_handle = ClientHandle::from(client_handle);
+
+        let res = core.run(client_handle.call(request::Message::Options)).unwrap();
+        match res {
+            Message::Result(ResultMessage::Rows { rows, meta }, _) => {
+                assert_eq!(meta.rows_count, 3);
+                let values: Vec<Text> = rows.iter().map(|r| r.value_at(0).unwrap()).collect();
+                let values: Vec<&str> = values.iter().map(|v| v.as_ref()).collect();
+                assert_eq!(values, vec!["a", "b", "c"]);
+            }
+            res => panic!("Expected all rows, got {:?}", res),
+        }
     }
 }
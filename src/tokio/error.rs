@@ -7,9 +7,18 @@ error_chain! {
             display("CQL Server Error({}): {}", code, msg)
         }
         HandshakeError(msg: String)
+        InvalidToken(token: String) {
+            description("A token could not be parsed")
+            display("Invalid token: '{}'", token)
+        }
+        UnsupportedProtocolVersion(version: ::codec::header::ProtocolVersion, msg: String) {
+            description("The server does not support the requested protocol version")
+            display("The server rejected protocol {:?}: {}", version, msg)
+        }
     }
 
     foreign_links{
         IoErr(io::Error);
+        ResponseErr(::codec::response::Error);
     }
 }
@@ -1,16 +1,92 @@
 use codec::request;
 use codec::response;
+use codec::header::{self, ProtocolVersion};
+use codec::compression::Compression;
 use codec::authentication::{Authenticator, Credentials};
 use codec::primitives::{CqlString, CqlBytes, CqlFrom};
 use bytes::BytesMut;
 use tokio_service::Service;
 use futures::{future, Future};
 use semver;
+use std::rc::Rc;
+use std::io;
 
 use super::error::{Error, Result, ErrorKind, ResultExt};
 use super::client::ClientHandle;
 use super::messages::StreamingMessage;
 
+/// Connect with the given protocol version, and step down to lower versions for as long as
+/// the server rejects the one we tried. Each attempt uses a new connection, as the version
+/// cannot be changed once the first frame was exchanged.
+pub fn negotiate_protocol_version<F>(
+    version: ProtocolVersion,
+    connect: F,
+) -> Box<Future<Item = ClientHandle, Error = Error>>
+where
+    F: Fn(ProtocolVersion) -> Box<Future<Item = ClientHandle, Error = Error>> + 'static,
+{
+    try_protocol_version(version, Rc::new(connect))
+}
+
+fn try_protocol_version<F>(
+    version: ProtocolVersion,
+    connect: Rc<F>,
+) -> Box<Future<Item = ClientHandle, Error = Error>>
+where
+    F: Fn(ProtocolVersion) -> Box<Future<Item = ClientHandle, Error = Error>> + 'static,
+{
+    Box::new(connect(version).or_else(move |err| match version.lower() {
+        Some(lower) if is_version_rejection(&err) => {
+            debug!(
+                "server rejected protocol {:?} with '{}', retrying with {:?}",
+                version,
+                err,
+                lower
+            );
+            try_protocol_version(lower, connect)
+        }
+        _ => Box::new(future::err(err)),
+    }))
+}
+
+fn is_version_rejection(err: &Error) -> bool {
+    match *err.kind() {
+        ErrorKind::UnsupportedProtocolVersion(..) => true,
+        _ => false,
+    }
+}
+
+/// Servers answer the first frame of a connection with a protocol error, or with a frame of
+/// their own version, if they don't speak the version we used.
+pub fn check_protocol_version(
+    version: ProtocolVersion,
+    res: io::Result<StreamingMessage>,
+) -> Result<StreamingMessage> {
+    match res {
+        Ok(StreamingMessage::Error(ref msg)) if msg.code == response::error_code::PROTOCOL_ERROR => {
+            Err(
+                ErrorKind::UnsupportedProtocolVersion(version, msg.text.as_ref().into()).into(),
+            )
+        }
+        Err(err) => {
+            let mismatch = match err.get_ref().and_then(|e| e.downcast_ref::<header::Error>()) {
+                Some(e) => {
+                    match *e.kind() {
+                        header::ErrorKind::VersionMismatch(..) => Some(e.to_string()),
+                        _ => None,
+                    }
+                }
+                None => None,
+            };
+            match mismatch {
+                Some(msg) => Err(ErrorKind::UnsupportedProtocolVersion(version, msg).into()),
+                None => Err(err.into()),
+            }
+        }
+        Ok(res) => Ok(res),
+    }
+}
+
 // TODO: prevent infinite recursion on malformed input
 pub fn interpret_response_and_handle(
     handle: ClientHandle,
@@ -18,7 +94,15 @@ pub fn interpret_response_and_handle(
     creds: Option<Credentials>,
     desired_cql_version: Option<semver::Version>,
 ) -> Box<Future<Item = ClientHandle, Error = Error>> {
-    let res: response::Message = res.into();
+    let res = match res.into_message() {
+        Ok(res) => res,
+        Err(rows) => {
+            return Box::new(future::err(
+                ErrorKind::HandshakeError(format!("Did not expect to receive the following message {:?}", rows))
+                    .into(),
+            ))
+        }
+    };
     match res {
         response::Message::Supported(msg) => {
             let startup = startup_message_from_supported(msg, desired_cql_version.as_ref());
@@ -78,7 +162,9 @@ fn startup_message_from_supported(
                         .into(),
                 ))?
                 .clone(),
-            compression: None,
+            compression: msg.compression()
+                .and_then(|names| Compression::negotiate(names.iter().map(|n| n.as_ref())))
+                .map(|c| unsafe { CqlString::unchecked_from(c.as_str()) }),
         }
     };
 
@@ -109,3 +195,124 @@ fn auth_response_from_authenticate(
         },
     ))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codec::header::ProtocolVersion::*;
+    use codec::primitives::CqlStringMultiMap;
+    use codec::response::{error_code, CqlErrorKind, ErrorMessage, SupportedMessage};
+    use tokio_core::reactor::Core;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use super::super::client::ConnectOptions;
+    use super::super::utils::io_err;
+
+    /// What a server only speaking protocol version 3 answers to the first frame of v4.
+    fn protocol_error() -> io::Result<StreamingMessage> {
+        Ok(StreamingMessage::Error(ErrorMessage {
+            code: error_code::PROTOCOL_ERROR,
+            text: CqlString::try_from("Invalid or unsupported protocol version (4)").unwrap(),
+            kind: CqlErrorKind::ProtocolError,
+        }))
+    }
+
+    /// What a server answering v4 frames in v3 is decoded to.
+    fn version_mismatch() -> io::Result<StreamingMessage> {
+        let err = header::ErrorKind::VersionMismatch(0x04, 0x03);
+        Err(io::Error::new(io::ErrorKind::Other, header::Error::from(err)))
+    }
+
+    fn supported() -> io::Result<StreamingMessage> {
+        Ok(StreamingMessage::Supported(SupportedMessage(CqlStringMultiMap::try_from(HashMap::new()).unwrap())))
+    }
+
+    /// Negotiate the version starting with v4, with a server which answers the OPTIONS request
+    /// of each version with `respond`. Returns the outcome, and the versions which were tried.
+    fn negotiate<F>(respond: F) -> (Result<ProtocolVersion>, Vec<ProtocolVersion>)
+    where
+        F: Fn(ProtocolVersion) -> io::Result<StreamingMessage> + 'static,
+    {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let tried = Rc::new(RefCell::new(Vec::new()));
+        let log = tried.clone();
+        let respond = Rc::new(respond);
+        let negotiation = negotiate_protocol_version(Version4, move |version| {
+            log.borrow_mut().push(version);
+            let respond = respond.clone();
+            let (client_handle, _) = ClientHandle::fake(version, &handle, &ConnectOptions::default(), move |_| {
+                respond(version)
+            });
+            Box::new(
+                client_handle
+                    .call(request::Message::Options)
+                    .then(move |res| check_protocol_version(version, res))
+                    .map(move |_| client_handle),
+            )
+        });
+        let res = core.run(negotiation).map(|client_handle| client_handle.protocol_version());
+        let tried = tried.borrow().clone();
+        (res, tried)
+    }
+
+    #[test]
+    fn steps_down_after_protocol_error() {
+        let (res, tried) = negotiate(|version| match version {
+            Version4 => protocol_error(),
+            Version3 => supported(),
+        });
+        assert_eq!(res.unwrap(), Version3);
+        assert_eq!(tried, vec![Version4, Version3]);
+    }
+
+    #[test]
+    fn steps_down_after_version_mismatch() {
+        let (res, tried) = negotiate(|version| match version {
+            Version4 => version_mismatch(),
+            Version3 => supported(),
+        });
+        assert_eq!(res.unwrap(), Version3);
+        assert_eq!(tried, vec![Version4, Version3]);
+    }
+
+    #[test]
+    fn uses_highest_version() {
+        let (res, tried) = negotiate(|_| supported());
+        assert_eq!(res.unwrap(), Version4);
+        assert_eq!(tried, vec![Version4]);
+    }
+
+    #[test]
+    fn fails_if_lowest_version_is_rejected() {
+        let (res, tried) = negotiate(|_| protocol_error());
+        match *res.unwrap_err().kind() {
+            ErrorKind::UnsupportedProtocolVersion(Version3, ref msg) => {
+                assert_eq!(msg, "Invalid or unsupported protocol version (4)")
+            }
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        assert_eq!(tried, vec![Version4, Version3]);
+    }
+
+    #[test]
+    fn fails_on_other_errors() {
+        let (res, tried) = negotiate(|_| Err(io_err("connection reset")));
+        match *res.unwrap_err().kind() {
+            ErrorKind::IoErr(ref err) => assert_eq!(err.to_string(), "connection reset"),
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        assert_eq!(tried, vec![Version4]);
+    }
+
+    #[test]
+    fn reports_version_mismatch() {
+        match *check_protocol_version(Version4, version_mismatch()).unwrap_err().kind() {
+            ErrorKind::UnsupportedProtocolVersion(Version4, ref msg) => {
+                assert_eq!(msg, "Expected a response of protocol version 4, but got 3")
+            }
+            ref e => panic!("unexpected error {:?}", e),
+        }
+        assert!(check_protocol_version(Version4, supported()).is_ok());
+    }
+}
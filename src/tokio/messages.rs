@@ -1,44 +1,106 @@
 use codec::request;
 use codec::response;
 use tokio_proto::streaming::{Message, Body};
-use std::io;
+use futures::{Async, Future, Poll, Stream};
+use std::{fmt, io, vec};
 
-/// A chunk of a result - similar to response::ResultMessage, but only a chunk of it
-/// TODO: this is just a dummy to show the intent - this is likely to change
-#[derive(Debug)]
-pub struct ResultChunk;
-
-/// A message representing a partial response
+/// A part of a streamed response, as produced by the codec while the frame is still being
+/// received.
 #[derive(Debug)]
 pub enum ChunkedMessage {
-    Result(ResultChunk),
+    /// All rows of a Rows result which could be decoded from the data received so far.
+    Rows(Vec<response::Row>),
 }
 
 /// Streamable responses use the body type, which implements stream, with the streamable response.
-/// In our case, this will only be the Result response
-/// TODO: fix comment above once things get clearer
+/// In our case, this is only the Rows result of a large frame, see `RowStream`.
 #[derive(Debug)]
 pub enum StreamingMessage {
     Supported(response::SupportedMessage),
     Error(response::ErrorMessage),
-    Partial(ResponseStream),
+    Partial(RowStream),
     Authenticate(response::AuthenticateMessage),
     AuthSuccess(response::AuthSuccessMessage),
     Ready,
-    Result(response::ResultMessage), // FIXME: later use chunked message
+    Result(response::ResultMessage, response::ResponseInfo),
+}
+
+/// The rows of a Rows result, which are yielded as soon as they are received instead of
+/// waiting for the whole frame.
+pub struct RowStream {
+    /// Describes the columns of all rows, and how many rows there are in total.
+    pub meta: response::RowsMetadata,
+    pub info: response::ResponseInfo,
+    body: ResponseStream,
+    rows: vec::IntoIter<response::Row>,
+}
+
+impl RowStream {
+    pub fn new(meta: response::RowsMetadata, info: response::ResponseInfo, body: ResponseStream) -> Self {
+        RowStream {
+            meta: meta,
+            info: info,
+            body: body,
+            rows: Vec::new().into_iter(),
+        }
+    }
+
+    /// Receive all remaining rows and turn them into the result a non-streamed response
+    /// would have had.
+    pub fn into_result(self) -> Box<Future<Item = (response::ResultMessage, response::ResponseInfo), Error = io::Error>> {
+        let meta = self.meta.clone();
+        let info = self.info.clone();
+        Box::new(self.collect().map(move |rows| {
+            (
+                response::ResultMessage::Rows {
+                    rows: rows,
+                    meta: meta,
+                },
+                info,
+            )
+        }))
+    }
+}
+
+impl Stream for RowStream {
+    type Item = response::Row;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(row) = self.rows.next() {
+                return Ok(Async::Ready(Some(row)));
+            }
+            match try_ready!(self.body.poll()) {
+                Some(ChunkedMessage::Rows(rows)) => self.rows = rows.into_iter(),
+                None => return Ok(Async::Ready(None)),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for RowStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RowStream")
+            .field("meta", &self.meta)
+            .field("info", &self.info)
+            .finish()
+    }
 }
 
-impl From<StreamingMessage> for response::Message {
-    fn from(f: StreamingMessage) -> Self {
+impl StreamingMessage {
+    /// Convert into the message a non-streaming codec would have decoded. A `Partial` result is
+    /// handed back as is, as its rows first have to be received with `RowStream::into_result()`.
+    pub fn into_message(self) -> Result<response::Message, RowStream> {
         use self::StreamingMessage::*;
-        match f {
-            Ready => response::Message::Ready,
-            Supported(msg) => response::Message::Supported(msg),
-            Error(msg) => response::Message::Error(msg),
-            AuthSuccess(msg) => response::Message::AuthSuccess(msg),
-            Authenticate(msg) => response::Message::Authenticate(msg),
-            Result(msg) => response::Message::Result(msg),
-            Partial(_) => panic!("Partials are not suppported - this is just used during handshake"),
+        match self {
+            Ready => Ok(response::Message::Ready),
+            Supported(msg) => Ok(response::Message::Supported(msg)),
+            Error(msg) => Ok(response::Message::Error(msg)),
+            AuthSuccess(msg) => Ok(response::Message::AuthSuccess(msg)),
+            Authenticate(msg) => Ok(response::Message::Authenticate(msg)),
+            Result(msg, _info) => Ok(response::Message::Result(msg)),
+            Partial(rows) => Err(rows),
         }
     }
 }
@@ -51,7 +113,7 @@ impl From<response::Message> for StreamingMessage {
             response::Message::AuthSuccess(msg) => StreamingMessage::AuthSuccess(msg),
             response::Message::Authenticate(msg) => StreamingMessage::Authenticate(msg),
             response::Message::Error(msg) => StreamingMessage::Error(msg),
-            response::Message::Result(msg) => StreamingMessage::Result(msg),
+            response::Message::Result(msg) => StreamingMessage::Result(msg, Default::default()),
         }
     }
 }
@@ -61,3 +123,63 @@ pub type ResponseMessage = Message<StreamingMessage, ResponseStream>;
 
 pub type RequestMessage = Message<request::Message, RequestStream>;
 pub type RequestStream = Body<request::Message, io::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codec::primitives::encode;
+    use codec::primitives::datatypes::{Int, ToCql};
+    use codec::response::{ResultMessage, ValueAt};
+    use futures::sync::mpsc;
+    use bytes::BytesMut;
+
+    fn meta() -> response::RowsMetadata {
+        response::RowsMetadata {
+            columns_count: 1,
+            rows_count: 3,
+            ..Default::default()
+        }
+    }
+
+    fn rows(values: &[i32]) -> ChunkedMessage {
+        ChunkedMessage::Rows(
+            values
+                .iter()
+                .map(|v| {
+                    let mut buf = BytesMut::with_capacity(8);
+                    encode::bytes(&v.to_cql(), &mut buf);
+                    response::Row::decode(buf, &meta()).unwrap().1.unwrap()
+                })
+                .collect(),
+        )
+    }
+
+    fn row_stream(chunks: Vec<io::Result<ChunkedMessage>>) -> RowStream {
+        let (mut tx, rx) = mpsc::channel(chunks.len());
+        for chunk in chunks {
+            tx.try_send(chunk).unwrap();
+        }
+        RowStream::new(meta(), response::ResponseInfo::default(), rx.into())
+    }
+
+    #[test]
+    fn into_result_collects_all_chunks() {
+        let stream = row_stream(vec![Ok(rows(&[1, 2])), Ok(rows(&[])), Ok(rows(&[3]))]);
+        match stream.into_result().wait().unwrap() {
+            (ResultMessage::Rows { rows, meta }, _) => {
+                assert_eq!(meta.rows_count, 3);
+                let values: Vec<Int> = rows.iter().map(|r| r.value_at(0).unwrap()).collect();
+                assert_eq!(values, vec![Int::new(1), Int::new(2), Int::new(3)]);
+            }
+            res => panic!("Expected rows, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn into_result_fails_if_the_body_does() {
+        let err = io::Error::new(io::ErrorKind::UnexpectedEof, "connection lost");
+        let stream = row_stream(vec![Ok(rows(&[1])), Err(err)]);
+        let err = stream.into_result().wait().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
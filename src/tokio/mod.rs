@@ -3,6 +3,13 @@ pub mod messages;
 pub mod codec;
 pub mod ssl;
 pub mod easy;
+pub mod tracing;
+pub mod paging;
+pub mod pool;
+pub mod cluster;
+pub mod policies;
+pub mod partitioner;
+pub mod session;
 mod utils;
 
 pub mod client;
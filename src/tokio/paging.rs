@@ -0,0 +1,240 @@
+//! Transparent paging of query results.
+//!
+//! Queries which could return more rows than fit into a single response are best sent with
+//! a page size. The server then responds with one page of rows at a time, along with a paging
+//! state which has to be sent with the query again to receive the next page.
+use codec::request::{self, QueryMessage};
+use codec::response::{ResultMessage, Row, RowsMetadata};
+use codec::primitives::CqlBytes;
+use futures::{Async, Future, Poll, Stream};
+use tokio_service::Service;
+use std::{io, vec};
+
+use super::error::*;
+use super::messages::{RowStream, StreamingMessage};
+use super::utils::io_err;
+
+/// A stream of all rows returned by a query, which fetches the next page once all rows of the
+/// current one were yielded.
+pub struct RowPages<S: Service> {
+    service: S,
+    query: QueryMessage,
+    meta: Option<RowsMetadata>,
+    next_paging_state: Option<CqlBytes>,
+    state: State<S::Future>,
+}
+
+enum State<F> {
+    Fetching(F),
+    Received(vec::IntoIter<Row>),
+    Streaming(RowStream),
+    Done,
+}
+
+impl<S> RowPages<S>
+where
+    S: Service<Request = request::Message, Response = StreamingMessage, Error = io::Error>,
+{
+    /// Send `query` through `service`, requesting at most `page_size` rows per page.
+    ///
+    /// If the query has a paging state set, the rows are fetched starting with the page it
+    /// refers to. This allows to resume from a state previously obtained by `paging_state()`.
+    pub fn new(service: S, mut query: QueryMessage, page_size: i32) -> Self {
+        query.parameters.page_size = Some(page_size);
+        let first = service.call(request::Message::Query(query.clone()));
+        RowPages {
+            service: service,
+            query: query,
+            meta: None,
+            next_paging_state: None,
+            state: State::Fetching(first),
+        }
+    }
+
+    /// The metadata of the page currently being yielded, which is `None` until the first page
+    /// was received.
+    pub fn meta(&self) -> Option<&RowsMetadata> {
+        self.meta.as_ref()
+    }
+
+    /// The paging state to set on the query to resume this stream later.
+    ///
+    /// It refers to the beginning of the page currently being yielded, which is why rows of it
+    /// which were yielded already will be yielded again when resuming. `None` refers to the
+    /// first page.
+    pub fn paging_state(&self) -> Option<&CqlBytes> {
+        self.query.parameters.paging_state.as_ref()
+    }
+
+    fn fetch_next_page(&mut self) {
+        self.state = match self.next_paging_state.take() {
+            Some(paging_state) => {
+                self.query.parameters.paging_state = Some(paging_state);
+                State::Fetching(self.service.call(request::Message::Query(self.query.clone())))
+            }
+            None => State::Done,
+        };
+    }
+
+    fn on_page(&mut self, res: StreamingMessage) -> Result<State<S::Future>> {
+        Ok(match res {
+            StreamingMessage::Result(ResultMessage::Rows { rows, meta }, _) => {
+                self.next_paging_state = meta.paging_state.clone();
+                self.meta = Some(meta);
+                State::Received(rows.into_iter())
+            }
+            StreamingMessage::Partial(rows) => {
+                self.next_paging_state = rows.meta.paging_state.clone();
+                self.meta = Some(rows.meta.clone());
+                State::Streaming(rows)
+            }
+            StreamingMessage::Error(msg) => bail!(ErrorKind::CqlError(msg.code, msg.text.into())),
+            msg => bail!(io_err(format!("Expected a Rows result, got {:?}", msg))),
+        })
+    }
+}
+
+impl<S> Stream for RowPages<S>
+where
+    S: Service<Request = request::Message, Response = StreamingMessage, Error = io::Error>,
+{
+    type Item = Row;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Row>, Error> {
+        loop {
+            let page = match self.state {
+                State::Fetching(ref mut f) => Some(try_ready!(f.poll())),
+                State::Received(ref mut rows) => {
+                    if let Some(row) = rows.next() {
+                        return Ok(Async::Ready(Some(row)));
+                    }
+                    None
+                }
+                State::Streaming(ref mut rows) => {
+                    if let Some(row) = try_ready!(rows.poll()) {
+                        return Ok(Async::Ready(Some(row)));
+                    }
+                    None
+                }
+                State::Done => return Ok(Async::Ready(None)),
+            };
+            match page {
+                Some(res) => {
+                    self.state = match self.on_page(res) {
+                        Ok(state) => state,
+                        Err(err) => {
+                            self.state = State::Done;
+                            return Err(err);
+                        }
+                    }
+                }
+                None => self.fetch_next_page(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bytes::BytesMut;
+    use codec::primitives::{encode, CqlFrom, CqlString};
+    use codec::primitives::datatypes::ToCql;
+    use codec::response::{ColumnSpec, ColumnType, ResponseInfo};
+    use futures::future;
+    use std::cell::RefCell;
+
+    /// Answers the first query with a page of the rows 1 and 2, and the query with the paging
+    /// state of that page with the last page, which has the row 3.
+    struct Pages {
+        sent: RefCell<Vec<QueryMessage>>,
+    }
+
+    fn meta(paging_state: Option<CqlBytes>) -> RowsMetadata {
+        RowsMetadata {
+            paging_state: paging_state,
+            column_spec: vec![
+                ColumnSpec::WithGlobalSpec {
+                    name: CqlString::try_from("id").unwrap(),
+                    column_type: ColumnType::Int,
+                },
+            ],
+            columns_count: 1,
+            ..Default::default()
+        }
+    }
+
+    fn page(meta: RowsMetadata, values: &[i32]) -> StreamingMessage {
+        let rows = values
+            .iter()
+            .map(|v| {
+                let mut buf = BytesMut::with_capacity(8);
+                encode::bytes(&v.to_cql(), &mut buf);
+                Row::decode(buf, &meta).unwrap().1.unwrap()
+            })
+            .collect();
+        StreamingMessage::Result(ResultMessage::Rows { rows: rows, meta: meta }, ResponseInfo::default())
+    }
+
+    impl<'a> Service for &'a Pages {
+        type Request = request::Message;
+        type Response = StreamingMessage;
+        type Error = io::Error;
+        type Future = future::FutureResult<StreamingMessage, io::Error>;
+
+        fn call(&self, req: Self::Request) -> Self::Future {
+            let query = match req {
+                request::Message::Query(query) => query,
+                req => panic!("unexpected request {:?}", req),
+            };
+            let res = match query.parameters.paging_state {
+                None => page(meta(Some(1i32.to_cql())), &[1, 2]),
+                Some(ref state) if *state == 1i32.to_cql() => page(meta(None), &[3]),
+                Some(ref state) => panic!("unexpected paging state {:?}", state),
+            };
+            self.sent.borrow_mut().push(query);
+            future::ok(res)
+        }
+    }
+
+    #[test]
+    fn fetches_all_pages() {
+        let pages = Pages { sent: RefCell::new(Vec::new()) };
+        let mut rows = RowPages::new(&pages, QueryMessage::default(), 2);
+        assert_eq!(pages.sent.borrow().len(), 1);
+        assert!(rows.meta().is_none());
+
+        let mut ids = Vec::new();
+        loop {
+            match rows.poll().unwrap() {
+                Async::Ready(Some(row)) => {
+                    let id: i32 = row.get(rows.meta().unwrap(), 0).unwrap();
+                    ids.push((id, rows.paging_state().cloned()));
+                }
+                Async::Ready(None) => break,
+                Async::NotReady => panic!("the pages to be ready"),
+            }
+        }
+        assert_eq!(ids, vec![(1, None), (2, None), (3, Some(1i32.to_cql()))]);
+
+        let sent = pages.sent.borrow();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].parameters.page_size, Some(2));
+        assert_eq!(sent[0].parameters.paging_state, None);
+        assert_eq!(sent[1].parameters.page_size, Some(2));
+        assert_eq!(sent[1].parameters.paging_state, Some(1i32.to_cql()));
+    }
+
+    #[test]
+    fn resumes_from_paging_state() {
+        let pages = Pages { sent: RefCell::new(Vec::new()) };
+        let mut query = QueryMessage::default();
+        query.parameters.paging_state = Some(1i32.to_cql());
+        let rows = RowPages::new(&pages, query, 2);
+
+        let rows = rows.collect().wait().unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(pages.sent.borrow().len(), 1);
+    }
+}
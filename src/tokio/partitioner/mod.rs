@@ -0,0 +1,169 @@
+//! Computation of partition tokens, and the mapping of tokens to the nodes which own them.
+//!
+//! The partition key of a request is hashed to a token by the partitioner of the cluster. Each
+//! node owns the range of tokens which ends with one of its own tokens, and the replication
+//! strategy of a keyspace decides which further nodes store replicas of the partition.
+use num_bigint::{BigInt, Sign};
+use std::fmt;
+
+use super::error::*;
+
+mod md5;
+mod murmur3;
+mod ring;
+
+pub use self::md5::md5;
+pub use self::murmur3::murmur3;
+pub use self::ring::*;
+
+/// A position on the token ring. Tokens of different partitioners can't be compared
+/// meaningfully.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Token {
+    Murmur3(i64),
+    /// A value between 0 and 2^127.
+    Random(BigInt),
+    ByteOrdered(Vec<u8>),
+}
+
+/// Formats the token as a CQL literal, as used in `WHERE token(...) > ...` clauses.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Murmur3(t) => write!(f, "{}", t),
+            Token::Random(ref t) => write!(f, "{}", t),
+            Token::ByteOrdered(ref t) => {
+                write!(f, "0x")?;
+                for b in t {
+                    write!(f, "{:02x}", b)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The algorithm the cluster uses to compute tokens from partition keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Partitioner {
+    Murmur3,
+    Random,
+    ByteOrdered,
+}
+
+impl Partitioner {
+    /// The partitioner with the given Java class name, as found in the `partitioner` column of
+    /// `system.local`.
+    pub fn from_class_name(name: &str) -> Option<Partitioner> {
+        match name.rsplit('.').next() {
+            Some("Murmur3Partitioner") => Some(Partitioner::Murmur3),
+            Some("RandomPartitioner") => Some(Partitioner::Random),
+            Some("ByteOrderedPartitioner") => Some(Partitioner::ByteOrdered),
+            _ => None,
+        }
+    }
+
+    /// The token of the partition with the given serialized key. Keys of tables with multiple
+    /// partition key columns must be encoded with `composite_key()` first.
+    pub fn token(&self, key: &[u8]) -> Token {
+        match *self {
+            Partitioner::Murmur3 => {
+                Token::Murmur3(match murmur3(key) {
+                    ::std::i64::MIN => ::std::i64::MAX,
+                    t => t,
+                })
+            }
+            Partitioner::Random => {
+                let t = BigInt::from_signed_bytes_be(&md5(key));
+                Token::Random(if t.sign() == Sign::Minus { -t } else { t })
+            }
+            Partitioner::ByteOrdered => Token::ByteOrdered(key.to_vec()),
+        }
+    }
+
+    /// Parse a token in the representation used by the `tokens` column of the system tables.
+    pub fn parse_token(&self, token: &str) -> Result<Token> {
+        let invalid = || Error::from(ErrorKind::InvalidToken(token.to_string()));
+        Ok(match *self {
+            Partitioner::Murmur3 => Token::Murmur3(token.parse().map_err(|_| invalid())?),
+            Partitioner::Random => Token::Random(token.parse().map_err(|_| invalid())?),
+            Partitioner::ByteOrdered => {
+                if !token.is_ascii() || token.len() % 2 != 0 {
+                    return Err(invalid());
+                }
+                Token::ByteOrdered((0..token.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&token[i..i + 2], 16).map_err(|_| invalid()))
+                    .collect::<Result<_>>()?)
+            }
+        })
+    }
+}
+
+/// Encode the serialized values of all partition key columns into the key the token is
+/// computed from. A single value is used as is.
+pub fn composite_key<T: AsRef<[u8]>>(components: &[T]) -> Vec<u8> {
+    if components.len() == 1 {
+        return components[0].as_ref().to_vec();
+    }
+    let mut key = Vec::new();
+    for c in components {
+        let c = c.as_ref();
+        key.push((c.len() >> 8) as u8);
+        key.push(c.len() as u8);
+        key.extend_from_slice(c);
+        key.push(0);
+    }
+    key
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn murmur3_tokens() {
+        let p = Partitioner::from_class_name("org.apache.cassandra.dht.Murmur3Partitioner").unwrap();
+        assert_eq!(p.token(b"123"), Token::Murmur3(-7468325962851647638));
+        assert_eq!(p.token(&[0, 0, 0, 1]), Token::Murmur3(-4069959284402364209));
+        assert_eq!(p.parse_token("-7468325962851647638").unwrap(), p.token(b"123"));
+        assert!(p.parse_token("abc").is_err());
+    }
+
+    #[test]
+    fn random_tokens() {
+        let p = Partitioner::from_class_name("org.apache.cassandra.dht.RandomPartitioner").unwrap();
+        // The digest of "123" is positive, the one of "1" negative as a signed integer.
+        assert_eq!(
+            p.token(b"123").to_string(),
+            "42767516990368493138776584305024125808"
+        );
+        assert_eq!(
+            p.token(b"1").to_string(),
+            "78703492656118554854272571946195123045"
+        );
+        assert_eq!(
+            p.parse_token("42767516990368493138776584305024125808").unwrap(),
+            p.token(b"123")
+        );
+    }
+
+    #[test]
+    fn byte_ordered_tokens() {
+        let p = Partitioner::from_class_name("org.apache.cassandra.dht.ByteOrderedPartitioner").unwrap();
+        assert_eq!(p.token(b"key"), Token::ByteOrdered(b"key".to_vec()));
+        assert_eq!(p.token(b"key").to_string(), "0x6b6579");
+        assert_eq!(p.parse_token("6b6579").unwrap(), p.token(b"key"));
+        assert!(p.parse_token("aéb").is_err());
+        assert!(p.parse_token("6b657").is_err());
+    }
+
+    #[test]
+    fn composite_keys() {
+        assert_eq!(composite_key(&[&[1u8, 2][..]]), vec![1, 2]);
+        assert_eq!(
+            composite_key(&[&[0u8, 0, 0, 1][..], b"ab"]),
+            vec![0, 4, 0, 0, 0, 1, 0, 0, 2, b'a', b'b', 0]
+        );
+    }
+}
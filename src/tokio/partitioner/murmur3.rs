@@ -0,0 +1,77 @@
+//! The variant of MurmurHash3 (x64, 128 bit) used by the `Murmur3Partitioner`.
+//!
+//! It differs from the reference implementation in that the bytes of the tail are sign-extended
+//! before they are mixed in, as Java bytes are signed.
+use byteorder::{ByteOrder, LittleEndian};
+
+const C1: u64 = 0x87c3_7b91_1142_53d5;
+const C2: u64 = 0x4cf5_ad43_2745_937f;
+
+fn fmix(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    k ^= k >> 33;
+    k
+}
+
+fn mix_k1(k1: u64) -> u64 {
+    k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2)
+}
+
+fn mix_k2(k2: u64) -> u64 {
+    k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1)
+}
+
+/// The first 64 bits of the hash of `data`, with a seed of 0.
+pub fn murmur3(data: &[u8]) -> i64 {
+    let (mut h1, mut h2) = (0u64, 0u64);
+
+    let blocks = data.len() / 16;
+    for block in data[..blocks * 16].chunks(16) {
+        h1 ^= mix_k1(LittleEndian::read_u64(&block[..8]));
+        h1 = h1.rotate_left(27).wrapping_add(h2).wrapping_mul(5).wrapping_add(0x52dc_e729);
+        h2 ^= mix_k2(LittleEndian::read_u64(&block[8..]));
+        h2 = h2.rotate_left(31).wrapping_add(h1).wrapping_mul(5).wrapping_add(0x3849_5ab5);
+    }
+
+    let tail = &data[blocks * 16..];
+    let (mut k1, mut k2) = (0u64, 0u64);
+    for (i, byte) in tail.iter().enumerate() {
+        let byte = *byte as i8 as i64 as u64;
+        if i < 8 {
+            k1 ^= byte << (8 * i);
+        } else {
+            k2 ^= byte << (8 * (i - 8));
+        }
+    }
+    if tail.len() > 8 {
+        h2 ^= mix_k2(k2);
+    }
+    if !tail.is_empty() {
+        h1 ^= mix_k1(k1);
+    }
+
+    h1 ^= data.len() as u64;
+    h2 ^= data.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix(h1);
+    h2 = fmix(h2);
+    h1.wrapping_add(h2) as i64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn known_hashes() {
+        assert_eq!(murmur3(b"123"), -7468325962851647638);
+        assert_eq!(murmur3(&b"\x00\xff\x10\xfa\x99".repeat(10)), 5837342703291459765);
+        assert_eq!(murmur3(&[0xfe; 8]), -8927430733708461935);
+        assert_eq!(murmur3(&[0x10; 8]), 1446172840243228796);
+        assert_eq!(murmur3(b"9223372036854775807"), 7162290910810015547);
+    }
+}
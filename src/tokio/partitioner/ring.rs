@@ -0,0 +1,371 @@
+use codec::request::RoutingKey;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
+
+use super::{Partitioner, Token};
+use super::super::cluster::Node;
+use super::super::error::*;
+use super::super::policies::ReplicaLocator;
+
+/// How a keyspace places the replicas of its partitions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplicationStrategy {
+    /// Replicas are stored by the nodes following the owner on the ring, regardless of their
+    /// datacenter.
+    Simple { replication_factor: usize },
+    /// Each datacenter stores its own amount of replicas, which are spread over as many racks as
+    /// possible.
+    NetworkTopology { replication_factors: BTreeMap<String, usize> },
+    /// Any other strategy, with the name of its class. Only the owner of a token is known to
+    /// store it.
+    Other(String),
+}
+
+impl ReplicationStrategy {
+    /// The strategy described by the `replication` options of a keyspace, as found in
+    /// `system_schema.keyspaces`. `None` is returned if a replication factor is not a number.
+    pub fn from_options(options: &HashMap<String, String>) -> Option<ReplicationStrategy> {
+        let class = options.get("class").map(|c| c.as_str()).unwrap_or("");
+        match class.rsplit('.').next() {
+            Some("SimpleStrategy") => {
+                let rf = options.get("replication_factor")?.parse().ok()?;
+                Some(ReplicationStrategy::Simple { replication_factor: rf })
+            }
+            Some("NetworkTopologyStrategy") => {
+                let mut factors = BTreeMap::new();
+                for (dc, rf) in options.iter().filter(|&(k, _)| k != "class") {
+                    factors.insert(dc.clone(), rf.parse().ok()?);
+                }
+                Some(ReplicationStrategy::NetworkTopology { replication_factors: factors })
+            }
+            _ => Some(ReplicationStrategy::Other(class.to_string())),
+        }
+    }
+}
+
+/// A range of tokens, which starts after `start` and includes `end`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenRange {
+    pub start: Token,
+    pub end: Token,
+    /// The nodes storing the partitions of the range, with the owner first.
+    pub replicas: Vec<SocketAddr>,
+}
+
+/// The tokens of all nodes of a cluster, which maps each token to the nodes storing it.
+///
+/// It is a snapshot of the nodes it was created from, and has to be created again once the
+/// topology of the cluster changes.
+#[derive(Debug, Clone)]
+pub struct TokenRing {
+    partitioner: Partitioner,
+    /// All tokens in ascending order, along with the node owning them.
+    tokens: Vec<(Token, SocketAddr)>,
+    /// The datacenter and rack of each node.
+    locations: HashMap<SocketAddr, (Option<String>, Option<String>)>,
+    keyspaces: HashMap<String, ReplicationStrategy>,
+}
+
+struct DcReplicas<'a> {
+    wanted: usize,
+    added: usize,
+    racks: usize,
+    seen_racks: HashSet<Option<&'a str>>,
+    skipped: Vec<SocketAddr>,
+}
+
+impl TokenRing {
+    /// Build the ring from the tokens of `nodes`, which are parsed using `partitioner`.
+    pub fn new(partitioner: Partitioner, nodes: &[Node]) -> Result<TokenRing> {
+        let mut tokens = Vec::new();
+        for node in nodes {
+            for token in &node.tokens {
+                tokens.push((partitioner.parse_token(token)?, node.address));
+            }
+        }
+        tokens.sort();
+        Ok(TokenRing {
+            partitioner: partitioner,
+            tokens: tokens,
+            locations: nodes
+                .iter()
+                .map(|n| (n.address, (n.datacenter.clone(), n.rack.clone())))
+                .collect(),
+            keyspaces: HashMap::new(),
+        })
+    }
+
+    pub fn partitioner(&self) -> Partitioner {
+        self.partitioner
+    }
+
+    /// Use `strategy` to determine the replicas of partitions of `keyspace`. Without a strategy,
+    /// only the owner of a token is considered to be a replica.
+    pub fn set_replication(&mut self, keyspace: &str, strategy: ReplicationStrategy) {
+        self.keyspaces.insert(keyspace.to_string(), strategy);
+    }
+
+    /// The node owning `token`, which is the one with the smallest token not lower than it.
+    pub fn owner(&self, token: &Token) -> Option<SocketAddr> {
+        self.position(token).map(|i| self.tokens[i].1)
+    }
+
+    /// The nodes storing `token` in `keyspace`, with the owner first.
+    pub fn replicas_of_token(&self, keyspace: Option<&str>, token: &Token) -> Vec<SocketAddr> {
+        match self.position(token) {
+            Some(start) => self.replicas_from(keyspace, start),
+            None => Vec::new(),
+        }
+    }
+
+    /// All ranges between two consecutive tokens of the ring, which together cover all
+    /// partitions of `keyspace`. The first range wraps around, and starts at the highest token.
+    ///
+    /// This allows to scan a whole table with one query per range, each sent to a replica.
+    pub fn token_ranges(&self, keyspace: Option<&str>) -> Vec<TokenRange> {
+        let len = self.tokens.len();
+        (0..len)
+            .map(|i| {
+                TokenRange {
+                    start: self.tokens[(i + len - 1) % len].0.clone(),
+                    end: self.tokens[i].0.clone(),
+                    replicas: self.replicas_from(keyspace, i),
+                }
+            })
+            .collect()
+    }
+
+    fn position(&self, token: &Token) -> Option<usize> {
+        if self.tokens.is_empty() {
+            return None;
+        }
+        let i = match self.tokens.binary_search_by(|&(ref t, _)| t.cmp(token)) {
+            Ok(i) | Err(i) => i,
+        };
+        Some(if i == self.tokens.len() { 0 } else { i })
+    }
+
+    /// The replicas of the range ending with the token at `start`.
+    fn replicas_from(&self, keyspace: Option<&str>, start: usize) -> Vec<SocketAddr> {
+        let len = self.tokens.len();
+        let ring = (0..len).map(|i| self.tokens[(start + i) % len].1);
+        match keyspace.and_then(|k| self.keyspaces.get(k)) {
+            Some(&ReplicationStrategy::Simple { replication_factor }) => {
+                let mut replicas = Vec::new();
+                for addr in ring {
+                    if replicas.len() == replication_factor {
+                        break;
+                    }
+                    if !replicas.contains(&addr) {
+                        replicas.push(addr);
+                    }
+                }
+                replicas
+            }
+            Some(&ReplicationStrategy::NetworkTopology { ref replication_factors }) => {
+                self.network_topology_replicas(ring, replication_factors)
+            }
+            Some(&ReplicationStrategy::Other(_)) | None => vec![self.tokens[start].1],
+        }
+    }
+
+    /// Walk the ring, and take the first nodes of each datacenter which are on distinct racks.
+    /// Nodes on racks which have a replica already are only used once all racks have one.
+    fn network_topology_replicas<I>(&self, ring: I, factors: &BTreeMap<String, usize>) -> Vec<SocketAddr>
+    where
+        I: Iterator<Item = SocketAddr>,
+    {
+        let mut dcs: HashMap<&str, DcReplicas> = HashMap::new();
+        for (dc, rf) in factors {
+            let nodes: Vec<_> = self.locations
+                .values()
+                .filter(|&&(ref d, _)| d.as_ref() == Some(dc))
+                .collect();
+            let racks: HashSet<_> = nodes.iter().map(|&&(_, ref r)| r).collect();
+            dcs.insert(
+                dc,
+                DcReplicas {
+                    wanted: cmp::min(*rf, nodes.len()),
+                    added: 0,
+                    racks: racks.len(),
+                    seen_racks: HashSet::new(),
+                    skipped: Vec::new(),
+                },
+            );
+        }
+
+        let mut replicas = Vec::new();
+        for addr in ring {
+            if dcs.values().all(|dc| dc.added == dc.wanted) {
+                break;
+            }
+            if replicas.contains(&addr) {
+                continue;
+            }
+            let (dc, rack) = match self.locations.get(&addr) {
+                Some(&(Some(ref dc), ref rack)) => (dc.as_str(), rack.as_ref().map(|r| r.as_str())),
+                _ => continue,
+            };
+            let dc = match dcs.get_mut(dc) {
+                Some(dc) if dc.added < dc.wanted => dc,
+                _ => continue,
+            };
+            if dc.seen_racks.len() == dc.racks {
+                replicas.push(addr);
+                dc.added += 1;
+            } else if dc.seen_racks.contains(&rack) {
+                if !dc.skipped.contains(&addr) {
+                    dc.skipped.push(addr);
+                }
+            } else {
+                replicas.push(addr);
+                dc.added += 1;
+                dc.seen_racks.insert(rack);
+                if dc.seen_racks.len() == dc.racks {
+                    for skipped in dc.skipped.drain(..) {
+                        if dc.added == dc.wanted {
+                            break;
+                        }
+                        replicas.push(skipped);
+                        dc.added += 1;
+                    }
+                }
+            }
+        }
+        replicas
+    }
+}
+
+impl ReplicaLocator for TokenRing {
+    fn replicas(&self, key: &RoutingKey) -> Vec<SocketAddr> {
+        let token = self.partitioner.token(&key.key);
+        self.replicas_of_token(key.keyspace.as_ref().map(|k| k.as_str()), &token)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn node(addr: &str, dc: &str, rack: &str, token: i64) -> Node {
+        Node {
+            address: addr.parse().unwrap(),
+            host_id: None,
+            datacenter: Some(dc.to_string()),
+            rack: Some(rack.to_string()),
+            tokens: vec![token.to_string()],
+            release_version: None,
+            is_up: true,
+        }
+    }
+
+    fn addrs(addrs: &[&str]) -> Vec<SocketAddr> {
+        addrs.iter().map(|a| format!("10.0.0.{}:9042", a).parse().unwrap()).collect()
+    }
+
+    fn ring() -> TokenRing {
+        let nodes = vec![
+            node("10.0.0.1:9042", "dc1", "r1", -100),
+            node("10.0.0.2:9042", "dc1", "r1", 0),
+            node("10.0.0.3:9042", "dc1", "r2", 100),
+            node("10.0.0.4:9042", "dc2", "r1", -50),
+            node("10.0.0.5:9042", "dc2", "r1", 50),
+        ];
+        let mut ring = TokenRing::new(Partitioner::Murmur3, &nodes).unwrap();
+        ring.set_replication("simple", ReplicationStrategy::Simple { replication_factor: 2 });
+        let factors = vec![("dc1".to_string(), 2), ("dc2".to_string(), 1)];
+        ring.set_replication(
+            "nts",
+            ReplicationStrategy::NetworkTopology { replication_factors: factors.into_iter().collect() },
+        );
+        ring.set_replication(
+            "nts_dc1",
+            ReplicationStrategy::NetworkTopology { replication_factors: Some(("dc1".to_string(), 3)).into_iter().collect() },
+        );
+        ring
+    }
+
+    #[test]
+    fn owner() {
+        let ring = ring();
+        assert_eq!(ring.owner(&Token::Murmur3(-200)), Some(addrs(&["1"])[0]));
+        assert_eq!(ring.owner(&Token::Murmur3(0)), Some(addrs(&["2"])[0]));
+        assert_eq!(ring.owner(&Token::Murmur3(101)), Some(addrs(&["1"])[0]));
+        assert_eq!(TokenRing::new(Partitioner::Murmur3, &[]).unwrap().owner(&Token::Murmur3(0)), None);
+    }
+
+    #[test]
+    fn simple_strategy() {
+        let ring = ring();
+        assert_eq!(ring.replicas_of_token(Some("simple"), &Token::Murmur3(1)), addrs(&["5", "3"]));
+        assert_eq!(ring.replicas_of_token(Some("unknown"), &Token::Murmur3(1)), addrs(&["5"]));
+        assert_eq!(ring.replicas_of_token(None, &Token::Murmur3(1)), addrs(&["5"]));
+    }
+
+    #[test]
+    fn network_topology_strategy() {
+        let ring = ring();
+        assert_eq!(ring.replicas_of_token(Some("nts"), &Token::Murmur3(-100)), addrs(&["1", "4", "3"]));
+        // Nodes of other datacenters are skipped, and the node on an already used rack is only
+        // used once all racks have a replica.
+        assert_eq!(ring.replicas_of_token(Some("nts_dc1"), &Token::Murmur3(1)), addrs(&["3", "1", "2"]));
+        assert_eq!(ring.replicas_of_token(Some("nts_dc1"), &Token::Murmur3(-100)), addrs(&["1", "3", "2"]));
+    }
+
+    #[test]
+    fn token_ranges() {
+        let ranges = ring().token_ranges(Some("simple"));
+        assert_eq!(ranges.len(), 5);
+        assert_eq!(
+            ranges[0],
+            TokenRange {
+                start: Token::Murmur3(100),
+                end: Token::Murmur3(-100),
+                replicas: addrs(&["1", "4"]),
+            }
+        );
+        assert_eq!(ranges[4].start, Token::Murmur3(50));
+        assert_eq!(ranges[4].end, Token::Murmur3(100));
+    }
+
+    #[test]
+    fn replicas_of_routing_key() {
+        let key = RoutingKey {
+            keyspace: Some("simple".to_string()),
+            key: vec![0, 0, 0, 1],
+        };
+        assert_eq!(ReplicaLocator::replicas(&ring(), &key), addrs(&["1", "4"]));
+    }
+
+    #[test]
+    fn replication_strategy_from_options() {
+        let options = |o: &[(&str, &str)]| -> HashMap<String, String> {
+            o.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        assert_eq!(
+            ReplicationStrategy::from_options(&options(&[
+                ("class", "org.apache.cassandra.locator.SimpleStrategy"),
+                ("replication_factor", "3"),
+            ])),
+            Some(ReplicationStrategy::Simple { replication_factor: 3 })
+        );
+        assert_eq!(
+            ReplicationStrategy::from_options(&options(&[
+                ("class", "org.apache.cassandra.locator.NetworkTopologyStrategy"),
+                ("dc1", "3"),
+            ])),
+            Some(ReplicationStrategy::NetworkTopology {
+                replication_factors: Some(("dc1".to_string(), 3)).into_iter().collect(),
+            })
+        );
+        assert_eq!(
+            ReplicationStrategy::from_options(&options(&[("class", "org.apache.cassandra.locator.LocalStrategy")])),
+            Some(ReplicationStrategy::Other("org.apache.cassandra.locator.LocalStrategy".to_string()))
+        );
+        assert_eq!(
+            ReplicationStrategy::from_options(&options(&[("class", "SimpleStrategy"), ("replication_factor", "x")])),
+            None
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use codec::request::{self, RoutingKey};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashSet};
+use std::net::SocketAddr;
+use std::rc::Rc;
+
+use super::super::cluster::Node;
+
+/// How close a node is considered to be, which determines if and how it is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Distance {
+    Local,
+    /// Only used if no local node is available.
+    Remote,
+    /// Never used, and no connections are opened to it.
+    Ignored,
+}
+
+/// Decides which nodes a request is sent to.
+pub trait LoadBalancingPolicy {
+    fn distance(&self, node: &Node) -> Distance;
+
+    /// The nodes to send `request` to, in the order they should be tried if sending it fails.
+    /// Nodes which are down or ignored are never part of the plan.
+    fn query_plan(&self, request: &request::Message, nodes: &[Node]) -> Vec<SocketAddr>;
+
+    /// Called by the session with the token ring of the cluster once it is connected, and
+    /// again whenever nodes joined, left or moved.
+    fn update_replicas(&self, _replicas: Rc<ReplicaLocator>) {}
+}
+
+/// Finds the nodes which store a partition.
+pub trait ReplicaLocator {
+    /// The replicas of the partition identified by `key`, with the primary replica first.
+    fn replicas(&self, key: &RoutingKey) -> Vec<SocketAddr>;
+}
+
+/// Rotate `addresses` by the value of `counter`, which is incremented, to spread the load
+/// over all of them.
+fn rotate(mut addresses: Vec<SocketAddr>, counter: &Cell<usize>) -> Vec<SocketAddr> {
+    let n = counter.get();
+    counter.set(n.wrapping_add(1));
+    if !addresses.is_empty() {
+        let len = addresses.len();
+        addresses.rotate_left(n % len);
+    }
+    addresses
+}
+
+/// Uses all nodes in turn, regardless of their datacenter.
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    counter: Cell<usize>,
+}
+
+impl RoundRobin {
+    pub fn new() -> Self {
+        RoundRobin::default()
+    }
+}
+
+impl LoadBalancingPolicy for RoundRobin {
+    fn distance(&self, _node: &Node) -> Distance {
+        Distance::Local
+    }
+
+    fn query_plan(&self, _request: &request::Message, nodes: &[Node]) -> Vec<SocketAddr> {
+        let up = nodes.iter().filter(|n| n.is_up).map(|n| n.address).collect();
+        rotate(up, &self.counter)
+    }
+}
+
+/// Uses all nodes of the local datacenter in turn. Nodes of other datacenters are only tried
+/// once all local nodes failed.
+#[derive(Debug)]
+pub struct DcAwareRoundRobin {
+    local_dc: String,
+    used_hosts_per_remote_dc: usize,
+    counter: Cell<usize>,
+}
+
+impl DcAwareRoundRobin {
+    /// Use the nodes of `local_dc`, and fall back to up to `used_hosts_per_remote_dc` nodes
+    /// of each other datacenter. With 0, remote nodes are never used.
+    pub fn new(local_dc: &str, used_hosts_per_remote_dc: usize) -> Self {
+        DcAwareRoundRobin {
+            local_dc: local_dc.to_string(),
+            used_hosts_per_remote_dc: used_hosts_per_remote_dc,
+            counter: Cell::new(0),
+        }
+    }
+
+    fn is_local(&self, node: &Node) -> bool {
+        node.datacenter.as_ref().map_or(false, |dc| *dc == self.local_dc)
+    }
+}
+
+impl LoadBalancingPolicy for DcAwareRoundRobin {
+    fn distance(&self, node: &Node) -> Distance {
+        if self.is_local(node) {
+            Distance::Local
+        } else if self.used_hosts_per_remote_dc > 0 {
+            Distance::Remote
+        } else {
+            Distance::Ignored
+        }
+    }
+
+    fn query_plan(&self, _request: &request::Message, nodes: &[Node]) -> Vec<SocketAddr> {
+        let mut local = Vec::new();
+        let mut remote = BTreeMap::new();
+        for node in nodes.iter().filter(|n| n.is_up) {
+            if self.is_local(node) {
+                local.push(node.address);
+            } else {
+                remote
+                    .entry(node.datacenter.clone())
+                    .or_insert_with(Vec::new)
+                    .push(node.address);
+            }
+        }
+        let mut plan = rotate(local, &self.counter);
+        let n = self.counter.get();
+        for (_, mut addresses) in remote {
+            let len = addresses.len();
+            addresses.rotate_left(n % len);
+            plan.extend(addresses.into_iter().take(self.used_hosts_per_remote_dc));
+        }
+        plan
+    }
+}
+
+/// Sends requests with a routing key to the replicas of their partition first, as long as the
+/// wrapped policy considers them local. All other nodes follow in the order of the wrapped
+/// policy, which is used as is for requests without a routing key.
+///
+/// The replicas are located using the token ring the session provides, and the wrapped policy
+/// is used as is until it did.
+pub struct TokenAware<P> {
+    child: P,
+    replicas: RefCell<Option<Rc<ReplicaLocator>>>,
+}
+
+impl<P: LoadBalancingPolicy> TokenAware<P> {
+    pub fn new(child: P) -> Self {
+        TokenAware {
+            child: child,
+            replicas: RefCell::new(None),
+        }
+    }
+}
+
+impl<P: LoadBalancingPolicy> LoadBalancingPolicy for TokenAware<P> {
+    fn distance(&self, node: &Node) -> Distance {
+        self.child.distance(node)
+    }
+
+    fn query_plan(&self, request: &request::Message, nodes: &[Node]) -> Vec<SocketAddr> {
+        let plan = self.child.query_plan(request, nodes);
+        let (key, locator) = match (request.routing_key(), self.replicas.borrow().clone()) {
+            (Some(key), Some(locator)) => (key, locator),
+            _ => return plan,
+        };
+        let replicas: HashSet<_> = locator
+            .replicas(key)
+            .into_iter()
+            .filter(|addr| {
+                nodes.iter().any(|n| {
+                    n.address == *addr && self.child.distance(n) == Distance::Local
+                })
+            })
+            .collect();
+        let (mut first, rest): (Vec<_>, Vec<_>) =
+            plan.into_iter().partition(|addr| replicas.contains(addr));
+        first.extend(rest);
+        first
+    }
+
+    fn update_replicas(&self, replicas: Rc<ReplicaLocator>) {
+        self.child.update_replicas(replicas.clone());
+        *self.replicas.borrow_mut() = Some(replicas);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codec::request::{Message, QueryMessage};
+
+    fn node(addr: &str, dc: &str) -> Node {
+        Node {
+            address: addr.parse().unwrap(),
+            host_id: None,
+            datacenter: Some(dc.to_string()),
+            rack: None,
+            tokens: Vec::new(),
+            release_version: None,
+            is_up: true,
+        }
+    }
+
+    fn addrs(addrs: &[&str]) -> Vec<SocketAddr> {
+        addrs.iter().map(|a| a.parse().unwrap()).collect()
+    }
+
+    fn nodes() -> Vec<Node> {
+        let mut down = node("10.0.0.3:9042", "dc1");
+        down.is_up = false;
+        vec![
+            node("10.0.0.1:9042", "dc1"),
+            node("10.0.0.2:9042", "dc1"),
+            down,
+            node("10.0.1.1:9042", "dc2"),
+            node("10.0.1.2:9042", "dc2"),
+        ]
+    }
+
+    #[test]
+    fn round_robin() {
+        let p = RoundRobin::new();
+        let (msg, nodes) = (Message::Options, nodes());
+        assert_eq!(
+            p.query_plan(&msg, &nodes),
+            addrs(&["10.0.0.1:9042", "10.0.0.2:9042", "10.0.1.1:9042", "10.0.1.2:9042"])
+        );
+        assert_eq!(
+            p.query_plan(&msg, &nodes),
+            addrs(&["10.0.0.2:9042", "10.0.1.1:9042", "10.0.1.2:9042", "10.0.0.1:9042"])
+        );
+        assert!(p.query_plan(&msg, &[]).is_empty());
+    }
+
+    #[test]
+    fn dc_aware_round_robin() {
+        let p = DcAwareRoundRobin::new("dc1", 1);
+        let (msg, nodes) = (Message::Options, nodes());
+        assert_eq!(p.distance(&nodes[0]), Distance::Local);
+        assert_eq!(p.distance(&nodes[3]), Distance::Remote);
+        assert_eq!(
+            p.query_plan(&msg, &nodes),
+            addrs(&["10.0.0.1:9042", "10.0.0.2:9042", "10.0.1.2:9042"])
+        );
+        assert_eq!(
+            p.query_plan(&msg, &nodes),
+            addrs(&["10.0.0.2:9042", "10.0.0.1:9042", "10.0.1.1:9042"])
+        );
+
+        let p = DcAwareRoundRobin::new("dc1", 0);
+        assert_eq!(p.distance(&nodes[3]), Distance::Ignored);
+        assert_eq!(p.query_plan(&msg, &nodes).len(), 2);
+    }
+
+    struct FixedReplicas(Vec<SocketAddr>);
+
+    impl ReplicaLocator for FixedReplicas {
+        fn replicas(&self, _key: &RoutingKey) -> Vec<SocketAddr> {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn token_aware() {
+        let replicas = FixedReplicas(addrs(&["10.0.1.1:9042", "10.0.0.2:9042"]));
+        let p = TokenAware::new(DcAwareRoundRobin::new("dc1", 2));
+        let nodes = nodes();
+        let routed = Message::Query(QueryMessage {
+            routing_key: Some(RoutingKey {
+                keyspace: None,
+                key: vec![1],
+            }),
+            ..Default::default()
+        });
+
+        // Without replicas, the plan of the wrapped policy is used as is.
+        assert_eq!(
+            p.query_plan(&routed, &nodes),
+            addrs(&["10.0.0.1:9042", "10.0.0.2:9042", "10.0.1.2:9042", "10.0.1.1:9042"])
+        );
+
+        p.update_replicas(Rc::new(replicas));
+        let unrouted = Message::Query(QueryMessage::default());
+        assert_eq!(
+            p.query_plan(&unrouted, &nodes),
+            addrs(&["10.0.0.2:9042", "10.0.0.1:9042", "10.0.1.1:9042", "10.0.1.2:9042"])
+        );
+
+        // The remote replica is not preferred over local nodes.
+        assert_eq!(
+            p.query_plan(&routed, &nodes),
+            addrs(&["10.0.0.2:9042", "10.0.0.1:9042", "10.0.1.2:9042", "10.0.1.1:9042"])
+        );
+    }
+}
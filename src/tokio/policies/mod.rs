@@ -0,0 +1,10 @@
+//! Policies which customize how a `Session` sends requests to the nodes of a cluster.
+mod load_balancing;
+mod reconnection;
+mod retry;
+mod speculative_execution;
+
+pub use self::load_balancing::*;
+pub use self::reconnection::*;
+pub use self::retry::*;
+pub use self::speculative_execution::*;
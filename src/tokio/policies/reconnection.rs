@@ -0,0 +1,82 @@
+use std::cmp;
+use std::time::Duration;
+
+/// Decides how long to wait before trying to reestablish a connection which was lost.
+pub trait ReconnectionPolicy {
+    /// The time to wait before the next attempt, after `attempts` attempts failed already.
+    fn next_delay(&self, attempts: u32) -> Duration;
+}
+
+/// Waits the same time before each attempt.
+#[derive(Debug, Clone, Copy)]
+pub struct ConstantReconnection {
+    delay: Duration,
+}
+
+impl ConstantReconnection {
+    pub fn new(delay: Duration) -> Self {
+        ConstantReconnection { delay: delay }
+    }
+}
+
+impl ReconnectionPolicy for ConstantReconnection {
+    fn next_delay(&self, _attempts: u32) -> Duration {
+        self.delay
+    }
+}
+
+/// Doubles the time to wait after each failed attempt, starting with `base_delay`, until it
+/// reaches `max_delay`.
+#[derive(Debug, Clone, Copy)]
+pub struct ExponentialReconnection {
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl ExponentialReconnection {
+    pub fn new(base_delay: Duration, max_delay: Duration) -> Self {
+        ExponentialReconnection {
+            base_delay: base_delay,
+            max_delay: max_delay,
+        }
+    }
+}
+
+impl Default for ExponentialReconnection {
+    /// Starts with one second, and waits ten minutes at most.
+    fn default() -> Self {
+        ExponentialReconnection::new(Duration::from_secs(1), Duration::from_secs(10 * 60))
+    }
+}
+
+impl ReconnectionPolicy for ExponentialReconnection {
+    fn next_delay(&self, attempts: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempts).unwrap_or(u32::max_value());
+        match self.base_delay.checked_mul(factor) {
+            Some(delay) => cmp::min(delay, self.max_delay),
+            None => self.max_delay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn constant_reconnection() {
+        let p = ConstantReconnection::new(Duration::from_secs(2));
+        assert_eq!(p.next_delay(0), Duration::from_secs(2));
+        assert_eq!(p.next_delay(10), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn exponential_reconnection() {
+        let p = ExponentialReconnection::new(Duration::from_millis(100), Duration::from_secs(1));
+        assert_eq!(p.next_delay(0), Duration::from_millis(100));
+        assert_eq!(p.next_delay(1), Duration::from_millis(200));
+        assert_eq!(p.next_delay(3), Duration::from_millis(800));
+        assert_eq!(p.next_delay(4), Duration::from_secs(1));
+        assert_eq!(p.next_delay(40), Duration::from_secs(1));
+    }
+}
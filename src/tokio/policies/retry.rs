@@ -0,0 +1,332 @@
+use codec::request;
+use codec::response::WriteType;
+use codec::primitives::CqlConsistency;
+use std::io;
+
+/// What to do with a request which failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryDecision {
+    /// Send the request to the same host again, optionally at a different consistency.
+    RetrySameHost(Option<CqlConsistency>),
+    /// Send the request to the next host of the query plan, optionally at a different
+    /// consistency.
+    RetryNextHost(Option<CqlConsistency>),
+    /// Return the error to the caller.
+    Rethrow,
+    /// Return an empty result to the caller, as if the request succeeded.
+    Ignore,
+}
+
+/// Decides whether and how failed requests are retried.
+///
+/// `retries` is the amount of times the request was retried already. Requests which are not
+/// idempotent are never retried after write timeouts or connection errors, and the policy isn't
+/// consulted for them.
+pub trait RetryPolicy {
+    /// Not enough replicas answered a read in time.
+    fn on_read_timeout(
+        &self,
+        request: &request::Message,
+        consistency: CqlConsistency,
+        received: i32,
+        block_for: i32,
+        data_present: bool,
+        retries: u32,
+    ) -> RetryDecision;
+
+    /// Not enough replicas acknowledged a write in time.
+    fn on_write_timeout(
+        &self,
+        request: &request::Message,
+        consistency: CqlConsistency,
+        write_type: &WriteType,
+        received: i32,
+        block_for: i32,
+        retries: u32,
+    ) -> RetryDecision;
+
+    /// The coordinator knew that not enough replicas are alive, and didn't try the request.
+    fn on_unavailable(
+        &self,
+        request: &request::Message,
+        consistency: CqlConsistency,
+        required: i32,
+        alive: i32,
+        retries: u32,
+    ) -> RetryDecision;
+
+    /// The coordinator was overloaded or still bootstrapping, and didn't try the request.
+    fn on_overloaded(&self, request: &request::Message, retries: u32) -> RetryDecision;
+
+    /// The connection to the host failed before the response was received, or the response
+    /// didn't arrive in time, in which case `err` is of kind `TimedOut`.
+    fn on_connection_error(&self, request: &request::Message, err: &io::Error, retries: u32) -> RetryDecision;
+}
+
+/// Retries once, and only if doing so is likely to succeed:
+///
+/// * read timeouts if enough replicas answered, but the one asked for data didn't,
+/// * write timeouts of the batch log, which are applied later anyway,
+/// * unavailable errors on the next host, which may see more replicas alive,
+/// * overloaded hosts and connection errors on the next host.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultRetryPolicy;
+
+impl RetryPolicy for DefaultRetryPolicy {
+    fn on_read_timeout(
+        &self,
+        _request: &request::Message,
+        _consistency: CqlConsistency,
+        received: i32,
+        block_for: i32,
+        data_present: bool,
+        retries: u32,
+    ) -> RetryDecision {
+        if retries == 0 && received >= block_for && !data_present {
+            RetryDecision::RetrySameHost(None)
+        } else {
+            RetryDecision::Rethrow
+        }
+    }
+
+    fn on_write_timeout(
+        &self,
+        _request: &request::Message,
+        _consistency: CqlConsistency,
+        write_type: &WriteType,
+        _received: i32,
+        _block_for: i32,
+        retries: u32,
+    ) -> RetryDecision {
+        if retries == 0 && *write_type == WriteType::BatchLog {
+            RetryDecision::RetrySameHost(None)
+        } else {
+            RetryDecision::Rethrow
+        }
+    }
+
+    fn on_unavailable(
+        &self,
+        _request: &request::Message,
+        _consistency: CqlConsistency,
+        _required: i32,
+        _alive: i32,
+        retries: u32,
+    ) -> RetryDecision {
+        if retries == 0 {
+            RetryDecision::RetryNextHost(None)
+        } else {
+            RetryDecision::Rethrow
+        }
+    }
+
+    fn on_overloaded(&self, _request: &request::Message, _retries: u32) -> RetryDecision {
+        RetryDecision::RetryNextHost(None)
+    }
+
+    fn on_connection_error(&self, _request: &request::Message, _err: &io::Error, _retries: u32) -> RetryDecision {
+        RetryDecision::RetryNextHost(None)
+    }
+}
+
+/// Behaves like the `DefaultRetryPolicy`, but also retries at a lower consistency if not
+/// enough replicas answered or are alive, and ignores write timeouts of writes which were
+/// applied by at least one replica.
+///
+/// This trades consistency for availability, which is why the caller won't notice that the
+/// consistency was lowered.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DowngradingConsistencyRetryPolicy;
+
+fn is_serial(consistency: CqlConsistency) -> bool {
+    consistency == CqlConsistency::Serial || consistency == CqlConsistency::LocalSerial
+}
+
+/// The highest consistency `replicas` nodes can achieve.
+fn downgraded(consistency: CqlConsistency, replicas: i32) -> RetryDecision {
+    let consistency = match replicas {
+        n if n >= 3 => CqlConsistency::Three,
+        2 => CqlConsistency::Two,
+        1 => CqlConsistency::One,
+        // Each quorum may fail just because a single datacenter has no replica alive.
+        _ if consistency == CqlConsistency::EachQuorum => CqlConsistency::One,
+        _ => return RetryDecision::Rethrow,
+    };
+    RetryDecision::RetrySameHost(Some(consistency))
+}
+
+impl RetryPolicy for DowngradingConsistencyRetryPolicy {
+    fn on_read_timeout(
+        &self,
+        _request: &request::Message,
+        consistency: CqlConsistency,
+        received: i32,
+        block_for: i32,
+        data_present: bool,
+        retries: u32,
+    ) -> RetryDecision {
+        if retries != 0 || is_serial(consistency) {
+            RetryDecision::Rethrow
+        } else if received < block_for {
+            downgraded(consistency, received)
+        } else if !data_present {
+            RetryDecision::RetrySameHost(None)
+        } else {
+            RetryDecision::Rethrow
+        }
+    }
+
+    fn on_write_timeout(
+        &self,
+        _request: &request::Message,
+        consistency: CqlConsistency,
+        write_type: &WriteType,
+        received: i32,
+        _block_for: i32,
+        retries: u32,
+    ) -> RetryDecision {
+        if retries != 0 {
+            return RetryDecision::Rethrow;
+        }
+        match *write_type {
+            // The write will reach the other replicas eventually.
+            WriteType::Simple | WriteType::Batch if received > 0 => RetryDecision::Ignore,
+            WriteType::UnloggedBatch => downgraded(consistency, received),
+            WriteType::BatchLog => RetryDecision::RetrySameHost(None),
+            _ => RetryDecision::Rethrow,
+        }
+    }
+
+    fn on_unavailable(
+        &self,
+        _request: &request::Message,
+        consistency: CqlConsistency,
+        _required: i32,
+        alive: i32,
+        retries: u32,
+    ) -> RetryDecision {
+        if retries != 0 {
+            RetryDecision::Rethrow
+        } else if is_serial(consistency) {
+            RetryDecision::RetryNextHost(None)
+        } else {
+            downgraded(consistency, alive)
+        }
+    }
+
+    fn on_overloaded(&self, _request: &request::Message, _retries: u32) -> RetryDecision {
+        RetryDecision::RetryNextHost(None)
+    }
+
+    fn on_connection_error(&self, _request: &request::Message, _err: &io::Error, _retries: u32) -> RetryDecision {
+        RetryDecision::RetryNextHost(None)
+    }
+}
+
+/// Never retries, and returns all errors to the caller.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FallthroughRetryPolicy;
+
+impl RetryPolicy for FallthroughRetryPolicy {
+    fn on_read_timeout(
+        &self,
+        _request: &request::Message,
+        _consistency: CqlConsistency,
+        _received: i32,
+        _block_for: i32,
+        _data_present: bool,
+        _retries: u32,
+    ) -> RetryDecision {
+        RetryDecision::Rethrow
+    }
+
+    fn on_write_timeout(
+        &self,
+        _request: &request::Message,
+        _consistency: CqlConsistency,
+        _write_type: &WriteType,
+        _received: i32,
+        _block_for: i32,
+        _retries: u32,
+    ) -> RetryDecision {
+        RetryDecision::Rethrow
+    }
+
+    fn on_unavailable(
+        &self,
+        _request: &request::Message,
+        _consistency: CqlConsistency,
+        _required: i32,
+        _alive: i32,
+        _retries: u32,
+    ) -> RetryDecision {
+        RetryDecision::Rethrow
+    }
+
+    fn on_overloaded(&self, _request: &request::Message, _retries: u32) -> RetryDecision {
+        RetryDecision::Rethrow
+    }
+
+    fn on_connection_error(&self, _request: &request::Message, _err: &io::Error, _retries: u32) -> RetryDecision {
+        RetryDecision::Rethrow
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::RetryDecision::*;
+    use codec::primitives::CqlConsistency::*;
+
+    fn msg() -> request::Message {
+        request::Message::Options
+    }
+
+    #[test]
+    fn default_retry_policy() {
+        let p = DefaultRetryPolicy;
+        assert_eq!(p.on_read_timeout(&msg(), Quorum, 2, 2, false, 0), RetrySameHost(None));
+        assert_eq!(p.on_read_timeout(&msg(), Quorum, 2, 2, false, 1), Rethrow);
+        assert_eq!(p.on_read_timeout(&msg(), Quorum, 1, 2, false, 0), Rethrow);
+        assert_eq!(p.on_read_timeout(&msg(), Quorum, 2, 2, true, 0), Rethrow);
+
+        assert_eq!(p.on_write_timeout(&msg(), Quorum, &WriteType::BatchLog, 0, 2, 0), RetrySameHost(None));
+        assert_eq!(p.on_write_timeout(&msg(), Quorum, &WriteType::Simple, 0, 2, 0), Rethrow);
+
+        assert_eq!(p.on_unavailable(&msg(), Quorum, 2, 1, 0), RetryNextHost(None));
+        assert_eq!(p.on_unavailable(&msg(), Quorum, 2, 1, 1), Rethrow);
+        assert_eq!(p.on_overloaded(&msg(), 3), RetryNextHost(None));
+    }
+
+    #[test]
+    fn downgrading_consistency_retry_policy() {
+        let p = DowngradingConsistencyRetryPolicy;
+        assert_eq!(p.on_read_timeout(&msg(), All, 2, 3, true, 0), RetrySameHost(Some(Two)));
+        assert_eq!(p.on_read_timeout(&msg(), Quorum, 0, 2, false, 0), Rethrow);
+        assert_eq!(p.on_read_timeout(&msg(), EachQuorum, 0, 2, false, 0), RetrySameHost(Some(One)));
+        assert_eq!(p.on_read_timeout(&msg(), Quorum, 2, 2, false, 0), RetrySameHost(None));
+        assert_eq!(p.on_read_timeout(&msg(), Serial, 1, 2, false, 0), Rethrow);
+        assert_eq!(p.on_read_timeout(&msg(), All, 2, 3, true, 1), Rethrow);
+
+        assert_eq!(p.on_write_timeout(&msg(), Quorum, &WriteType::Simple, 1, 2, 0), Ignore);
+        assert_eq!(p.on_write_timeout(&msg(), Quorum, &WriteType::Simple, 0, 2, 0), Rethrow);
+        assert_eq!(
+            p.on_write_timeout(&msg(), All, &WriteType::UnloggedBatch, 4, 5, 0),
+            RetrySameHost(Some(Three))
+        );
+        assert_eq!(p.on_write_timeout(&msg(), Quorum, &WriteType::Counter, 1, 2, 0), Rethrow);
+
+        assert_eq!(p.on_unavailable(&msg(), Quorum, 2, 1, 0), RetrySameHost(Some(One)));
+        assert_eq!(p.on_unavailable(&msg(), LocalSerial, 2, 1, 0), RetryNextHost(None));
+        assert_eq!(p.on_unavailable(&msg(), Quorum, 2, 1, 1), Rethrow);
+    }
+
+    #[test]
+    fn fallthrough_retry_policy() {
+        let p = FallthroughRetryPolicy;
+        assert_eq!(p.on_unavailable(&msg(), Quorum, 2, 1, 0), Rethrow);
+        assert_eq!(p.on_overloaded(&msg(), 0), Rethrow);
+        let err = io::Error::new(io::ErrorKind::BrokenPipe, "closed");
+        assert_eq!(p.on_connection_error(&msg(), &err, 0), Rethrow);
+    }
+}
@@ -0,0 +1,158 @@
+use codec::request;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Decides whether idempotent requests are sent to further hosts while waiting for a response.
+///
+/// The first successful response of any execution is used, and all other executions are
+/// cancelled.
+pub trait SpeculativeExecutionPolicy {
+    /// The time to wait before starting another execution of `request`, of which `executions`
+    /// were started already. `None` starts no further execution.
+    fn next_execution(&self, request: &request::Message, executions: u32) -> Option<Duration>;
+
+    /// Called with the time it took to receive each successful response.
+    fn record_latency(&self, _latency: Duration) {}
+}
+
+/// Never starts another execution.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoSpeculativeExecution;
+
+impl SpeculativeExecutionPolicy for NoSpeculativeExecution {
+    fn next_execution(&self, _request: &request::Message, _executions: u32) -> Option<Duration> {
+        None
+    }
+}
+
+/// Starts up to `max_speculative_executions` further executions, each `delay` after the
+/// previous one.
+#[derive(Debug, Clone, Copy)]
+pub struct ConstantSpeculativeExecution {
+    delay: Duration,
+    max_speculative_executions: u32,
+}
+
+impl ConstantSpeculativeExecution {
+    pub fn new(delay: Duration, max_speculative_executions: u32) -> Self {
+        ConstantSpeculativeExecution {
+            delay: delay,
+            max_speculative_executions: max_speculative_executions,
+        }
+    }
+}
+
+impl SpeculativeExecutionPolicy for ConstantSpeculativeExecution {
+    fn next_execution(&self, _request: &request::Message, executions: u32) -> Option<Duration> {
+        if executions <= self.max_speculative_executions {
+            Some(self.delay)
+        } else {
+            None
+        }
+    }
+}
+
+/// The amount of latencies a `PercentileSpeculativeExecution` keeps.
+const LATENCY_WINDOW: usize = 1000;
+/// The amount of latencies a `PercentileSpeculativeExecution` needs before it starts further
+/// executions.
+const MIN_LATENCIES: usize = 100;
+
+/// Starts up to `max_speculative_executions` further executions, each once the previous one
+/// took longer than the given percentile of the latencies of recent responses.
+#[derive(Debug)]
+pub struct PercentileSpeculativeExecution {
+    percentile: f64,
+    max_speculative_executions: u32,
+    /// The latest latencies in order of arrival, and the same ones in ascending order.
+    latencies: RefCell<(VecDeque<Duration>, Vec<Duration>)>,
+}
+
+impl PercentileSpeculativeExecution {
+    /// `percentile` is between 0 and 100, like 99 for the 99th percentile.
+    pub fn new(percentile: f64, max_speculative_executions: u32) -> Self {
+        PercentileSpeculativeExecution {
+            percentile: percentile,
+            max_speculative_executions: max_speculative_executions,
+            latencies: RefCell::new((VecDeque::with_capacity(LATENCY_WINDOW), Vec::with_capacity(LATENCY_WINDOW))),
+        }
+    }
+
+    /// The configured percentile of the recorded latencies, or `None` if not enough were
+    /// recorded yet.
+    pub fn current_delay(&self) -> Option<Duration> {
+        let latencies = self.latencies.borrow();
+        let sorted = &latencies.1;
+        if sorted.len() < MIN_LATENCIES {
+            return None;
+        }
+        let rank = (self.percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.max(1).min(sorted.len()) - 1])
+    }
+}
+
+impl SpeculativeExecutionPolicy for PercentileSpeculativeExecution {
+    fn next_execution(&self, _request: &request::Message, executions: u32) -> Option<Duration> {
+        if executions <= self.max_speculative_executions {
+            self.current_delay()
+        } else {
+            None
+        }
+    }
+
+    fn record_latency(&self, latency: Duration) {
+        let mut latencies = self.latencies.borrow_mut();
+        let (ref mut window, ref mut sorted) = *latencies;
+        if window.len() == LATENCY_WINDOW {
+            let oldest = window.pop_front().expect("a full window");
+            if let Ok(i) = sorted.binary_search(&oldest) {
+                sorted.remove(i);
+            }
+        }
+        window.push_back(latency);
+        let i = match sorted.binary_search(&latency) {
+            Ok(i) | Err(i) => i,
+        };
+        sorted.insert(i, latency);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn constant_speculative_execution() {
+        let p = ConstantSpeculativeExecution::new(ms(50), 2);
+        let msg = request::Message::Options;
+        assert_eq!(p.next_execution(&msg, 1), Some(ms(50)));
+        assert_eq!(p.next_execution(&msg, 2), Some(ms(50)));
+        assert_eq!(p.next_execution(&msg, 3), None);
+        assert_eq!(NoSpeculativeExecution.next_execution(&msg, 1), None);
+    }
+
+    #[test]
+    fn percentile_speculative_execution() {
+        let p = PercentileSpeculativeExecution::new(99.0, 1);
+        let msg = request::Message::Options;
+        for latency in 1..MIN_LATENCIES as u64 {
+            p.record_latency(ms(latency));
+        }
+        assert_eq!(p.next_execution(&msg, 1), None);
+
+        p.record_latency(ms(100));
+        assert_eq!(p.next_execution(&msg, 1), Some(ms(99)));
+        assert_eq!(p.next_execution(&msg, 2), None);
+
+        // Only the latest latencies are considered.
+        for _ in 0..LATENCY_WINDOW {
+            p.record_latency(ms(5));
+        }
+        assert_eq!(p.current_delay(), Some(ms(5)));
+    }
+}
@@ -0,0 +1,648 @@
+//! A pool of connections to a single host, to spread concurrent requests over multiple sockets.
+use codec::request;
+use codec::header::ProtocolVersion;
+use futures::{future, Future, Stream};
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_service::Service;
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::{io, iter};
+
+use super::client::{Client, ClientHandle, CqlProto, ConnectOptions};
+use super::error::*;
+use super::messages::StreamingMessage;
+use super::policies::{ExponentialReconnection, ReconnectionPolicy};
+use super::utils::io_err;
+
+/// Configuration of a `Pool`.
+#[derive(Clone)]
+pub struct PoolOptions {
+    /// The amount of connections to keep open. At least one connection is used.
+    pub connections: usize,
+    /// Decides how long to wait after failed attempts to replace a lost connection.
+    pub reconnection: Rc<ReconnectionPolicy>,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        PoolOptions {
+            connections: 2,
+            reconnection: Rc::new(ExponentialReconnection::default()),
+        }
+    }
+}
+
+/// Opens another connection to the host of a pool with the given options.
+pub type Connect = Fn(ConnectOptions) -> Box<Future<Item = ClientHandle, Error = Error>>;
+/// Called with whether any connection of a pool is usable, whenever that changes.
+type Listener = Fn(bool);
+
+struct Connection {
+    handle: ClientHandle,
+    in_flight: Cell<usize>,
+}
+
+impl Connection {
+    fn new(handle: ClientHandle) -> Rc<Connection> {
+        Rc::new(Connection {
+            handle: handle,
+            in_flight: Cell::new(0),
+        })
+    }
+}
+
+/// Counts a request as in flight on a connection until it completed or was cancelled.
+struct InFlight(Rc<Connection>);
+
+impl InFlight {
+    fn new(conn: Rc<Connection>) -> InFlight {
+        conn.in_flight.set(conn.in_flight.get() + 1);
+        InFlight(conn)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.set(self.0.in_flight.get() - 1);
+    }
+}
+
+enum Slot {
+    Connected(Rc<Connection>),
+    Connecting,
+}
+
+struct Shared {
+    slots: RefCell<Vec<Slot>>,
+    connect: Box<Connect>,
+    /// The options new connections are opened with, which include the current keyspace.
+    connect_options: RefCell<ConnectOptions>,
+    reactor: Handle,
+    options: PoolOptions,
+    listener: RefCell<Option<Box<Listener>>>,
+}
+
+/// A set of connections to the same host, which sends each request through the connection with
+/// the least requests in flight.
+///
+/// Connections which are closed, fail a request or don't answer a heartbeat are replaced in the
+/// background, which repeats the handshake with the options the pool was connected with. Pools
+/// are cheap to clone, and all clones share the same connections.
+#[derive(Clone)]
+pub struct Pool {
+    shared: Rc<Shared>,
+    version: ProtocolVersion,
+}
+
+impl Pool {
+    /// Open all connections of the pool, or fail if any of them can't be established.
+    ///
+    /// The protocol version is negotiated by the first connection, and used by all others.
+    pub fn connect(
+        client: Client,
+        addr: &SocketAddr,
+        handle: &Handle,
+        options: ConnectOptions,
+        pool_options: PoolOptions,
+    ) -> Box<Future<Item = Pool, Error = Error>> {
+        let addr = *addr;
+        let reactor = handle.clone();
+        Box::new(client.clone().connect(&addr, handle, options.clone()).and_then(
+            move |first| {
+                let client = Client {
+                    protocol: CqlProto {
+                        version: first.protocol_version(),
+                        ..client.protocol
+                    },
+                };
+                let connect_reactor = reactor.clone();
+                let connect: Box<Connect> = Box::new(move |options| {
+                    client.clone().connect(&addr, &connect_reactor, options)
+                });
+                let rest: Vec<_> = (1..pool_options.connections).map(|_| connect(options.clone())).collect();
+                future::join_all(rest).map(move |rest| {
+                    let handles = iter::once(first).chain(rest).collect();
+                    Pool::new(handles, connect, &reactor, options, pool_options)
+                })
+            },
+        ))
+    }
+
+    /// A pool of the connections `handles`, which were opened with `options`. Lost connections
+    /// are replaced by ones opened with `connect`.
+    ///
+    /// The protocol version and heartbeat interval of the first connection are used for all.
+    pub(crate) fn new(
+        handles: Vec<ClientHandle>,
+        connect: Box<Connect>,
+        handle: &Handle,
+        options: ConnectOptions,
+        pool_options: PoolOptions,
+    ) -> Pool {
+        let version = handles[0].protocol_version();
+        let heartbeat_interval = handles[0].heartbeat_interval();
+        let shared = Rc::new(Shared {
+            slots: RefCell::new(handles.iter().map(|_| Slot::Connecting).collect()),
+            connect: connect,
+            connect_options: RefCell::new(options),
+            reactor: handle.clone(),
+            options: pool_options,
+            listener: RefCell::new(None),
+        });
+        for (index, handle) in handles.into_iter().enumerate() {
+            install(&shared, index, handle);
+        }
+        if let Some(interval) = heartbeat_interval {
+            schedule_heartbeats(&shared, interval);
+        }
+        Pool {
+            shared: shared,
+            version: version,
+        }
+    }
+
+    /// The protocol version all connections of the pool use.
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.version
+    }
+
+    /// Call `listener` with `false` once all connections were lost, and with `true` once one
+    /// of them was replaced afterwards. It replaces any previous listener.
+    pub fn on_availability_change(&self, listener: Box<Fn(bool)>) {
+        *self.shared.listener.borrow_mut() = Some(listener);
+    }
+
+    /// Make `keyspace` the keyspace of all connections, including the ones opened later.
+    pub fn use_keyspace(&self, keyspace: &str) -> Box<Future<Item = (), Error = Error>> {
+        self.shared.connect_options.borrow_mut().keyspace = Some(keyspace.to_string());
+        let uses: Vec<_> = self.shared
+            .slots
+            .borrow()
+            .iter()
+            .filter_map(|s| match *s {
+                Slot::Connected(ref c) => Some(c.handle.use_keyspace(keyspace)),
+                Slot::Connecting => None,
+            })
+            .collect();
+        Box::new(future::join_all(uses).map(|_| ()))
+    }
+
+    /// The amount of connections which are currently usable. The others are being replaced.
+    pub fn live_connections(&self) -> usize {
+        live_connections(&self.shared)
+    }
+
+    /// The total amount of requests currently in flight on all connections.
+    pub fn in_flight(&self) -> usize {
+        self.shared
+            .slots
+            .borrow()
+            .iter()
+            .map(|s| match *s {
+                Slot::Connected(ref c) => c.in_flight.get(),
+                Slot::Connecting => 0,
+            })
+            .sum()
+    }
+
+    fn least_loaded(&self) -> Option<(usize, Rc<Connection>)> {
+        self.shared
+            .slots
+            .borrow()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| match *s {
+                Slot::Connected(ref c) => Some((i, c.clone())),
+                Slot::Connecting => None,
+            })
+            .min_by_key(|&(_, ref c)| c.in_flight.get())
+    }
+}
+
+fn live_connections(shared: &Shared) -> usize {
+    shared
+        .slots
+        .borrow()
+        .iter()
+        .filter(|s| match **s {
+            Slot::Connected(_) => true,
+            Slot::Connecting => false,
+        })
+        .count()
+}
+
+fn notify(shared: &Shared, available: bool) {
+    if let Some(ref listener) = *shared.listener.borrow() {
+        listener(available);
+    }
+}
+
+/// Use `handle` as the connection at `index`, and replace it once it is closed.
+fn install(shared: &Rc<Shared>, index: usize, mut handle: ClientHandle) {
+    // Connections of a pool never register for events, which is why their stream only ends
+    // once the connection is closed.
+    let closed = handle.take_events();
+    let conn = Connection::new(handle);
+    shared.slots.borrow_mut()[index] = Slot::Connected(conn.clone());
+    if live_connections(shared) == 1 {
+        notify(shared, true);
+    }
+    if let Some(closed) = closed {
+        let weak = Rc::downgrade(shared);
+        // Connections which were replaced already are closed once they are dropped.
+        let conn = Rc::downgrade(&conn);
+        shared.reactor.spawn(closed.for_each(|_| Ok(())).then(move |_| {
+            if let (Some(shared), Some(conn)) = (weak.upgrade(), conn.upgrade()) {
+                replace(&shared, index, &conn);
+            }
+            Ok(())
+        }));
+    }
+}
+
+/// Send a heartbeat through each connection once it was idle for `interval`, and replace the
+/// connections which don't answer it.
+fn schedule_heartbeats(shared: &Rc<Shared>, interval: Duration) {
+    let delay = heartbeat_delay(shared, interval);
+    let timeout = match Timeout::new(delay, &shared.reactor) {
+        Ok(timeout) => timeout,
+        Err(err) => {
+            warn!("failed to schedule heartbeats of pool: {}", err);
+            return;
+        }
+    };
+    let weak = Rc::downgrade(shared);
+    shared.reactor.spawn(timeout.then(move |_| {
+        if let Some(shared) = weak.upgrade() {
+            send_heartbeats(&shared, interval);
+        }
+        Ok(())
+    }));
+}
+
+/// The time until the first connection was idle for `interval`.
+fn heartbeat_delay(shared: &Shared, interval: Duration) -> Duration {
+    shared
+        .slots
+        .borrow()
+        .iter()
+        .filter_map(|s| match *s {
+            Slot::Connected(ref c) => Some(interval.checked_sub(c.handle.idle_time()).unwrap_or_default()),
+            Slot::Connecting => None,
+        })
+        .min()
+        .unwrap_or(interval)
+}
+
+fn send_heartbeats(shared: &Rc<Shared>, interval: Duration) {
+    let idle: Vec<_> = shared
+        .slots
+        .borrow()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, s)| match *s {
+            Slot::Connected(ref c) if c.handle.idle_time() >= interval => Some((i, c.clone())),
+            _ => None,
+        })
+        .collect();
+    for (index, conn) in idle {
+        let weak = Rc::downgrade(shared);
+        shared.reactor.spawn(conn.handle.heartbeat().then(move |res| {
+            if let (Err(err), Some(shared)) = (res, weak.upgrade()) {
+                warn!("connection {} of pool is defunct: {}", index, err);
+                replace(&shared, index, &conn);
+            }
+            Ok(())
+        }));
+    }
+    schedule_heartbeats(shared, interval);
+}
+
+/// Replace the connection at `index` if it is still `dead`, which might not be the case if it
+/// failed more than one request.
+fn replace(shared: &Rc<Shared>, index: usize, dead: &Rc<Connection>) {
+    {
+        let mut slots = shared.slots.borrow_mut();
+        match slots[index] {
+            Slot::Connected(ref c) if Rc::ptr_eq(c, dead) => {}
+            _ => return,
+        }
+        slots[index] = Slot::Connecting;
+    }
+    debug!("replacing connection {} of pool", index);
+    if live_connections(shared) == 0 {
+        notify(shared, false);
+    }
+    reconnect(shared, index, 0);
+}
+
+fn reconnect(shared: &Rc<Shared>, index: usize, attempts: u32) {
+    let weak = Rc::downgrade(shared);
+    let attempt = (shared.connect)(shared.connect_options.borrow().clone()).then(move |res| {
+        if let Some(shared) = weak.upgrade() {
+            match res {
+                Ok(handle) => install(&shared, index, handle),
+                Err(err) => {
+                    warn!("failed to replace connection {} of pool: {}", index, err);
+                    retry_later(&shared, index, attempts + 1);
+                }
+            }
+        }
+        Ok(())
+    });
+    shared.reactor.spawn(attempt);
+}
+
+fn retry_later(shared: &Rc<Shared>, index: usize, attempts: u32) {
+    let weak: Weak<Shared> = Rc::downgrade(shared);
+    let delay = shared.options.reconnection.next_delay(attempts - 1);
+    let timeout = match Timeout::new(delay, &shared.reactor) {
+        Ok(timeout) => timeout,
+        Err(err) => {
+            warn!("failed to schedule replacement of connection {} of pool: {}", index, err);
+            return;
+        }
+    };
+    shared.reactor.spawn(timeout.then(move |_| {
+        if let Some(shared) = weak.upgrade() {
+            reconnect(&shared, index, attempts);
+        }
+        Ok(())
+    }));
+}
+
+impl Service for Pool {
+    type Request = request::Message;
+    type Response = StreamingMessage;
+    type Error = io::Error;
+    type Future = Box<Future<Item = Self::Response, Error = Self::Error>>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        let (index, conn) = match self.least_loaded() {
+            Some(c) => c,
+            None => return Box::new(future::err(io_err("No connection of the pool is available"))),
+        };
+        let in_flight = InFlight::new(conn);
+        let shared = Rc::downgrade(&self.shared);
+        Box::new(in_flight.0.handle.call(req).then(move |res| {
+            let conn = in_flight.0.clone();
+            drop(in_flight);
+            // Failures of the connection itself surface as io errors, unlike errors reported
+            // by the server. A request which timed out says nothing about the connection.
+            if let Err(ref err) = res {
+                if err.kind() != io::ErrorKind::TimedOut {
+                    if let Some(shared) = shared.upgrade() {
+                        replace(&shared, index, &conn);
+                    }
+                }
+            }
+            res
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codec::primitives::{CqlFrom, CqlString, CqlStringMultiMap};
+    use codec::response::{EventMessage, ResultMessage, SupportedMessage};
+    use futures::sync::mpsc::UnboundedSender;
+    use std::collections::HashMap;
+    use tokio_core::reactor::Core;
+
+    type Respond = Fn(usize, request::Message) -> io::Result<StreamingMessage>;
+
+    /// Opens fake connections, which are numbered in the order they were opened.
+    struct Connections {
+        reactor: Handle,
+        respond: Rc<Respond>,
+        /// The options of each connection, and the sender of its events which closes it when
+        /// dropped.
+        opened: RefCell<Vec<(ConnectOptions, Option<UnboundedSender<EventMessage>>)>>,
+    }
+
+    impl Connections {
+        fn open(&self, options: ConnectOptions) -> ClientHandle {
+            let id = self.opened.borrow().len();
+            let respond = self.respond.clone();
+            let (handle, events) = ClientHandle::fake(ProtocolVersion::Version3, &self.reactor, &options, move |req| {
+                respond(id, req)
+            });
+            self.opened.borrow_mut().push((options, Some(events)));
+            handle
+        }
+
+        fn count(&self) -> usize {
+            self.opened.borrow().len()
+        }
+    }
+
+    fn pool<F>(core: &Core, options: ConnectOptions, respond: F) -> (Pool, Rc<Connections>)
+    where
+        F: Fn(usize, request::Message) -> io::Result<StreamingMessage> + 'static,
+    {
+        let connections = Rc::new(Connections {
+            reactor: core.handle(),
+            respond: Rc::new(respond),
+            opened: RefCell::new(Vec::new()),
+        });
+        let handles = vec![connections.open(options.clone()), connections.open(options.clone())];
+        let opener = connections.clone();
+        let connect: Box<Connect> = Box::new(move |options| Box::new(future::ok(opener.open(options))));
+        let pool_options = PoolOptions {
+            connections: 2,
+            ..Default::default()
+        };
+        (Pool::new(handles, connect, &core.handle(), options, pool_options), connections)
+    }
+
+    fn without_heartbeats() -> ConnectOptions {
+        ConnectOptions {
+            heartbeat_interval: None,
+            ..Default::default()
+        }
+    }
+
+    /// Run everything spawned on the reactor which is ready.
+    fn settle(core: &mut Core) {
+        for _ in 0..10 {
+            core.turn(Some(Duration::from_millis(1)));
+        }
+    }
+
+    #[test]
+    fn sends_through_least_loaded_connection() {
+        let core = Core::new().unwrap();
+        let used = Rc::new(RefCell::new(Vec::new()));
+        let log = used.clone();
+        let (pool, _connections) = pool(&core, without_heartbeats(), move |id, _| {
+            log.borrow_mut().push(id);
+            Ok(StreamingMessage::Ready)
+        });
+        let mut core = core;
+
+        let first = pool.call(request::Message::Options);
+        let second = pool.call(request::Message::Options);
+        assert_eq!(pool.in_flight(), 2);
+        drop(first);
+        assert_eq!(pool.in_flight(), 1);
+        let third = pool.call(request::Message::Options);
+        assert_eq!(*used.borrow(), vec![0, 1, 0]);
+
+        core.run(second).unwrap();
+        assert_eq!(pool.in_flight(), 1);
+        let fourth = pool.call(request::Message::Options);
+        assert_eq!(*used.borrow(), vec![0, 1, 0, 1]);
+        core.run(third.join(fourth)).unwrap();
+        assert_eq!(pool.in_flight(), 0);
+        assert_eq!(pool.live_connections(), 2);
+    }
+
+    #[test]
+    fn replaces_failed_connections() {
+        let mut core = Core::new().unwrap();
+        let (pool, connections) = pool(&core, without_heartbeats(), |id, req| match (id, req) {
+            (0, request::Message::Options) => Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")),
+            (0, _) => Err(io_err("connection reset")),
+            _ => Ok(StreamingMessage::Ready),
+        });
+
+        assert!(core.run(pool.call(request::Message::Options)).is_err());
+        settle(&mut core);
+        assert_eq!(connections.count(), 2);
+
+        assert!(core.run(pool.call(request::Message::Batch(Default::default()))).is_err());
+        assert_eq!(pool.live_connections(), 1);
+        settle(&mut core);
+        assert_eq!(connections.count(), 3);
+        assert_eq!(pool.live_connections(), 2);
+
+        connections.opened.borrow_mut()[1].1.take();
+        settle(&mut core);
+        assert_eq!(connections.count(), 4);
+        assert_eq!(pool.live_connections(), 2);
+    }
+
+    #[test]
+    fn replaces_connections_once() {
+        let mut core = Core::new().unwrap();
+        let (pool, connections) = pool(&core, without_heartbeats(), |_, _| Ok(StreamingMessage::Ready));
+        let (index, dead) = pool.least_loaded().unwrap();
+
+        replace(&pool.shared, index, &dead);
+        replace(&pool.shared, index, &dead);
+        settle(&mut core);
+        assert_eq!(connections.count(), 3);
+
+        replace(&pool.shared, index, &dead);
+        settle(&mut core);
+        assert_eq!(connections.count(), 3);
+        assert_eq!(pool.live_connections(), 2);
+    }
+
+    fn supported() -> StreamingMessage {
+        StreamingMessage::Supported(SupportedMessage(CqlStringMultiMap::try_from(HashMap::new()).unwrap()))
+    }
+
+    fn with_heartbeats(interval: Duration) -> ConnectOptions {
+        ConnectOptions {
+            heartbeat_interval: Some(interval),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn schedules_heartbeats_by_idle_time() {
+        let core = Core::new().unwrap();
+        let interval = Duration::from_secs(60);
+        let (pool, _connections) = pool(&core, with_heartbeats(interval), |_, _| Ok(StreamingMessage::Ready));
+        let delay = heartbeat_delay(&pool.shared, interval);
+        assert!(delay <= interval && delay > interval - Duration::from_secs(1));
+
+        let (_, conn) = pool.least_loaded().unwrap();
+        conn.handle.set_idle_time(Duration::from_secs(20));
+        let delay = heartbeat_delay(&pool.shared, interval);
+        assert!(delay <= Duration::from_secs(40) && delay > Duration::from_secs(39));
+
+        conn.handle.set_idle_time(Duration::from_secs(90));
+        assert_eq!(heartbeat_delay(&pool.shared, interval), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn sends_heartbeats_through_idle_connections() {
+        let mut core = Core::new().unwrap();
+        let interval = Duration::from_secs(60);
+        let heartbeats = Rc::new(RefCell::new(Vec::new()));
+        let log = heartbeats.clone();
+        let (pool, connections) = pool(&core, with_heartbeats(interval), move |id, req| match req {
+            request::Message::Options => {
+                log.borrow_mut().push(id);
+                match id {
+                    1 => Err(io_err("connection reset")),
+                    _ => Ok(supported()),
+                }
+            }
+            _ => Ok(StreamingMessage::Ready),
+        });
+
+        settle(&mut core);
+        assert!(heartbeats.borrow().is_empty());
+
+        for slot in pool.shared.slots.borrow().iter() {
+            if let Slot::Connected(ref c) = *slot {
+                c.handle.set_idle_time(interval);
+            }
+        }
+        core.run(pool.call(request::Message::Batch(Default::default()))).unwrap();
+        send_heartbeats(&pool.shared, interval);
+        settle(&mut core);
+        assert_eq!(*heartbeats.borrow(), vec![1]);
+        assert_eq!(connections.count(), 3);
+        assert_eq!(pool.live_connections(), 2);
+    }
+
+    #[test]
+    fn replaces_connections_which_miss_heartbeats() {
+        let mut core = Core::new().unwrap();
+        let (pool, connections) = pool(&core, with_heartbeats(Duration::from_millis(10)), |id, req| match (id, req) {
+            (0, request::Message::Options) => Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")),
+            (_, request::Message::Options) => Ok(supported()),
+            _ => Ok(StreamingMessage::Ready),
+        });
+
+        let wait = Timeout::new(Duration::from_millis(50), &core.handle()).unwrap();
+        core.run(wait).unwrap();
+        assert_eq!(connections.count(), 3);
+        assert_eq!(pool.live_connections(), 2);
+    }
+
+    #[test]
+    fn reconnects_with_current_keyspace() {
+        let mut core = Core::new().unwrap();
+        let queries = Rc::new(RefCell::new(Vec::new()));
+        let log = queries.clone();
+        let (pool, connections) = pool(&core, without_heartbeats(), move |id, req| match req {
+            request::Message::Query(query) => {
+                log.borrow_mut().push((id, query.query.as_ref().to_string()));
+                let keyspace = CqlString::try_from("ks").unwrap();
+                Ok(StreamingMessage::Result(ResultMessage::Keyspace(keyspace), Default::default()))
+            }
+            _ => Ok(StreamingMessage::Ready),
+        });
+
+        core.run(pool.use_keyspace("ks")).unwrap();
+        let mut used = queries.borrow().clone();
+        used.sort();
+        assert_eq!(used, vec![(0, String::from("USE \"ks\"")), (1, String::from("USE \"ks\""))]);
+
+        let (index, conn) = pool.least_loaded().unwrap();
+        replace(&pool.shared, index, &conn);
+        settle(&mut core);
+        let opened = connections.opened.borrow();
+        assert_eq!(opened.len(), 3);
+        assert_eq!(opened[0].0.keyspace, None);
+        assert_eq!(opened[2].0.keyspace, Some(String::from("ks")));
+    }
+}
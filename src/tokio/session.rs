@@ -0,0 +1,870 @@
+//! Sending requests to any node of a cluster.
+//!
+//! A session keeps a `Pool` of connections to each node its load balancing policy wants to use,
+//! and sends every request along the query plan the policy produces for it. Idempotent requests
+//! may be sent to multiple nodes of the plan at once, as decided by the speculative execution
+//! policy.
+//!
+//! The keyspace set by a `USE` query sent through a session is used by all of its connections,
+//! including the ones opened later.
+use codec::request;
+use codec::response::{CqlErrorKind, ResponseInfo, ResultMessage};
+use futures::{future, Async, Future, Poll, Stream};
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_service::Service;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::time::Instant;
+use std::{io, vec};
+
+use super::client::{Client, ConnectOptions};
+use super::cluster::{Cluster, HostEvent};
+use super::error::*;
+use super::messages::StreamingMessage;
+use super::policies::{DefaultRetryPolicy, Distance, LoadBalancingPolicy, NoSpeculativeExecution, RetryDecision,
+                      RetryPolicy, RoundRobin, SpeculativeExecutionPolicy};
+use super::pool::{Pool, PoolOptions};
+use super::utils::io_err;
+
+/// Configuration of a `Session`.
+pub struct SessionOptions {
+    /// The options of the pool opened to each node.
+    pub pool: PoolOptions,
+    pub load_balancing: Box<LoadBalancingPolicy>,
+    pub retry: Box<RetryPolicy>,
+    pub speculative_execution: Box<SpeculativeExecutionPolicy>,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        SessionOptions {
+            pool: PoolOptions::default(),
+            load_balancing: Box::new(RoundRobin::new()),
+            retry: Box::new(DefaultRetryPolicy),
+            speculative_execution: Box::new(NoSpeculativeExecution),
+        }
+    }
+}
+
+/// The nodes a request may still be sent to, which is shared by all of its executions.
+type QueryPlan = Rc<RefCell<vec::IntoIter<SocketAddr>>>;
+
+enum PoolSlot {
+    Ready(Pool),
+    Connecting,
+}
+
+struct Shared {
+    cluster: Cluster,
+    policy: Box<LoadBalancingPolicy>,
+    retry: Box<RetryPolicy>,
+    speculative_execution: Box<SpeculativeExecutionPolicy>,
+    pools: RefCell<HashMap<SocketAddr, PoolSlot>>,
+    client: Client,
+    /// The options of new pools, which include the current keyspace.
+    options: RefCell<ConnectOptions>,
+    pool_options: PoolOptions,
+    reactor: Handle,
+}
+
+/// A connection to a whole cluster, which sends each request to the nodes chosen by a
+/// `LoadBalancingPolicy`.
+///
+/// Failed requests are retried as decided by a `RetryPolicy`, possibly on the next node of the
+/// query plan. Sessions are cheap to clone, and all clones share the same connections.
+#[derive(Clone)]
+pub struct Session {
+    shared: Rc<Shared>,
+}
+
+impl Session {
+    /// Discover the cluster through the first reachable contact point, and open a pool to
+    /// each node which is up and not ignored by the load balancing policy. The policy is
+    /// provided with the token ring of the cluster, which is built again whenever nodes join,
+    /// leave or move.
+    ///
+    /// Nodes which can't be connected to are skipped, and connected to again once they are
+    /// part of a query plan.
+    pub fn connect(
+        client: Client,
+        contact_points: Vec<SocketAddr>,
+        handle: &Handle,
+        options: ConnectOptions,
+        session_options: SessionOptions,
+    ) -> Box<Future<Item = Session, Error = Error>> {
+        let reactor = handle.clone();
+        let reconnection = session_options.pool.reconnection.clone();
+        let cluster = Cluster::connect(client.clone(), contact_points, handle, options.clone(), reconnection);
+        Box::new(cluster.and_then(move |cluster| {
+            let session = Session {
+                shared: Rc::new(Shared {
+                    cluster: cluster,
+                    policy: session_options.load_balancing,
+                    retry: session_options.retry,
+                    speculative_execution: session_options.speculative_execution,
+                    pools: RefCell::new(HashMap::new()),
+                    client: client,
+                    options: RefCell::new(options),
+                    pool_options: session_options.pool,
+                    reactor: reactor,
+                }),
+            };
+            let pools: Vec<_> = session
+                .shared
+                .cluster
+                .nodes()
+                .iter()
+                .filter(|n| n.is_up && session.shared.policy.distance(n) != Distance::Ignored)
+                .map(|n| connect_pool(&session.shared, n.address))
+                .collect();
+            watch_topology(&session.shared);
+            let ring = update_replicas(&session.shared);
+            future::join_all(pools).join(ring).then(move |_| Ok(session))
+        }))
+    }
+
+    pub fn cluster(&self) -> &Cluster {
+        &self.shared.cluster
+    }
+
+    /// The keyspace unqualified table names currently resolve to, as set by the
+    /// `ConnectOptions` or the last `USE` query.
+    pub fn keyspace(&self) -> Option<String> {
+        self.shared.options.borrow().keyspace.clone()
+    }
+
+    /// The addresses of all nodes a pool is currently connected to.
+    pub fn connected_nodes(&self) -> Vec<SocketAddr> {
+        let mut nodes: Vec<_> = self.shared
+            .pools
+            .borrow()
+            .iter()
+            .filter_map(|(addr, slot)| match *slot {
+                PoolSlot::Ready(_) => Some(*addr),
+                PoolSlot::Connecting => None,
+            })
+            .collect();
+        nodes.sort();
+        nodes
+    }
+}
+
+/// Provide the current token ring of the cluster to the load balancing policy.
+fn update_replicas(shared: &Rc<Shared>) -> Box<Future<Item = (), Error = ()>> {
+    let weak = Rc::downgrade(shared);
+    Box::new(shared.cluster.token_ring_with_replication().then(move |res| {
+        match (res, weak.upgrade()) {
+            (Ok(ring), Some(shared)) => shared.policy.update_replicas(Rc::new(ring)),
+            (Err(err), _) => warn!("failed to build the token ring: {}", err),
+            (Ok(_), None) => {}
+        }
+        Ok(())
+    }))
+}
+
+/// Update the token ring of the load balancing policy whenever nodes join, leave or move,
+/// until the session is gone.
+fn watch_topology(shared: &Rc<Shared>) {
+    let weak = Rc::downgrade(shared);
+    let events = shared.cluster.host_events().for_each(move |event| {
+        let shared = match weak.upgrade() {
+            Some(shared) => shared,
+            None => return Err(()),
+        };
+        match event {
+            HostEvent::Added(_) | HostEvent::Removed(_) | HostEvent::Moved(_) => {
+                shared.reactor.spawn(update_replicas(&shared))
+            }
+            HostEvent::Up(_) | HostEvent::Down(_) => {}
+        }
+        Ok(())
+    });
+    shared.reactor.spawn(events);
+}
+
+/// Open a pool to `addr` in the background. Failures are logged, and the next request routed
+/// to `addr` tries again. The node is marked down while its pool has no connection.
+fn connect_pool(shared: &Rc<Shared>, addr: SocketAddr) -> Box<Future<Item = (), Error = ()>> {
+    shared.pools.borrow_mut().insert(addr, PoolSlot::Connecting);
+    let weak = Rc::downgrade(shared);
+    let options = shared.options.borrow().clone();
+    let keyspace = options.keyspace.clone();
+    let pool = Pool::connect(
+        shared.client.clone(),
+        &addr,
+        &shared.reactor,
+        options,
+        shared.pool_options.clone(),
+    );
+    Box::new(pool.then(move |res| {
+        if let Some(shared) = weak.upgrade() {
+            let mut pools = shared.pools.borrow_mut();
+            match res {
+                Ok(pool) => {
+                    let cluster = shared.cluster.clone();
+                    pool.on_availability_change(Box::new(move |up| cluster.set_up(&addr, up)));
+                    // The keyspace may have changed while the pool was connecting.
+                    let current = shared.options.borrow().keyspace.clone();
+                    if let Some(current) = current.filter(|k| Some(k) != keyspace.as_ref()) {
+                        use_keyspace_on(&shared, &pool, current);
+                    }
+                    pools.insert(addr, PoolSlot::Ready(pool));
+                }
+                Err(err) => {
+                    warn!("failed to connect to node {}: {}", addr, err);
+                    pools.remove(&addr);
+                }
+            }
+        }
+        Ok(())
+    }))
+}
+
+/// Make `keyspace` the keyspace of all pools, if it isn't already.
+fn use_keyspace(shared: &Rc<Shared>, keyspace: &str) {
+    if shared.options.borrow().keyspace.as_ref().map_or(false, |k| k == keyspace) {
+        return;
+    }
+    debug!("using keyspace {} on all connections", keyspace);
+    shared.options.borrow_mut().keyspace = Some(keyspace.to_string());
+    for slot in shared.pools.borrow().values() {
+        if let PoolSlot::Ready(ref pool) = *slot {
+            use_keyspace_on(shared, pool, keyspace.to_string());
+        }
+    }
+}
+
+fn use_keyspace_on(shared: &Shared, pool: &Pool, keyspace: String) {
+    shared.reactor.spawn(pool.use_keyspace(&keyspace).or_else(move |err| {
+        warn!("failed to use keyspace {} on a pool: {}", keyspace, err);
+        Ok(())
+    }));
+}
+
+/// The pool of `addr`, or `None` if there is none yet. In that case, connecting to it is
+/// started in the background.
+fn pool_for(shared: &Rc<Shared>, addr: SocketAddr) -> Option<Pool> {
+    let connecting = match shared.pools.borrow().get(&addr) {
+        Some(&PoolSlot::Ready(ref pool)) => return Some(pool.clone()),
+        Some(&PoolSlot::Connecting) => true,
+        None => false,
+    };
+    if !connecting {
+        let pool = connect_pool(shared, addr);
+        shared.reactor.spawn(pool);
+    }
+    None
+}
+
+/// Send `req` to the first node of `plan` which has a pool. If there is none, the request fails
+/// with `failure`, the outcome of the previous attempt to send it.
+fn send(
+    shared: Rc<Shared>,
+    plan: QueryPlan,
+    req: request::Message,
+    retries: u32,
+    failure: Option<io::Result<StreamingMessage>>,
+) -> Box<Future<Item = StreamingMessage, Error = io::Error>> {
+    let pool = plan.borrow_mut().by_ref().filter_map(|addr| pool_for(&shared, addr)).next();
+    match pool {
+        Some(pool) => execute(shared, plan, pool, req, retries),
+        None => Box::new(future::result(
+            failure.unwrap_or_else(|| Err(io_err("No host available to send the request to"))),
+        )),
+    }
+}
+
+/// Send `req` through `pool`, and retry it as the retry policy decides if it fails.
+fn execute(
+    shared: Rc<Shared>,
+    plan: QueryPlan,
+    pool: Pool,
+    mut req: request::Message,
+    retries: u32,
+) -> Box<Future<Item = StreamingMessage, Error = io::Error>> {
+    Box::new(pool.call(req.clone()).then(move |res| -> Box<Future<Item = StreamingMessage, Error = io::Error>> {
+        let decision = match res {
+            Ok(StreamingMessage::Error(ref msg)) => retry_decision(&*shared.retry, &req, &msg.kind, retries),
+            Ok(_) => return Box::new(future::result(res)),
+            Err(ref err) if req.is_idempotent() => shared.retry.on_connection_error(&req, err, retries),
+            Err(_) => RetryDecision::Rethrow,
+        };
+        let consistency = match decision {
+            RetryDecision::Rethrow => return Box::new(future::result(res)),
+            RetryDecision::Ignore => {
+                return Box::new(future::ok(StreamingMessage::Result(ResultMessage::Void, ResponseInfo::default())))
+            }
+            RetryDecision::RetrySameHost(consistency) |
+            RetryDecision::RetryNextHost(consistency) => consistency,
+        };
+        if let Some(consistency) = consistency {
+            req.set_consistency(consistency);
+        }
+        debug!("retrying failed request: {:?}", decision);
+        match decision {
+            RetryDecision::RetrySameHost(_) => execute(shared, plan, pool, req, retries + 1),
+            _ => send(shared, plan, req, retries + 1, Some(res)),
+        }
+    }))
+}
+
+fn retry_decision(
+    policy: &RetryPolicy,
+    req: &request::Message,
+    kind: &CqlErrorKind,
+    retries: u32,
+) -> RetryDecision {
+    match *kind {
+        CqlErrorKind::ReadTimeout {
+            consistency,
+            received,
+            block_for,
+            data_present,
+        } => policy.on_read_timeout(req, consistency, received, block_for, data_present, retries),
+        CqlErrorKind::WriteTimeout {
+            consistency,
+            received,
+            block_for,
+            ref write_type,
+        } if req.is_idempotent() => {
+            policy.on_write_timeout(req, consistency, write_type, received, block_for, retries)
+        }
+        CqlErrorKind::Unavailable {
+            consistency,
+            required,
+            alive,
+        } => policy.on_unavailable(req, consistency, required, alive, retries),
+        CqlErrorKind::Overloaded | CqlErrorKind::IsBootstrapping => policy.on_overloaded(req, retries),
+        _ => RetryDecision::Rethrow,
+    }
+}
+
+/// All executions of a request, which completes with the first successful response, or the
+/// last failure once all executions failed.
+struct Executions {
+    shared: Rc<Shared>,
+    plan: QueryPlan,
+    req: request::Message,
+    /// The running executions, along with the time they were started at.
+    running: Vec<(Instant, Box<Future<Item = StreamingMessage, Error = io::Error>>)>,
+    started: u32,
+    next: Option<Timeout>,
+    failure: Option<io::Result<StreamingMessage>>,
+}
+
+impl Executions {
+    fn new(shared: Rc<Shared>, plan: QueryPlan, req: request::Message) -> Self {
+        let mut executions = Executions {
+            shared: shared,
+            plan: plan,
+            req: req,
+            running: Vec::new(),
+            started: 0,
+            next: None,
+            failure: None,
+        };
+        executions.start();
+        executions
+    }
+
+    fn start(&mut self) {
+        let execution = send(self.shared.clone(), self.plan.clone(), self.req.clone(), 0, None);
+        self.running.push((Instant::now(), execution));
+        self.started += 1;
+        self.next = None;
+        if !self.req.is_idempotent() {
+            return;
+        }
+        if let Some(delay) = self.shared.speculative_execution.next_execution(&self.req, self.started) {
+            match Timeout::new(delay, &self.shared.reactor) {
+                Ok(timeout) => self.next = Some(timeout),
+                Err(err) => warn!("failed to schedule speculative execution: {}", err),
+            }
+        }
+    }
+}
+
+impl Future for Executions {
+    type Item = StreamingMessage;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<StreamingMessage, io::Error> {
+        loop {
+            let elapsed = match self.next {
+                Some(ref mut timeout) => timeout.poll()?.is_ready(),
+                None => false,
+            };
+            if !elapsed {
+                break;
+            }
+            debug!("starting speculative execution {} of request", self.started + 1);
+            self.start();
+        }
+
+        let mut i = 0;
+        while i < self.running.len() {
+            let res = match self.running[i].1.poll() {
+                Ok(Async::NotReady) => {
+                    i += 1;
+                    continue;
+                }
+                Ok(Async::Ready(res)) => Ok(res),
+                Err(err) => Err(err),
+            };
+            let (started_at, _) = self.running.swap_remove(i);
+            match res {
+                Ok(StreamingMessage::Error(_)) | Err(_) => self.failure = Some(res),
+                // Dropping the other executions cancels them.
+                Ok(res) => {
+                    self.shared.speculative_execution.record_latency(started_at.elapsed());
+                    return Ok(Async::Ready(res));
+                }
+            }
+        }
+        if self.running.is_empty() {
+            return self.failure.take().expect("a failed execution").map(Async::Ready);
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+impl Service for Session {
+    type Request = request::Message;
+    type Response = StreamingMessage;
+    type Error = io::Error;
+    type Future = Box<Future<Item = Self::Response, Error = Self::Error>>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        let plan = self.shared.policy.query_plan(&req, &self.shared.cluster.nodes());
+        let executions = Executions::new(self.shared.clone(), Rc::new(RefCell::new(plan.into_iter())), req);
+        let shared = self.shared.clone();
+        Box::new(executions.map(move |res| {
+            if let StreamingMessage::Result(ResultMessage::Keyspace(ref keyspace), _) = res {
+                use_keyspace(&shared, keyspace.as_ref());
+            }
+            res
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use codec::header::ProtocolVersion;
+    use codec::primitives::{CqlConsistency, CqlFrom, CqlString};
+    use codec::request::{QueryMessage, RoutingKey};
+    use codec::response::{ErrorMessage, EventMessage, WriteType};
+    use futures::sync::mpsc::UnboundedSender;
+    use futures::sync::oneshot;
+    use tokio_core::reactor::Core;
+    use std::time::Duration;
+    use super::super::client::{ClientHandle, CqlProto};
+    use super::super::cluster::test::{describe, keyspace, peer};
+    use super::super::policies::{ConstantSpeculativeExecution, ExponentialReconnection, TokenAware};
+    use super::super::pool::Connect;
+
+    /// Decides as told, in order.
+    struct Decisions(RefCell<Vec<RetryDecision>>);
+
+    impl Decisions {
+        fn next(&self) -> RetryDecision {
+            self.0.borrow_mut().remove(0)
+        }
+    }
+
+    impl RetryPolicy for Decisions {
+        fn on_read_timeout(
+            &self,
+            _: &request::Message,
+            _: CqlConsistency,
+            _: i32,
+            _: i32,
+            _: bool,
+            _: u32,
+        ) -> RetryDecision {
+            self.next()
+        }
+
+        fn on_write_timeout(
+            &self,
+            _: &request::Message,
+            _: CqlConsistency,
+            _: &WriteType,
+            _: i32,
+            _: i32,
+            _: u32,
+        ) -> RetryDecision {
+            self.next()
+        }
+
+        fn on_unavailable(&self, _: &request::Message, _: CqlConsistency, _: i32, _: i32, _: u32) -> RetryDecision {
+            self.next()
+        }
+
+        fn on_overloaded(&self, _: &request::Message, _: u32) -> RetryDecision {
+            self.next()
+        }
+
+        fn on_connection_error(&self, _: &request::Message, _: &io::Error, _: u32) -> RetryDecision {
+            self.next()
+        }
+    }
+
+    fn node(i: usize) -> SocketAddr {
+        SocketAddr::new(format!("10.0.0.{}", i + 1).parse().unwrap(), 9042)
+    }
+
+    /// Options with pools of one connection, which decide retries as told by `decisions`.
+    fn options(decisions: Vec<RetryDecision>) -> SessionOptions {
+        SessionOptions {
+            pool: PoolOptions {
+                connections: 1,
+                ..Default::default()
+            },
+            retry: Box::new(Decisions(RefCell::new(decisions))),
+            ..Default::default()
+        }
+    }
+
+    /// A session with a pool to each of `nodes`, whose connections answer requests with
+    /// `respond`, called with the index of the node. The control connection is the one of node
+    /// 0. The senders of the events of all connections are returned, which close them when
+    /// dropped.
+    fn session<F>(
+        core: &Core,
+        nodes: usize,
+        session_options: SessionOptions,
+        respond: F,
+    ) -> (Rc<Shared>, Vec<UnboundedSender<EventMessage>>)
+    where
+        F: Fn(usize, request::Message) -> io::Result<StreamingMessage> + 'static,
+    {
+        session_with(core, nodes, session_options, move |i, req| Box::new(future::result(respond(i, req))))
+    }
+
+    /// Like `session()`, but each request is answered once the future returned by `respond`
+    /// completes.
+    fn session_with<F>(
+        core: &Core,
+        nodes: usize,
+        session_options: SessionOptions,
+        respond: F,
+    ) -> (Rc<Shared>, Vec<UnboundedSender<EventMessage>>)
+    where
+        F: Fn(usize, request::Message) -> Box<Future<Item = StreamingMessage, Error = io::Error>> + 'static,
+    {
+        let respond = Rc::new(respond);
+        let options = ConnectOptions {
+            heartbeat_interval: None,
+            ..Default::default()
+        };
+        let client = Client {
+            protocol: CqlProto {
+                version: ProtocolVersion::Version3,
+                debug: None,
+            },
+        };
+        let fake = |i: usize| {
+            let respond = respond.clone();
+            ClientHandle::fake_with(ProtocolVersion::Version3, &core.handle(), &options, move |req| respond(i, req))
+        };
+        let (control, control_events) = fake(0);
+        let mut senders = vec![control_events];
+        let pool_options = session_options.pool;
+        let mut pools = HashMap::new();
+        for i in 0..nodes {
+            let (handle, events) = fake(i);
+            senders.push(events);
+            let connect: Box<Connect> = Box::new(|_| Box::new(future::err(io_err("unreachable").into())));
+            let pool = Pool::new(vec![handle], connect, &core.handle(), options.clone(), pool_options.clone());
+            pools.insert(node(i), PoolSlot::Ready(pool));
+        }
+        let reconnection = Rc::new(ExponentialReconnection::default());
+        let shared = Rc::new(Shared {
+            cluster: Cluster::new(
+                client.clone(),
+                Vec::new(),
+                &core.handle(),
+                options.clone(),
+                reconnection,
+                node(0),
+                control,
+            ),
+            policy: session_options.load_balancing,
+            retry: session_options.retry,
+            speculative_execution: session_options.speculative_execution,
+            pools: RefCell::new(pools),
+            client: client,
+            options: RefCell::new(options),
+            pool_options: pool_options,
+            reactor: core.handle(),
+        });
+        (shared, senders)
+    }
+
+    /// Records the latencies it is given, and starts executions like the policy it wraps.
+    struct Latencies<P>(P, Rc<RefCell<Vec<Duration>>>);
+
+    impl<P: SpeculativeExecutionPolicy> SpeculativeExecutionPolicy for Latencies<P> {
+        fn next_execution(&self, request: &request::Message, executions: u32) -> Option<Duration> {
+            self.0.next_execution(request, executions)
+        }
+
+        fn record_latency(&self, latency: Duration) {
+            self.1.borrow_mut().push(latency);
+        }
+    }
+
+    fn settle(core: &mut Core) {
+        for _ in 0..10 {
+            core.turn(Some(Duration::from_millis(1)));
+        }
+    }
+
+    fn overloaded(i: usize) -> StreamingMessage {
+        StreamingMessage::Error(ErrorMessage {
+            code: 0x1001,
+            text: CqlString::try_from(["node 0 is overloaded", "node 1 is overloaded"][i]).unwrap(),
+            kind: CqlErrorKind::Overloaded,
+        })
+    }
+
+    fn query() -> request::Message {
+        request::Message::Query(QueryMessage {
+            idempotent: true,
+            ..Default::default()
+        })
+    }
+
+    /// Send `query()` along a plan of all `nodes`, and return its outcome along with the nodes
+    /// it was sent to and the consistency it was sent with.
+    fn run<F>(
+        nodes: usize,
+        decisions: Vec<RetryDecision>,
+        respond: F,
+    ) -> (io::Result<StreamingMessage>, Vec<(usize, CqlConsistency)>)
+    where
+        F: Fn(usize) -> io::Result<StreamingMessage> + 'static,
+    {
+        let mut core = Core::new().unwrap();
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let log = sent.clone();
+        let (shared, _senders) = session(&core, nodes, options(decisions), move |i, req| {
+            if let request::Message::Query(ref query) = req {
+                log.borrow_mut().push((i, query.parameters.consistency));
+            }
+            respond(i)
+        });
+        let plan = Rc::new(RefCell::new((0..nodes).map(node).collect::<Vec<_>>().into_iter()));
+        let res = core.run(Executions::new(shared, plan, query()));
+        let sent = sent.borrow().clone();
+        (res, sent)
+    }
+
+    fn error_text(res: io::Result<StreamingMessage>) -> String {
+        match res {
+            Ok(StreamingMessage::Error(msg)) => msg.text.as_ref().to_string(),
+            res => panic!("expected an error response, got {:?}", res.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn rethrows() {
+        let (res, sent) = run(2, vec![RetryDecision::Rethrow], |i| Ok(overloaded(i)));
+        assert_eq!(error_text(res), "node 0 is overloaded");
+        assert_eq!(sent, vec![(0, CqlConsistency::One)]);
+    }
+
+    #[test]
+    fn ignores() {
+        let (res, sent) = run(2, vec![RetryDecision::Ignore], |i| Ok(overloaded(i)));
+        match res {
+            Ok(StreamingMessage::Result(ResultMessage::Void, _)) => {}
+            res => panic!("expected a Void result, got {:?}", res.map(|_| ())),
+        }
+        assert_eq!(sent.len(), 1);
+    }
+
+    #[test]
+    fn retries_on_same_host() {
+        let decisions = vec![
+            RetryDecision::RetrySameHost(Some(CqlConsistency::Quorum)),
+            RetryDecision::Rethrow,
+        ];
+        let (res, sent) = run(2, decisions, |i| Ok(overloaded(i)));
+        assert_eq!(error_text(res), "node 0 is overloaded");
+        assert_eq!(sent, vec![(0, CqlConsistency::One), (0, CqlConsistency::Quorum)]);
+    }
+
+    #[test]
+    fn retries_on_next_host() {
+        let decisions = vec![RetryDecision::RetryNextHost(None)];
+        let (res, sent) = run(2, decisions, |i| match i {
+            0 => Err(io_err("connection reset")),
+            _ => Ok(StreamingMessage::Ready),
+        });
+        match res {
+            Ok(StreamingMessage::Ready) => {}
+            res => panic!("expected Ready, got {:?}", res.map(|_| ())),
+        }
+        assert_eq!(sent, vec![(0, CqlConsistency::One), (1, CqlConsistency::One)]);
+    }
+
+    #[test]
+    fn fails_with_last_error_once_all_hosts_were_tried() {
+        let decisions = vec![RetryDecision::RetryNextHost(None), RetryDecision::RetryNextHost(None)];
+        let (res, sent) = run(2, decisions, |i| Ok(overloaded(i)));
+        assert_eq!(error_text(res), "node 1 is overloaded");
+        assert_eq!(sent, vec![(0, CqlConsistency::One), (1, CqlConsistency::One)]);
+
+        let decisions = vec![RetryDecision::RetryNextHost(None)];
+        let (res, _) = run(1, decisions, |_| Err(io_err("connection reset")));
+        assert_eq!(res.unwrap_err().to_string(), "connection reset");
+    }
+
+    #[test]
+    fn uses_keyspace_on_all_pools() {
+        let mut core = Core::new().unwrap();
+        let queries = Rc::new(RefCell::new(Vec::new()));
+        let log = queries.clone();
+        let (shared, _senders) = session(&core, 2, options(Vec::new()), move |i, req| match req {
+            request::Message::Query(query) => {
+                log.borrow_mut().push((i, query.query.as_ref().to_string()));
+                let keyspace = CqlString::try_from("ks").unwrap();
+                Ok(StreamingMessage::Result(ResultMessage::Keyspace(keyspace), ResponseInfo::default()))
+            }
+            _ => Ok(StreamingMessage::Ready),
+        });
+        let session = Session { shared: shared.clone() };
+        assert_eq!(session.keyspace(), None);
+
+        use_keyspace(&shared, "ks");
+        use_keyspace(&shared, "ks");
+        settle(&mut core);
+        assert_eq!(session.keyspace(), Some(String::from("ks")));
+        assert_eq!(shared.options.borrow().keyspace, Some(String::from("ks")));
+        let mut used = queries.borrow().clone();
+        used.sort();
+        assert_eq!(used, vec![(0, String::from("USE \"ks\"")), (1, String::from("USE \"ks\""))]);
+    }
+
+    #[test]
+    fn routes_requests_to_replicas_first() {
+        let mut core = Core::new().unwrap();
+        let peers = Rc::new(RefCell::new(Vec::new()));
+        let known = peers.clone();
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let log = sent.clone();
+        let options = SessionOptions {
+            load_balancing: Box::new(TokenAware::new(RoundRobin::new())),
+            ..options(Vec::new())
+        };
+        let (shared, _senders) = session(&core, 2, options, move |i, req| match req {
+            request::Message::Query(ref query) if query.query.as_ref().is_empty() => {
+                log.borrow_mut().push(i);
+                Ok(StreamingMessage::Ready)
+            }
+            req => describe(req, &known.borrow(), &[keyspace("ks", "1")]),
+        });
+        core.run(shared.cluster.refresh()).unwrap();
+        watch_topology(&shared);
+        core.run(update_replicas(&shared)).unwrap();
+
+        let session = Session { shared: shared.clone() };
+        let routed = |key: &[u8]| {
+            request::Message::Query(QueryMessage {
+                routing_key: Some(RoutingKey {
+                    keyspace: Some(String::from("ks")),
+                    key: key.to_vec(),
+                }),
+                ..Default::default()
+            })
+        };
+        let send_both = |core: &mut Core| -> Vec<usize> {
+            for _ in 0..2 {
+                core.run(session.call(routed(b"123"))).unwrap();
+                core.run(session.call(routed(&[0, 0, 0, 1]))).unwrap();
+            }
+            sent.borrow_mut().drain(..).collect()
+        };
+        assert_eq!(send_both(&mut core), vec![0, 0, 0, 0]);
+
+        // The token of "123" is -7468325962851647638, the one of [0, 0, 0, 1] is
+        // -4069959284402364209, and 10.0.0.1 owns the tokens -100 and 100.
+        peers.borrow_mut().push(peer("10.0.0.2", "10.0.0.2", "-5000000000000000000"));
+        core.run(shared.cluster.refresh()).unwrap();
+        settle(&mut core);
+        assert_eq!(send_both(&mut core), vec![1, 0, 1, 0]);
+
+        peers.borrow_mut()[0] = peer("10.0.0.2", "10.0.0.2", "-3000000000000000000");
+        core.run(shared.cluster.refresh()).unwrap();
+        settle(&mut core);
+        assert_eq!(send_both(&mut core), vec![1, 1, 1, 1]);
+    }
+
+    /// Send `req` along a plan of node 0, which never answers, and node 1, which answers right
+    /// away. Another execution is started after 10 milliseconds. Returns the outcome, or `None`
+    /// if there was none after 100 milliseconds, along with the nodes `req` was sent to, the
+    /// recorded latencies and whether the execution of node 0 was dropped.
+    fn speculate(req: request::Message) -> (Option<StreamingMessage>, Vec<usize>, Vec<Duration>, bool) {
+        let mut core = Core::new().unwrap();
+        let latencies = Rc::new(RefCell::new(Vec::new()));
+        let policy = ConstantSpeculativeExecution::new(Duration::from_millis(10), 1);
+        let options = SessionOptions {
+            speculative_execution: Box::new(Latencies(policy, latencies.clone())),
+            ..options(Vec::new())
+        };
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let log = sent.clone();
+        let unanswered = Rc::new(RefCell::new(Vec::new()));
+        let pending = unanswered.clone();
+        let (shared, _senders) = session_with(&core, 2, options, move |i, _| {
+            log.borrow_mut().push(i);
+            if i == 1 {
+                return Box::new(future::ok(StreamingMessage::Ready));
+            }
+            let (tx, rx) = oneshot::channel();
+            pending.borrow_mut().push(tx);
+            Box::new(rx.map_err(|_| io_err("never answered")))
+        });
+
+        let plan = Rc::new(RefCell::new(vec![node(0), node(1)].into_iter()));
+        let wait = Timeout::new(Duration::from_millis(100), &core.handle()).unwrap();
+        let res = match core.run(Executions::new(shared, plan, req).select2(wait)) {
+            Ok(future::Either::A((res, _))) => Some(res),
+            Ok(future::Either::B(_)) => None,
+            Err(future::Either::A((err, _))) | Err(future::Either::B((err, _))) => panic!("failed: {}", err),
+        };
+        let sent = sent.borrow().clone();
+        let latencies = latencies.borrow().clone();
+        let dropped = unanswered.borrow().iter().all(|tx| tx.is_canceled());
+        (res, sent, latencies, dropped)
+    }
+
+    #[test]
+    fn speculative_executions_use_the_first_response() {
+        let (res, sent, latencies, dropped) = speculate(query());
+        match res {
+            Some(StreamingMessage::Ready) => {}
+            res => panic!("expected the response of node 1, got {:?}", res),
+        }
+        assert_eq!(sent, vec![0, 1]);
+        assert_eq!(latencies.len(), 1);
+        assert!(dropped);
+    }
+
+    #[test]
+    fn requests_which_are_not_idempotent_are_not_speculated() {
+        let req = request::Message::Query(QueryMessage {
+            idempotent: false,
+            ..Default::default()
+        });
+        let (res, sent, latencies, dropped) = speculate(req);
+        assert!(res.is_none());
+        assert_eq!(sent, vec![0]);
+        assert!(latencies.is_empty());
+        assert!(dropped);
+    }
+}
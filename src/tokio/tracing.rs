@@ -0,0 +1,103 @@
+use codec::request::{self, QueryMessage, QueryParameters, QueryValues};
+use codec::response::{Row, ValueAt};
+use codec::primitives::{CqlBytes, CqlConsistency, CqlFrom, CqlLongString};
+use codec::primitives::datatypes::{CqlSerializable, Inet, Int, Text, TimeUuid, Timestamp, Uuid};
+use bytes::BytesMut;
+use futures::Future;
+use tokio_service::Service;
+
+use super::client::ClientHandle;
+use super::error::*;
+use super::utils::into_rows;
+
+/// A trace of a single request, as recorded by the server in the `system_traces` keyspace.
+#[cfg_attr(feature = "with-serde", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct Trace {
+    pub session_id: Uuid,
+    /// The node which coordinated the request.
+    pub coordinator: Option<Inet>,
+    pub request: Option<Text>,
+    pub started_at: Option<Timestamp>,
+    /// The time it took to process the request, in microseconds.
+    pub duration: Option<Int>,
+    /// All events recorded on all nodes involved, in order of occurrence.
+    pub events: Vec<TraceEvent>,
+}
+
+#[cfg_attr(feature = "with-serde", derive(Serialize))]
+#[derive(Debug, Clone)]
+pub struct TraceEvent {
+    pub event_id: TimeUuid,
+    pub activity: Option<Text>,
+    /// The node on which the event occurred.
+    pub source: Option<Inet>,
+    /// The time since the start of the request on the `source` node, in microseconds.
+    pub source_elapsed: Option<Int>,
+    pub thread: Option<Text>,
+}
+
+const SESSION_QUERY: &str = "SELECT coordinator, request, started_at, duration \
+                             FROM system_traces.sessions WHERE session_id = ?";
+const EVENTS_QUERY: &str = "SELECT event_id, activity, source, source_elapsed, thread \
+                            FROM system_traces.events WHERE session_id = ?";
+
+/// Fetch the trace with the given id, as found in `ResponseInfo::tracing_id` of a traced request.
+///
+/// Traces are written asynchronously by the server, and `None` is returned if the trace is not
+/// complete yet. In that case, it's up to the caller to try again later.
+pub fn fetch_trace(handle: &ClientHandle, session_id: Uuid) -> Box<Future<Item = Option<Trace>, Error = Error>> {
+    let session = query_rows(handle, SESSION_QUERY, &session_id);
+    let events = query_rows(handle, EVENTS_QUERY, &session_id);
+    Box::new(session.join(events).and_then(move |(session, events)| {
+        let session = match session.into_iter().next() {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let duration: Option<Int> = session.value_at(3)?;
+        if duration.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Trace {
+            session_id: session_id,
+            coordinator: session.value_at(0)?,
+            request: session.value_at(1)?,
+            started_at: session.value_at(2)?,
+            duration: duration,
+            events: events
+                .iter()
+                .map(|row| {
+                    Ok(TraceEvent {
+                        event_id: row.value_at(0)?,
+                        activity: row.value_at(1)?,
+                        source: row.value_at(2)?,
+                        source_elapsed: row.value_at(3)?,
+                        thread: row.value_at(4)?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?,
+        }))
+    }))
+}
+
+fn query_rows(handle: &ClientHandle, query: &'static str, session_id: &Uuid) -> Box<Future<Item = Vec<Row>, Error = Error>> {
+    let mut id = BytesMut::with_capacity(16);
+    session_id.serialize(&mut id);
+    let msg = request::Message::Query(QueryMessage {
+        query: CqlLongString::try_from(query).expect("a valid query"),
+        parameters: QueryParameters {
+            values: Some(QueryValues::Positional(
+                vec![CqlBytes::try_from(id).expect("16 bytes to fit")],
+            )),
+            consistency: CqlConsistency::One,
+            ..Default::default()
+        },
+        tracing: false,
+        routing_key: None,
+        idempotent: true,
+        timeout: None,
+    });
+    Box::new(handle.call(msg).map_err(|e| e.into()).and_then(
+        |res| into_rows(res, "fetching a trace"),
+    ))
+}
@@ -1,5 +1,13 @@
+use codec::response::{ResultMessage, Row};
+use futures::{future, Future, Stream};
+use futures::future::Either;
+use tokio_core::reactor::{Handle, Timeout};
 use std::io;
 use std::error;
+use std::time::Duration;
+
+use super::error::*;
+use super::messages::StreamingMessage;
 
 pub fn io_err<S>(msg: S) -> io::Error
 where
@@ -7,3 +15,44 @@ where
 {
     io::Error::new(io::ErrorKind::Other, msg)
 }
+
+/// Receive all rows of the Rows result `res`, or fail with the error the server responded with.
+/// `what` describes the request in the error message of any other response.
+pub fn into_rows(res: StreamingMessage, what: &str) -> Box<Future<Item = Vec<Row>, Error = Error>> {
+    match res {
+        StreamingMessage::Result(ResultMessage::Rows { rows, .. }, _) => Box::new(future::ok(rows)),
+        StreamingMessage::Partial(rows) => Box::new(rows.collect().map_err(|e| e.into())),
+        StreamingMessage::Error(msg) => Box::new(future::err(
+            ErrorKind::CqlError(msg.code, msg.text.into()).into(),
+        )),
+        msg => Box::new(future::err(
+            io_err(format!("Unexpected response when {}: {:?}", what, msg)).into(),
+        )),
+    }
+}
+
+/// Fail `f` with an error of kind `TimedOut` if it doesn't complete within `timeout`.
+/// `f` is handed to `on_timeout` in that case.
+pub fn with_timeout<F, T>(
+    f: F,
+    timeout: Duration,
+    handle: &Handle,
+    on_timeout: T,
+) -> Box<Future<Item = F::Item, Error = io::Error>>
+where
+    F: Future<Error = io::Error> + 'static,
+    T: FnOnce(F) + 'static,
+{
+    let timeout = match Timeout::new(timeout, handle) {
+        Ok(timeout) => timeout,
+        Err(err) => return Box::new(future::err(err)),
+    };
+    Box::new(f.select2(timeout).then(|res| match res {
+        Ok(Either::A((item, _))) => Ok(item),
+        Ok(Either::B((_, f))) => {
+            on_timeout(f);
+            Err(io::Error::new(io::ErrorKind::TimedOut, "The request timed out"))
+        }
+        Err(Either::A((err, _))) | Err(Either::B((err, _))) => Err(err),
+    }))
+}